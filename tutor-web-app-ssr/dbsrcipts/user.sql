@@ -1,4 +1,5 @@
 drop table if exists ezyweb_user;
+drop table if exists ezyweb_rate_limit;
 
 /* Create tables. */
 /* Note: Don't put a comma after last field */
@@ -8,3 +9,11 @@ create table ezyweb_user
     user_password VARCHAR(255) not null,
     tutor_id INT
 );
+
+/* Token buckets shared by all instances when rate_limit.store = "postgres" */
+create table ezyweb_rate_limit
+(
+    bucket_key varchar(300) primary key,
+    tokens double precision not null,
+    updated_at TIMESTAMPTZ not null default now()
+);
@@ -4,6 +4,8 @@ use actix_web::HttpResponse;
 
 use serde::Serialize;
 
+use crate::middleware::current_request_id;
+
 use std::fmt::{self, Display};
 
 #[derive(Debug)]
@@ -12,6 +14,7 @@ pub enum EzyTutorError {
     ActixError(actix_web::Error),
     NotFound(String),
     TeraError(tera::Error),
+    TooManyRequests,
 }
 
 impl From<actix_web::Error> for EzyTutorError {
@@ -33,6 +36,7 @@ impl Display for EzyTutorError {
             Self::ActixError(_) => write!(f, "Internal server error"),
             Self::TeraError(_) => write!(f, "Template render error"),
             Self::NotFound(err) => write!(f, "{}", err),
+            Self::TooManyRequests => write!(f, "Too many requests, please retry later"),
         }
     }
 }
@@ -40,10 +44,21 @@ impl Display for EzyTutorError {
 impl EzyTutorError {
     fn error_response(&self) -> String {
         match self {
-            EzyTutorError::DbError(err) => eprintln!("Database error occurred: {}", err),
-            EzyTutorError::ActixError(err) => eprintln!("Server error occurred: {}", err),
-            EzyTutorError::TeraError(err) => eprintln!("Error in rendering the template {}", err),
-            EzyTutorError::NotFound(err) => eprintln!("Not found error occurred: {:?}", err),
+            EzyTutorError::DbError(err) => {
+                tracing::error!(error = %err, "Database error occurred")
+            }
+            EzyTutorError::ActixError(err) => {
+                tracing::error!(error = %err, "Server error occurred")
+            }
+            EzyTutorError::TeraError(err) => {
+                tracing::error!(error = %err, "Error in rendering the template")
+            }
+            EzyTutorError::NotFound(err) => {
+                tracing::warn!(error = %err, "Not found error occurred")
+            }
+            EzyTutorError::TooManyRequests => {
+                tracing::info!("Request rejected by rate limiter")
+            }
         }
         format!("{}", self)
     }
@@ -58,12 +73,14 @@ impl ResponseError for EzyTutorError {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
             Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 
     fn error_response(&self) -> actix_web::HttpResponse<actix_web::body::BoxBody> {
         HttpResponse::build(self.status_code()).json(MyErrorResponse {
             error_message: Self::error_response(self),
+            request_id: current_request_id(),
         })
     }
 }
@@ -71,4 +88,6 @@ impl ResponseError for EzyTutorError {
 #[derive(Debug, Serialize)]
 struct MyErrorResponse {
     error_message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
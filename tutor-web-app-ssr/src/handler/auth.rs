@@ -1,6 +1,7 @@
 use actix_web::{web, Error, HttpResponse};
 
 use tera::Tera;
+use tutor_web_common::telemetry::ClientRequestExt;
 
 use crate::errors::EzyTutorError;
 use crate::model::{TutorRegisterForm, TutorResponse, TutorSigninForm, User};
@@ -70,7 +71,9 @@ pub async fn handle_register(
 
     let client = Client::new();
     let resp = client
-        .post("http://localhost:3030/tutors/")
+        .post(app_state.backend.url("/tutors/"))
+        .timeout(app_state.backend.timeout())
+        .with_trace_context()
         .send_json(&new_tutor)
         .await
         .unwrap()
@@ -1,5 +1,6 @@
 use actix_web::{web, Error, HttpResponse};
 use tera::Tera;
+use tutor_web_common::telemetry::ClientRequestExt;
 
 use crate::model::{
     CourseResponse, NewCourse, NewCourseResponse, UpdateCourse, UpdateCourseResponse,
@@ -10,12 +11,23 @@ use serde_json::{json, Value};
 
 use awc::Client;
 
-pub async fn handle_get_course(params: web::Path<(i32,)>) -> Result<HttpResponse, Error> {
+pub async fn handle_get_course(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, Error> {
     let (tutor_id,) = params.into_inner();
 
-    let get_url = format!("http://localhost:3030/courses/{}", tutor_id);
+    let get_url = app_state.backend.url(&format!("/courses/{}", tutor_id));
     let client = Client::new();
-    let resp = client.get(get_url).send().await.unwrap().body().await?;
+    let resp = client
+        .get(get_url)
+        .timeout(app_state.backend.timeout())
+        .with_trace_context()
+        .send()
+        .await
+        .unwrap()
+        .body()
+        .await?;
 
     let courses: Vec<CourseResponse> = serde_json::from_str(std::str::from_utf8(&resp)?)?;
 
@@ -24,7 +36,7 @@ pub async fn handle_get_course(params: web::Path<(i32,)>) -> Result<HttpResponse
 
 pub async fn handle_insert_course(
     _tmpl: web::Data<Tera>,
-    _app_state: web::Data<AppState>,
+    app_state: web::Data<AppState>,
     params: web::Path<(i32,)>,
     new_course: web::Json<NewCourse>,
 ) -> Result<HttpResponse, Error> {
@@ -53,14 +65,16 @@ pub async fn handle_insert_course(
     });
     let client = Client::new();
     let resp = client
-        .post("http://localhost:3030/courses")
+        .post(app_state.backend.url("/courses"))
+        .timeout(app_state.backend.timeout())
+        .with_trace_context()
         .send_json(&new_course)
         .await
         .unwrap()
         .body()
         .await?;
 
-    println!("Finished call: {:?}", resp);
+    tracing::debug!(response = ?resp, "Finished call");
 
     let course: NewCourseResponse = serde_json::from_str(std::str::from_utf8(&resp)?)?;
 
@@ -69,7 +83,7 @@ pub async fn handle_insert_course(
 
 pub async fn hanlde_update_course(
     _tmpl: web::Data<Tera>,
-    _app_state: web::Data<AppState>,
+    app_state: web::Data<AppState>,
     params: web::Path<(i32, i32)>,
     update_course: web::Json<UpdateCourse>,
 ) -> Result<HttpResponse, Error> {
@@ -97,9 +111,13 @@ pub async fn hanlde_update_course(
     });
 
     let client = Client::new();
-    let update_url = format!("http://localhost:3030/courses/{}/{}", tutor_id, course_id);
+    let update_url = app_state
+        .backend
+        .url(&format!("/courses/{}/{}", tutor_id, course_id));
     let resp = client
         .put(update_url)
+        .timeout(app_state.backend.timeout())
+        .with_trace_context()
         .send_json(&update_course)
         .await
         .unwrap()
@@ -113,15 +131,19 @@ pub async fn hanlde_update_course(
 
 pub async fn handle_delete_course(
     _tmpl: web::Data<Tera>,
-    _app_state: web::Data<AppState>,
+    app_state: web::Data<AppState>,
     params: web::Path<(i32, i32)>,
 ) -> Result<HttpResponse, Error> {
     let (tutor_id, course_id) = params.into_inner();
 
     let client = Client::new();
-    let delete_url = format!("http://localhost:3030/courses/{}/{}", tutor_id, course_id);
+    let delete_url = app_state
+        .backend
+        .url(&format!("/courses/{}/{}", tutor_id, course_id));
     let resp = client
         .delete(delete_url)
+        .timeout(app_state.backend.timeout())
+        .with_trace_context()
         .send()
         .await
         .unwrap()
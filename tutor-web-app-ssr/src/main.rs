@@ -1,5 +1,7 @@
+mod config;
 mod errors;
 mod handler;
+mod middleware;
 mod model;
 mod routes;
 mod state;
@@ -8,26 +10,83 @@ mod store;
 use state::AppState;
 use tera::Tera;
 
-use std::env;
-
-use sqlx::postgres::PgPool;
+use std::process::ExitCode;
 
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
 
+use tutor_web_common::{db, telemetry};
+
+use config::{Args, Config};
 use routes::{app_config, course_config};
 
+/// The name spans are exported under.
+const SERVICE_NAME: &str = "tutor-web-app-ssr";
+/// Exit status for an unusable configuration, `EX_CONFIG` from sysexits(3).
+const EXIT_CONFIG: u8 = 78;
+
 #[actix_web::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+async fn main() -> ExitCode {
     dotenvy::dotenv().ok();
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+    let (args, config) = match Args::parse()
+        .and_then(|args| Config::load(args.config_path.as_deref()).map(|config| (args, config)))
+    {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_CONFIG);
+        }
+    };
+    if args.print_config {
+        return match config.to_redacted_toml() {
+            Ok(printed) => {
+                print!("{}", printed);
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                ExitCode::from(EXIT_CONFIG)
+            }
+        };
+    }
+
+    let telemetry = match telemetry::init_subscriber(
+        SERVICE_NAME,
+        config.log.format,
+        &config.log.trace_exporter(),
+    ) {
+        Ok(telemetry) => telemetry,
+        Err(err) => {
+            eprintln!("Failed to initialise telemetry: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = run(config).await;
+    if let Err(err) = &result {
+        tracing::error!(error = %err, "Server terminated with an error");
+    }
+    telemetry.shutdown().await;
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(_) => ExitCode::FAILURE,
+    }
+}
 
-    let pg_pool = PgPool::connect(&database_url).await.unwrap();
-    let shared_data = web::Data::new(AppState { pg_pool });
+async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let pg_pool = db::connect(&config.database).await?;
+    let shared_data = web::Data::new(AppState {
+        pg_pool: pg_pool.clone(),
+        backend: config.backend.clone(),
+    });
+    let rate_limiter = web::Data::new(config.rate_limit.limiter(&pg_pool));
+    let pruner = actix_web::rt::spawn(rate_limiter.clone().into_inner().prune_periodically());
 
-    let host_port = env::var("HOST_PORT").expect("HOST:PORT address is not set in .env file");
-    println!("Serving on: {}", host_port);
+    tracing::info!("Serving on: {}", config.server.bind_address);
 
+    let body_limit = config.server.body_limit;
     HttpServer::new(move || {
         let tera = Tera::new(concat!(
             env!("CARGO_MANIFEST_DIR"),
@@ -35,14 +94,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>
         ))
         .unwrap();
         App::new()
+            .wrap(from_fn(middleware::rate_limit))
+            .wrap(from_fn(middleware::request_id))
             .app_data(web::Data::new(tera))
             .app_data(shared_data.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(web::FormConfig::default().limit(body_limit))
+            .app_data(web::JsonConfig::default().limit(body_limit))
             .configure(course_config)
             .configure(app_config)
     })
-    .bind(&host_port)?
+    .bind(&config.server.bind_address)?
+    .shutdown_timeout(config.server.shutdown_timeout_secs)
     .run()
     .await?;
 
+    tracing::info!("Server stopped, closing database pool");
+    pg_pool.close().await;
+    // Closing the pool is what stops the rate limit pruning.
+    let _ = pruner.await;
     Ok(())
 }
@@ -0,0 +1,5 @@
+mod rate_limit;
+
+pub use rate_limit::*;
+
+pub use tutor_web_common::request_id::*;
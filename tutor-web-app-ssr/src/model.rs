@@ -55,7 +55,10 @@ pub struct NewCourseResponse {
     pub course_price: Option<i32>,
     pub course_language: Option<String>,
     pub course_level: Option<String>,
-    pub posted_time: String,
+    /// Unset until the course is first published.
+    pub posted_time: Option<String>,
+    #[serde(default)]
+    pub course_status: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -82,7 +85,10 @@ pub struct UpdateCourseResponse {
     pub course_price: i32,
     pub course_language: String,
     pub course_level: String,
-    pub posted_time: String,
+    /// Unset until the course is first published.
+    pub posted_time: Option<String>,
+    #[serde(default)]
+    pub course_status: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -97,5 +103,8 @@ pub struct CourseResponse {
     pub course_price: i32,
     pub course_language: String,
     pub course_level: String,
-    pub posted_time: String,
+    /// Unset until the course is first published.
+    pub posted_time: Option<String>,
+    #[serde(default)]
+    pub course_status: String,
 }
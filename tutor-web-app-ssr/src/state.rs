@@ -1,5 +1,8 @@
 use sqlx::postgres::PgPool;
 
+use crate::config::BackendConfig;
+
 pub struct AppState {
     pub pg_pool: PgPool,
+    pub backend: BackendConfig,
 }
@@ -0,0 +1,85 @@
+use sqlx::postgres::{PgConnection, PgPool, PgPoolOptions};
+use sqlx::Connection;
+
+use std::time::Duration;
+
+use crate::config::DatabaseConfig;
+
+/// Opens the connection pool, retrying transient failures with exponential backoff so
+/// the binaries can be started before Postgres accepts connections.
+///
+/// Each attempt is a single probe connection, which fails fast instead of waiting out
+/// the pool's acquire timeout.
+pub async fn connect(config: &DatabaseConfig) -> Result<PgPool, sqlx::Error> {
+    let mut backoff = config.connect_backoff();
+    let mut attempt = 1;
+    loop {
+        match PgConnection::connect(&config.url).await {
+            Ok(probe) => {
+                probe.close().await?;
+                return pool_options(config).connect(&config.url).await;
+            }
+            Err(err) if attempt <= config.connect_retries && is_transient(&err) => {
+                tracing::warn!(
+                    attempt,
+                    retry_in_ms = backoff.as_millis() as u64,
+                    error = %err,
+                    "Database not reachable, retrying"
+                );
+                actix_web::rt::time::sleep(backoff).await;
+                backoff = next_backoff(backoff, config.connect_backoff_max());
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn pool_options(config: &DatabaseConfig) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(config.acquire_timeout())
+        .idle_timeout(config.idle_timeout())
+        .max_lifetime(config.max_lifetime())
+}
+
+pub fn next_backoff(current: Duration, max: Duration) -> Duration {
+    current.saturating_mul(2).min(max)
+}
+
+/// Connection refused, a server still starting up (57P03) or out of connection slots
+/// (53300) are worth retrying; bad credentials or a bad URL are not.
+pub fn is_transient(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(db_err) => {
+            matches!(db_err.code().as_deref(), Some("57P03") | Some("53300"))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let max = Duration::from_secs(5);
+        let mut backoff = Duration::from_millis(1500);
+        backoff = next_backoff(backoff, max);
+        assert_eq!(Duration::from_secs(3), backoff);
+        backoff = next_backoff(backoff, max);
+        assert_eq!(max, backoff);
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        let refused = sqlx::Error::Io(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert!(is_transient(&refused));
+        assert!(is_transient(&sqlx::Error::PoolTimedOut));
+        assert!(!is_transient(&sqlx::Error::Configuration("bad url".into())));
+    }
+}
@@ -0,0 +1,7 @@
+//! Code shared by the tutor web service and the server-side rendered web app.
+
+pub mod config;
+pub mod db;
+pub mod rate_limit;
+pub mod request_id;
+pub mod telemetry;
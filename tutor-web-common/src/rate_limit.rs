@@ -0,0 +1,526 @@
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, RETRY_AFTER};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::postgres::PgPool;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const API_KEY_HEADER: &str = "x-api-key";
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+/// How often idle rows are deleted from the Postgres store, at the least.
+const MIN_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStoreKind {
+    #[default]
+    Memory,
+    Postgres,
+}
+
+/// Allows `requests` per `period_secs` for each client on one route. A `path` ending in
+/// `*` matches every path with that prefix.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitRule {
+    pub method: String,
+    pub path: String,
+    pub requests: u32,
+    pub period_secs: u64,
+}
+
+impl RateLimitRule {
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        let path_matches = match self.path.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == self.path,
+        };
+        path_matches && (self.method == "*" || self.method.eq_ignore_ascii_case(method))
+    }
+
+    pub fn quota(&self) -> Quota {
+        Quota {
+            capacity: self.requests,
+            period: Duration::from_secs(self.period_secs),
+        }
+    }
+}
+
+/// A token bucket holding up to `capacity` tokens that refills completely over `period`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Time until the bucket is full again.
+    pub reset: Duration,
+    /// Time until the next request would be allowed; zero when this one was.
+    pub retry_after: Duration,
+}
+
+impl Quota {
+    fn refill_rate(&self) -> f64 {
+        f64::from(self.capacity) / self.period.as_secs_f64()
+    }
+
+    /// Refills a bucket that held `tokens` `elapsed` ago and tries to take one token from
+    /// it. Returns the decision along with the tokens left in the bucket.
+    pub fn take(&self, tokens: f64, elapsed: Duration) -> (Decision, f64) {
+        let capacity = f64::from(self.capacity);
+        let rate = self.refill_rate();
+        let available = (tokens + elapsed.as_secs_f64() * rate).min(capacity);
+        let allowed = available >= 1.0;
+        let left = if allowed { available - 1.0 } else { available };
+
+        let decision = Decision {
+            allowed,
+            limit: self.capacity,
+            remaining: left.floor() as u32,
+            reset: Duration::from_secs_f64((capacity - left) / rate),
+            retry_after: if allowed {
+                Duration::ZERO
+            } else {
+                Duration::from_secs_f64((1.0 - left) / rate)
+            },
+        };
+        (decision, left)
+    }
+}
+
+impl Decision {
+    /// Adds the `RateLimit-*` headers, and `Retry-After` when the request was denied.
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(RATELIMIT_RESET, HeaderValue::from(whole_secs(self.reset)));
+        if !self.allowed {
+            headers.insert(RETRY_AFTER, HeaderValue::from(whole_secs(self.retry_after)));
+        }
+    }
+}
+
+impl Decision {
+    /// Whichever of the two decisions leaves the client less room: a denial over an
+    /// allowance, then the longer wait or the fewer remaining requests.
+    pub fn stricter(self, other: Decision) -> Decision {
+        match (self.allowed, other.allowed) {
+            (true, false) => other,
+            (false, true) => self,
+            (false, false) if other.retry_after > self.retry_after => other,
+            (true, true) if other.remaining < self.remaining => other,
+            _ => self,
+        }
+    }
+}
+
+/// Rounds up so clients never retry before a token is actually available.
+fn whole_secs(duration: Duration) -> u64 {
+    duration.as_secs_f64().ceil() as u64
+}
+
+/// Buckets kept in this process only; each instance enforces its own limits. Beyond
+/// `max_buckets` the least recently used bucket is forgotten, which can only let its
+/// client through sooner.
+#[derive(Debug)]
+pub struct MemoryStore {
+    buckets: Mutex<MemoryBuckets>,
+    max_buckets: usize,
+}
+
+#[derive(Debug, Default)]
+struct MemoryBuckets {
+    by_key: HashMap<String, MemoryBucket>,
+    /// Keys by when their bucket was last used, least recent first.
+    by_use: BTreeMap<u64, String>,
+    next_use: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MemoryBucket {
+    tokens: f64,
+    updated_at: Instant,
+    last_use: u64,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::with_max_buckets(MAX_TRACKED_BUCKETS)
+    }
+}
+
+impl MemoryStore {
+    pub fn with_max_buckets(max_buckets: usize) -> Self {
+        Self {
+            buckets: Mutex::new(MemoryBuckets::default()),
+            max_buckets,
+        }
+    }
+
+    fn take(&self, key: &str, quota: Quota) -> Decision {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let (tokens, elapsed) = buckets
+            .by_key
+            .get(key)
+            .map(|bucket| (bucket.tokens, now.duration_since(bucket.updated_at)))
+            .unwrap_or((f64::from(quota.capacity), Duration::ZERO));
+        let (decision, left) = quota.take(tokens, elapsed);
+
+        let last_use = buckets.next_use;
+        buckets.next_use += 1;
+        let bucket = MemoryBucket {
+            tokens: left,
+            updated_at: now,
+            last_use,
+        };
+        if let Some(previous) = buckets.by_key.insert(key.to_string(), bucket) {
+            buckets.by_use.remove(&previous.last_use);
+        }
+        buckets.by_use.insert(last_use, key.to_string());
+        if buckets.by_key.len() > self.max_buckets {
+            if let Some((_, oldest)) = buckets.by_use.pop_first() {
+                buckets.by_key.remove(&oldest);
+            }
+        }
+        decision
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.buckets.lock().unwrap().by_key.len()
+    }
+}
+
+/// Buckets shared by every instance through a table with `bucket_key`, `tokens` and
+/// `updated_at` columns.
+#[derive(Debug)]
+pub struct PostgresStore {
+    pg_pool: PgPool,
+    take_sql: String,
+    update_sql: String,
+    prune_sql: String,
+}
+
+impl PostgresStore {
+    pub fn new(pg_pool: PgPool, table: &str) -> Self {
+        Self {
+            pg_pool,
+            // Upserting locks the row, so concurrent instances and pruning wait for the
+            // update. Elapsed time comes from the database clock.
+            take_sql: format!(
+                "INSERT INTO {table} (bucket_key, tokens) VALUES ($1, $2)
+                ON CONFLICT (bucket_key) DO UPDATE SET bucket_key = EXCLUDED.bucket_key
+                RETURNING tokens, EXTRACT(EPOCH FROM (now() - updated_at))::float8"
+            ),
+            update_sql: format!(
+                "UPDATE {table} SET tokens = $2, updated_at = now() WHERE bucket_key = $1"
+            ),
+            prune_sql: format!(
+                "DELETE FROM {table} WHERE updated_at < now() - $1 * interval '1 second'"
+            ),
+        }
+    }
+
+    async fn take(&self, key: &str, quota: Quota) -> Result<Decision, sqlx::Error> {
+        let mut tx = self.pg_pool.begin().await?;
+        let (tokens, elapsed): (f64, f64) = sqlx::query_as(&self.take_sql)
+            .bind(key)
+            .bind(f64::from(quota.capacity))
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let elapsed = Duration::from_secs_f64(elapsed.max(0.0));
+        let (decision, tokens) = quota.take(tokens, elapsed);
+
+        sqlx::query(&self.update_sql)
+            .bind(key)
+            .bind(tokens)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(decision)
+    }
+
+    /// Deletes the buckets nobody has used for `idle`, returning how many there were.
+    pub async fn prune(&self, idle: Duration) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query(&self.prune_sql)
+            .bind(idle.as_secs_f64())
+            .execute(&self.pg_pool)
+            .await?;
+        Ok(deleted.rows_affected())
+    }
+}
+
+pub enum RateLimitStore {
+    Memory(MemoryStore),
+    Postgres(PostgresStore),
+}
+
+impl RateLimitStore {
+    async fn take(&self, key: &str, quota: Quota) -> Result<Decision, sqlx::Error> {
+        match self {
+            Self::Memory(store) => Ok(store.take(key, quota)),
+            Self::Postgres(store) => store.take(key, quota).await,
+        }
+    }
+}
+
+pub struct RateLimiter {
+    rules: Vec<RateLimitRule>,
+    store: RateLimitStore,
+    trust_forwarded_for: bool,
+}
+
+impl RateLimiter {
+    /// Applies the first of the `rules` matching each request. With `trust_forwarded_for`
+    /// clients are keyed by `X-Forwarded-For`/`Forwarded` instead of the peer address.
+    pub fn new(
+        rules: Vec<RateLimitRule>,
+        store: RateLimitStore,
+        trust_forwarded_for: bool,
+    ) -> Self {
+        Self {
+            rules,
+            store,
+            trust_forwarded_for,
+        }
+    }
+
+    /// Takes a token for the request if one of the configured routes matches it, from the
+    /// client address's bucket and, when it presents an API token, the token's bucket too.
+    /// Store failures are logged and let the request through rather than failing it.
+    pub async fn check(&self, req: &ServiceRequest) -> Option<Decision> {
+        let rule = self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(req.method().as_str(), req.path()))?;
+        let key = |client: &str| format!("{} {}|{}", rule.method, rule.path, client);
+
+        let mut decision = self.take(&key(&self.ip_key(req)), rule.quota()).await?;
+        if let (true, Some(token)) = (decision.allowed, api_token(req.headers())) {
+            let token_key = key(&format!("token:{}", token_fingerprint(token)));
+            decision = decision.stricter(self.take(&token_key, rule.quota()).await?);
+        }
+        Some(decision)
+    }
+
+    async fn take(&self, key: &str, quota: Quota) -> Option<Decision> {
+        match self.store.take(key, quota).await {
+            Ok(decision) => Some(decision),
+            Err(err) => {
+                tracing::warn!(error = %err, "Rate limit store unavailable, allowing request");
+                None
+            }
+        }
+    }
+
+    /// Deletes buckets from the Postgres store once they have been idle for the longest
+    /// configured period, by which time they are full again. Runs until the pool is
+    /// closed; returns straight away for the memory store, which needs no pruning.
+    pub async fn prune_periodically(self: Arc<Self>) {
+        let RateLimitStore::Postgres(store) = &self.store else {
+            return;
+        };
+        let Some(idle) = self.rules.iter().map(|rule| rule.quota().period).max() else {
+            return;
+        };
+        loop {
+            let wait = actix_web::rt::time::sleep(idle.max(MIN_PRUNE_INTERVAL));
+            if store.pg_pool.close_event().do_until(wait).await.is_err() {
+                return;
+            }
+            match store.prune(idle).await {
+                Ok(deleted) => tracing::debug!(deleted, "Pruned idle rate limit buckets"),
+                Err(sqlx::Error::PoolClosed) => return,
+                Err(err) => tracing::warn!(error = %err, "Could not prune rate limit buckets"),
+            }
+        }
+    }
+
+    /// Every client is limited by IP address. Tokens are not verified here, so a token's
+    /// own bucket only ever limits a client further, never in place of its address.
+    fn ip_key(&self, req: &ServiceRequest) -> String {
+        let ip = if self.trust_forwarded_for {
+            req.connection_info()
+                .realip_remote_addr()
+                .map(str::to_owned)
+        } else {
+            req.peer_addr().map(|addr| addr.ip().to_string())
+        };
+        format!("ip:{}", ip.unwrap_or_else(|| "unknown".to_string()))
+    }
+}
+
+/// Identifies a token in keys and logs without revealing it.
+pub fn token_fingerprint(token: &str) -> String {
+    hex::encode(&Sha256::digest(token.as_bytes())[..16])
+}
+
+/// The token sent as `Authorization: Bearer` or, failing that, `X-Api-Key`.
+pub fn api_token(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    bearer
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use sqlx::postgres::PgPoolOptions;
+
+    const QUOTA: Quota = Quota {
+        capacity: 2,
+        period: Duration::from_secs(10),
+    };
+
+    #[test]
+    fn bucket_allows_burst_then_denies() {
+        let (first, tokens) = QUOTA.take(2.0, Duration::ZERO);
+        assert!(first.allowed);
+        assert_eq!(1, first.remaining);
+
+        let (second, tokens) = QUOTA.take(tokens, Duration::ZERO);
+        assert!(second.allowed);
+        assert_eq!(0, second.remaining);
+        assert_eq!(Duration::from_secs(10), second.reset);
+
+        let (third, _) = QUOTA.take(tokens, Duration::ZERO);
+        assert!(!third.allowed);
+        assert_eq!(Duration::from_secs(5), third.retry_after);
+    }
+
+    #[test]
+    fn bucket_refills_over_time_up_to_capacity() {
+        let (decision, tokens) = QUOTA.take(0.0, Duration::from_secs(5));
+        assert!(decision.allowed);
+        assert_eq!(0.0, tokens);
+
+        let (decision, tokens) = QUOTA.take(0.0, Duration::from_secs(60));
+        assert!(decision.allowed);
+        assert_eq!(1.0, tokens);
+    }
+
+    #[test]
+    fn rules_match_method_and_path() {
+        let rule = RateLimitRule {
+            method: "POST".to_string(),
+            path: "/tutors/*".to_string(),
+            requests: 1,
+            period_secs: 1,
+        };
+        assert!(rule.matches("post", "/tutors/"));
+        assert!(rule.matches("POST", "/tutors/12"));
+        assert!(!rule.matches("GET", "/tutors/"));
+        assert!(!rule.matches("POST", "/courses"));
+    }
+
+    #[test]
+    fn memory_store_tracks_clients_separately() {
+        let store = MemoryStore::default();
+        assert!(store.take("a", QUOTA).allowed);
+        assert!(store.take("a", QUOTA).allowed);
+        assert!(!store.take("a", QUOTA).allowed);
+        assert!(store.take("b", QUOTA).allowed);
+    }
+
+    #[test]
+    fn memory_store_forgets_least_recently_used_bucket() {
+        let store = MemoryStore::with_max_buckets(2);
+        store.take("a", QUOTA);
+        store.take("b", QUOTA);
+        store.take("a", QUOTA);
+        store.take("c", QUOTA);
+        assert_eq!(2, store.len());
+
+        // a was used after b, so it is still tracked and empty.
+        assert!(!store.take("a", QUOTA).allowed);
+        assert_eq!(1, store.take("b", QUOTA).remaining);
+    }
+
+    #[actix_rt::test]
+    async fn clients_are_limited_by_address_and_by_token() {
+        let rule = RateLimitRule {
+            method: "*".to_string(),
+            path: "/*".to_string(),
+            requests: 2,
+            period_secs: 60,
+        };
+        let limiter = RateLimiter::new(
+            vec![rule],
+            RateLimitStore::Memory(MemoryStore::default()),
+            false,
+        );
+        let request = |ip: &str, token: &str| {
+            TestRequest::default()
+                .peer_addr(format!("{ip}:4000").parse().unwrap())
+                .insert_header((API_KEY_HEADER, token))
+                .to_srv_request()
+        };
+        let limiter = &limiter;
+        let allowed = |req| async move { limiter.check(&req).await.unwrap().allowed };
+
+        // A fresh token per request does not get an address past its quota.
+        assert!(allowed(request("10.0.0.1", "a")).await);
+        assert!(allowed(request("10.0.0.1", "b")).await);
+        assert!(!allowed(request("10.0.0.1", "c")).await);
+
+        // Nor does spreading a token over addresses get it past its own.
+        assert!(allowed(request("10.0.0.2", "d")).await);
+        assert!(allowed(request("10.0.0.3", "d")).await);
+        assert!(!allowed(request("10.0.0.4", "d")).await);
+    }
+
+    #[actix_rt::test]
+    async fn postgres_store_shares_and_prunes_buckets() {
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+        // One connection, so every query sees the temporary table.
+        let pg_pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&database_url)
+            .await
+            .unwrap();
+        sqlx::query(
+            "CREATE TEMPORARY TABLE test_rate_limit (
+                bucket_key varchar(300) primary key,
+                tokens double precision not null,
+                updated_at TIMESTAMPTZ not null default now()
+            )",
+        )
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+        let store = PostgresStore::new(pg_pool, "test_rate_limit");
+
+        assert_eq!(1, store.take("a", QUOTA).await.unwrap().remaining);
+        assert_eq!(0, store.take("a", QUOTA).await.unwrap().remaining);
+        assert!(!store.take("a", QUOTA).await.unwrap().allowed);
+
+        assert_eq!(0, store.prune(Duration::from_secs(3600)).await.unwrap());
+        assert_eq!(1, store.prune(Duration::ZERO).await.unwrap());
+        assert_eq!(1, store.take("a", QUOTA).await.unwrap().remaining);
+    }
+}
@@ -0,0 +1,327 @@
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+
+use awc::ClientRequest;
+
+use futures_util::future::BoxFuture;
+
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{TraceError, TracerProvider as _};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter};
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::request_id::{current_request_id, REQUEST_ID_HEADER};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    #[serde(alias = "human", alias = "text")]
+    Pretty,
+    Json,
+}
+
+/// Where finished OpenTelemetry spans are sent.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TraceExporter {
+    #[default]
+    None,
+    Otlp {
+        endpoint: String,
+    },
+    File {
+        path: PathBuf,
+    },
+}
+
+pub struct Telemetry {
+    provider: TracerProvider,
+}
+
+impl Telemetry {
+    /// Flushes buffered spans. The batch processor runs on this runtime, so the
+    /// blocking shutdown call has to happen off of it.
+    pub async fn shutdown(self) {
+        let provider = self.provider;
+        match actix_web::rt::task::spawn_blocking(move || provider.shutdown()).await {
+            Ok(Err(err)) => tracing::warn!(error = %err, "Failed to flush trace exporter"),
+            Err(err) => tracing::warn!(error = %err, "Failed to flush trace exporter"),
+            Ok(Ok(())) => {}
+        }
+    }
+}
+
+/// Installs the global tracing subscriber, exporting spans as `service_name`. Filtering
+/// follows `RUST_LOG`, defaulting to `info`.
+///
+/// Spans are always bridged to OpenTelemetry so W3C trace context propagates even
+/// when no exporter is configured.
+pub fn init_subscriber(
+    service_name: &'static str,
+    format: LogFormat,
+    exporter: &TraceExporter,
+) -> Result<Telemetry, TraceError> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let builder = TracerProvider::builder()
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]));
+    let provider = match exporter {
+        TraceExporter::None => builder.build(),
+        TraceExporter::Otlp { endpoint } => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()?;
+            builder
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .build()
+        }
+        TraceExporter::File { path } => {
+            let exporter =
+                FileSpanExporter::create(path).map_err(|err| TraceError::Other(Box::new(err)))?;
+            builder
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .build()
+        }
+    };
+
+    let otel_layer = tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name));
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let registry = tracing_subscriber::registry().with(filter).with(otel_layer);
+
+    match format {
+        LogFormat::Pretty => registry.with(fmt::layer()).init(),
+        LogFormat::Json => registry
+            .with(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(false),
+            )
+            .init(),
+    }
+
+    Ok(Telemetry { provider })
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Returns the remote trace context carried by `traceparent`/`tracestate` headers.
+pub fn extract_context(headers: &HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+pub trait ClientRequestExt {
+    /// Forwards the current trace context and request id to the service being called.
+    fn with_trace_context(self) -> Self;
+}
+
+impl ClientRequestExt for ClientRequest {
+    fn with_trace_context(mut self) -> Self {
+        let cx = tracing::Span::current().context();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&cx, &mut HeaderInjector(self.headers_mut()))
+        });
+
+        match current_request_id() {
+            Some(request_id) => self.insert_header((REQUEST_ID_HEADER, request_id)),
+            None => self,
+        }
+    }
+}
+
+/// Appends finished spans to a file as JSON lines.
+#[derive(Debug)]
+struct FileSpanExporter {
+    writer: BufWriter<File>,
+}
+
+impl FileSpanExporter {
+    fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    fn write_batch(&mut self, batch: &[SpanData]) -> io::Result<()> {
+        for span in batch {
+            serde_json::to_writer(&mut self.writer, &span_to_json(span))?;
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.flush()
+    }
+}
+
+impl SpanExporter for FileSpanExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let result = self
+            .write_batch(&batch)
+            .map_err(|err| TraceError::Other(Box::new(err)));
+        Box::pin(std::future::ready(result))
+    }
+}
+
+fn span_to_json(span: &SpanData) -> Value {
+    let attributes: Map<String, Value> = span
+        .attributes
+        .iter()
+        .map(|kv| (kv.key.to_string(), Value::String(kv.value.to_string())))
+        .collect();
+
+    json!({
+        "trace_id": span.span_context.trace_id().to_string(),
+        "span_id": span.span_context.span_id().to_string(),
+        "parent_span_id": span.parent_span_id.to_string(),
+        "name": span.name,
+        "kind": format!("{:?}", span.span_kind),
+        "start_time_unix_nano": unix_nanos(span.start_time),
+        "end_time_unix_nano": unix_nanos(span.end_time),
+        "attributes": attributes,
+        "status": format!("{:?}", span.status),
+    })
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request_id::request_id;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App, HttpRequest, HttpResponse, HttpServer};
+    use awc::Client;
+    use std::net::TcpListener;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const CALLER_SPAN_ID: &str = "00f067aa0ba902b7";
+
+    /// Answers with the trace and request id headers it received.
+    async fn echo_headers(req: HttpRequest) -> HttpResponse {
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        HttpResponse::Ok().json(json!({
+            "traceparent": header("traceparent"),
+            "request_id": header(REQUEST_ID_HEADER),
+        }))
+    }
+
+    /// Calls `url` the way the web app calls the web service.
+    async fn call_downstream(url: web::Data<String>) -> HttpResponse {
+        let mut resp = Client::new()
+            .get(url.as_str())
+            .with_trace_context()
+            .send()
+            .await
+            .unwrap();
+        HttpResponse::Ok().json(resp.json::<Value>().await.unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn incoming_trace_is_continued_downstream_and_exported() {
+        let path = std::env::temp_dir().join(format!("spans-{}.jsonl", uuid::Uuid::new_v4()));
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(FileSpanExporter::create(&path).unwrap())
+            .build();
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let downstream = HttpServer::new(|| App::new().route("/", web::get().to(echo_headers)))
+            .workers(1)
+            .listen(listener)
+            .unwrap()
+            .run();
+        let downstream_handle = downstream.handle();
+        actix_web::rt::spawn(downstream);
+
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(request_id))
+                .app_data(web::Data::new(url))
+                .route("/", web::get().to(call_downstream)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((
+                "traceparent",
+                format!("00-{}-{}-01", TRACE_ID, CALLER_SPAN_ID),
+            ))
+            .insert_header((REQUEST_ID_HEADER, "abc-123"))
+            .to_request();
+        let seen: Value = test::call_and_read_body_json(&app, req).await;
+        downstream_handle.stop(true).await;
+
+        // The downstream call belongs to the caller's trace, as a child of our span.
+        let traceparent = seen["traceparent"].as_str().unwrap();
+        let parts: Vec<&str> = traceparent.split('-').collect();
+        assert_eq!(4, parts.len(), "{}", traceparent);
+        assert_eq!(TRACE_ID, parts[1]);
+        assert_ne!(CALLER_SPAN_ID, parts[2]);
+        assert_eq!("abc-123", seen["request_id"]);
+
+        provider.force_flush();
+        let exported = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let span: Value = exported
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .find(|span| span["name"] == "http_request")
+            .expect("request span was not exported");
+        assert_eq!(TRACE_ID, span["trace_id"]);
+        assert_eq!(CALLER_SPAN_ID, span["parent_span_id"]);
+        assert_eq!(parts[2], span["span_id"]);
+    }
+}
@@ -0,0 +1,4 @@
+/* Runs after dbscripts/tutor-course.sql has created the schema */
+grant all privileges on all tables in schema public to truuser;
+grant all privileges on all sequences in schema public to truuser;
+grant execute on all functions in schema public to truuser;
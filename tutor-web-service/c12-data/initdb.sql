@@ -1,5 +1,4 @@
-CREATE DATABASE ezytutors; 
-
+/* The ezytutors database itself is created from POSTGRES_DB */
 CREATE USER truuser;
 GRANT ALL PRIVILEGES ON DATABASE ezytutors TO truuser;
 GRANT ALL ON SCHEMA public TO truuser;
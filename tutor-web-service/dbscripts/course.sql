@@ -14,7 +14,9 @@ create table ezy_course_c6 (
     course_price INT,
     course_language varchar(30),
     course_level varchar(30),
-    posted_time TIMESTAMP default now()
+    posted_time TIMESTAMP,
+    course_status varchar(20) not null default 'draft',
+    publish_at TIMESTAMPTZ
 );
 
 
@@ -1,6 +1,41 @@
 /* Drop tables if they already exist */
+drop table if exists ezy_course_translation;
+drop table if exists ezy_ledger_entry;
+drop table if exists ezy_ledger_transaction;
+drop table if exists ezy_payout;
+drop table if exists ezy_cart_item;
+drop table if exists ezy_cart_coupon;
+drop table if exists ezy_refund_item;
+drop table if exists ezy_refund;
+drop table if exists ezy_cancellation_policy;
+drop table if exists ezy_order_item;
+drop table if exists ezy_bundle_course;
+drop table if exists ezy_bundle;
+drop table if exists ezy_enrollment;
+drop table if exists ezy_order;
+drop table if exists ezy_coupon;
+drop table if exists ezy_course_sale;
+drop table if exists ezy_course_prerequisite;
+drop table if exists ezy_course_category;
+drop table if exists ezy_course_tag;
+drop table if exists ezy_category;
+drop table if exists ezy_tag;
+drop table if exists ezy_course_revision;
 drop table if exists ezy_course_c6 cascade;
+drop table if exists ezy_tutor_rating;
 drop table if exists ezy_tutor_c6;
+drop table if exists ezy_rate_limit;
+drop sequence if exists ezy_change_id_seq;
+drop sequence if exists ezy_visit_count_seq;
+drop table if exists ezy_audit_log;
+drop table if exists ezy_webhook_attempt;
+drop table if exists ezy_webhook_delivery;
+drop table if exists ezy_webhook_outbox;
+drop table if exists ezy_webhook_subscription;
+drop function if exists ezy_audit_log_append_only;
+drop function if exists ezy_ledger_append_only;
+drop function if exists ezy_ledger_check_balanced;
+drop function if exists ezy_jaccard;
 
 /* Create tables. */
 create table ezy_tutor_c6 (
@@ -10,6 +45,15 @@ create table ezy_tutor_c6 (
     tutor_profile varchar(2000) not null
 );
 
+/* One rating per student enrolled in one of the tutor's courses */
+create table ezy_tutor_rating (
+    tutor_id INT not null references ezy_tutor_c6(tutor_id) on delete cascade,
+    rater varchar(100) not null,
+    rating SMALLINT not null check (rating between 1 and 5),
+    rated_at TIMESTAMPTZ not null default now(),
+    primary key (tutor_id, rater)
+);
+
 create table ezy_course_c6 (
     course_id serial primary key,
     tutor_id INT not null,
@@ -21,7 +65,14 @@ create table ezy_course_c6 (
     course_price INT,
     course_language varchar(30),
     course_level varchar(30),
-    posted_time TIMESTAMP default now(),
+    /* Set when the course is first published */
+    posted_time TIMESTAMP,
+    course_status varchar(20) not null default 'draft'
+        check (course_status in ('draft', 'in_review', 'scheduled', 'published', 'archived')),
+    /* When a scheduled course goes live */
+    publish_at TIMESTAMPTZ,
+    /* When the first live session takes place; NULL for self-paced courses */
+    first_session_at TIMESTAMPTZ,
 
     CONSTRAINT fk_tutor
     FOREIGN KEY(tutor_id)
@@ -29,6 +80,408 @@ create table ezy_course_c6 (
     ON DELETE cascade
 );
 
+/* Lets the publisher find scheduled courses that are due */
+create index ezy_course_scheduled on ezy_course_c6 (publish_at)
+where course_status = 'scheduled';
+
+create table ezy_category (
+    category_id serial primary key,
+    /* Top-level categories have no parent */
+    parent_id INT references ezy_category(category_id),
+    category_name varchar(100) not null,
+    created_at TIMESTAMPTZ not null default now()
+);
+
+/* Sibling categories have distinct names */
+create unique index ezy_category_name on ezy_category (coalesce(parent_id, 0), lower(category_name));
+
+create table ezy_course_category (
+    course_id INT not null references ezy_course_c6(course_id) on delete cascade,
+    category_id INT not null references ezy_category(category_id) on delete cascade,
+    primary key (course_id, category_id)
+);
+
+create index ezy_course_category_category on ezy_course_category (category_id);
+
+create table ezy_tag (
+    tag_id serial primary key,
+    /* Trimmed and lowercase */
+    tag_name varchar(50) not null unique
+);
+
+create table ezy_course_tag (
+    course_id INT not null references ezy_course_c6(course_id) on delete cascade,
+    tag_id INT not null references ezy_tag(tag_id) on delete cascade,
+    primary key (course_id, tag_id)
+);
+
+create index ezy_course_tag_tag on ezy_course_tag (tag_id);
+
+/* A course requires each of its prerequisites to be completed first; the graph is acyclic */
+create table ezy_course_prerequisite (
+    course_id INT not null references ezy_course_c6(course_id) on delete cascade,
+    prerequisite_id INT not null references ezy_course_c6(course_id) on delete cascade,
+    primary key (course_id, prerequisite_id),
+    check (course_id <> prerequisite_id)
+);
+
+create index ezy_course_prerequisite_prerequisite on ezy_course_prerequisite (prerequisite_id);
+
+/* Courses of one tutor sold together and taken in order */
+create table ezy_bundle (
+    bundle_id serial primary key,
+    tutor_id INT not null references ezy_tutor_c6(tutor_id) on delete cascade,
+    bundle_name varchar(140) not null,
+    bundle_description varchar(2000),
+    bundle_price INT not null check (bundle_price > 0),
+    created_at TIMESTAMPTZ not null default now()
+);
+
+create index ezy_bundle_tutor on ezy_bundle (tutor_id);
+
+create table ezy_bundle_course (
+    bundle_id INT not null references ezy_bundle(bundle_id) on delete cascade,
+    course_id INT not null references ezy_course_c6(course_id) on delete cascade,
+    /* Where the course comes in the path, from 1 */
+    position INT not null,
+    primary key (bundle_id, course_id),
+    unique (bundle_id, position)
+);
+
+create index ezy_bundle_course_course on ezy_bundle_course (course_id);
+
+/* Time-boxed reduced prices; a course has at most one sale at any time */
+create table ezy_course_sale (
+    sale_id serial primary key,
+    course_id INT not null references ezy_course_c6(course_id) on delete cascade,
+    sale_price INT not null check (sale_price >= 0),
+    starts_at TIMESTAMPTZ not null,
+    ends_at TIMESTAMPTZ not null,
+    check (ends_at > starts_at)
+);
+
+create index ezy_course_sale_course on ezy_course_sale (course_id, ends_at);
+
+/* Discount codes for one of a tutor's courses, or for everything the tutor sells */
+create table ezy_coupon (
+    coupon_id serial primary key,
+    /* Stored uppercase, so codes match whatever their case */
+    code varchar(40) not null unique,
+    tutor_id INT not null references ezy_tutor_c6(tutor_id) on delete cascade,
+    course_id INT references ezy_course_c6(course_id) on delete cascade,
+    percent_off INT check (percent_off between 1 and 100),
+    /* Taken once off the items the coupon applies to, in the currency's minor unit */
+    amount_off INT check (amount_off > 0),
+    max_redemptions INT check (max_redemptions > 0),
+    redemptions INT not null default 0,
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ not null default now(),
+    check ((percent_off IS NULL) <> (amount_off IS NULL))
+);
+
+create index ezy_coupon_tutor on ezy_coupon (tutor_id);
+
+/* Checkouts by students, identified like audit log actors. Amounts are in the currency's minor unit */
+create table ezy_order (
+    order_id serial primary key,
+    student varchar(100) not null,
+    order_status varchar(20) not null default 'pending'
+        check (order_status in ('pending', 'paid', 'failed')),
+    /* What the items cost before any discount */
+    original_amount INT not null check (original_amount >= 0),
+    discount_amount INT not null default 0 check (discount_amount >= 0),
+    total_amount INT not null check (total_amount >= 0),
+    currency varchar(3) not null,
+    coupon_id INT references ezy_coupon(coupon_id) on delete set null,
+    coupon_code varchar(40),
+    /* The provider's id for the payment collecting total_amount */
+    payment_id varchar(100) unique,
+    /* Returned to the student so far */
+    refunded_amount INT not null default 0 check (refunded_amount between 0 and total_amount),
+    created_at TIMESTAMPTZ not null default now(),
+    paid_at TIMESTAMPTZ
+);
+
+create index ezy_order_student on ezy_order (student, created_at);
+
+/* What was bought, as it was at checkout */
+create table ezy_order_item (
+    order_id INT not null references ezy_order(order_id) on delete cascade,
+    line_no INT not null,
+    course_id INT references ezy_course_c6(course_id) on delete set null,
+    bundle_id INT references ezy_bundle(bundle_id) on delete set null,
+    /* The tutor selling the item */
+    tutor_id INT not null,
+    item_name varchar(140) not null,
+    original_price INT not null check (original_price >= 0),
+    /* From a sale and the order's coupon */
+    discount INT not null default 0 check (discount >= 0),
+    /* What the student paid for the item */
+    final_price INT not null check (final_price >= 0),
+    /* The platform's share of final_price, the rest being the tutor's */
+    platform_fee INT not null default 0 check (platform_fee between 0 and final_price),
+    /* The courses the item enrolls the student in once paid */
+    course_ids INT[] not null,
+    primary key (order_id, line_no)
+);
+
+create table ezy_cart_item (
+    student varchar(100) not null,
+    course_id INT references ezy_course_c6(course_id) on delete cascade,
+    bundle_id INT references ezy_bundle(bundle_id) on delete cascade,
+    added_at TIMESTAMPTZ not null default now(),
+    check ((course_id IS NULL) <> (bundle_id IS NULL))
+);
+
+create unique index ezy_cart_item_course on ezy_cart_item (student, course_id);
+create unique index ezy_cart_item_bundle on ezy_cart_item (student, bundle_id);
+
+/* The coupon applied to a student's cart */
+create table ezy_cart_coupon (
+    student varchar(100) primary key,
+    coupon_id INT not null references ezy_coupon(coupon_id) on delete cascade
+);
+
+/* How much of what a tutor sold students get back when they cancel */
+create table ezy_cancellation_policy (
+    tutor_id INT primary key references ezy_tutor_c6(tutor_id) on delete cascade,
+    /* Days after paying during which cancelling refunds the whole price */
+    full_refund_days INT not null check (full_refund_days between 0 and 365),
+    /* Refunded after that, as long as none of the item's courses has had its first session */
+    partial_refund_percent INT not null check (partial_refund_percent between 0 and 100),
+    updated_at TIMESTAMPTZ not null default now()
+);
+
+/* Refunds students asked for, approved or rejected by an operator */
+create table ezy_refund (
+    refund_id serial primary key,
+    order_id INT not null references ezy_order(order_id) on delete cascade,
+    refund_status varchar(20) not null default 'requested'
+        check (refund_status in ('requested', 'refunding', 'rejected', 'refunded')),
+    reason varchar(1000),
+    /* What the cancellation policies grant for the items when requested */
+    amount INT not null check (amount >= 0),
+    /* The provider's id for the refund, once made */
+    provider_refund_id varchar(100),
+    requested_at TIMESTAMPTZ not null default now(),
+    decided_at TIMESTAMPTZ,
+    decision_note varchar(1000)
+);
+
+create index ezy_refund_order on ezy_refund (order_id);
+create index ezy_refund_status on ezy_refund (refund_status, requested_at);
+
+/* The order items a refund cancels */
+create table ezy_refund_item (
+    refund_id INT not null references ezy_refund(refund_id) on delete cascade,
+    order_id INT not null,
+    line_no INT not null,
+    amount INT not null check (amount >= 0),
+    primary key (refund_id, line_no),
+    foreign key (order_id, line_no) references ezy_order_item(order_id, line_no) on delete cascade
+);
+
+create index ezy_refund_item_line on ezy_refund_item (order_id, line_no);
+
+/* Students, identified like audit log actors, taking a course */
+create table ezy_enrollment (
+    course_id INT not null references ezy_course_c6(course_id) on delete cascade,
+    student varchar(100) not null,
+    enrolled_at TIMESTAMPTZ not null default now(),
+    completed_at TIMESTAMPTZ,
+    /* The order paying for the enrollment, if any */
+    order_id INT references ezy_order(order_id),
+    primary key (course_id, student)
+);
+
+create index ezy_enrollment_student on ezy_enrollment (student, enrolled_at);
+create index ezy_enrollment_order on ezy_enrollment (order_id);
+
+/* Tutors' earnings paid out of their available balance, one per tutor per payout batch */
+create table ezy_payout (
+    payout_id serial primary key,
+    tutor_id INT not null,
+    amount INT not null check (amount > 0),
+    currency varchar(3) not null,
+    created_at TIMESTAMPTZ not null default now()
+);
+
+create index ezy_payout_tutor on ezy_payout (tutor_id, created_at);
+
+/* Double-entry ledger of tutors' earnings. Tutors are not referenced so that deleting one
+   keeps the ledger balanced. reference_id is the order, refund or payout of the kind */
+create table ezy_ledger_transaction (
+    transaction_id bigserial primary key,
+    kind varchar(20) not null check (kind in ('sale', 'refund', 'payout')),
+    tutor_id INT not null,
+    reference_id INT not null,
+    currency varchar(3) not null,
+    recorded_at TIMESTAMPTZ not null default now(),
+    unique (kind, reference_id, tutor_id)
+);
+
+create index ezy_ledger_transaction_tutor on ezy_ledger_transaction (tutor_id, recorded_at);
+
+/* Credits are positive and debits negative, so a transaction's entries sum to zero */
+create table ezy_ledger_entry (
+    transaction_id BIGINT not null references ezy_ledger_transaction(transaction_id),
+    account varchar(20) not null
+        check (account in ('student_payments', 'platform_fees', 'tutor_available', 'tutor_paid_out')),
+    amount INT not null check (amount <> 0),
+    primary key (transaction_id, account)
+);
+
+create function ezy_ledger_check_balanced() returns trigger as $$
+begin
+    if (select sum(amount) from ezy_ledger_entry where transaction_id = new.transaction_id) <> 0 then
+        raise exception 'ledger transaction % does not balance', new.transaction_id;
+    end if;
+    return null;
+end;
+$$ language plpgsql;
+
+create constraint trigger ezy_ledger_entry_balanced
+after insert on ezy_ledger_entry
+deferrable initially deferred
+for each row execute function ezy_ledger_check_balanced();
+
+/* The ledger is append-only, like the audit log */
+create function ezy_ledger_append_only() returns trigger as $$
+begin
+    raise exception '% is append-only', tg_table_name;
+end;
+$$ language plpgsql;
+
+create trigger ezy_ledger_transaction_no_changes
+before update or delete on ezy_ledger_transaction
+for each row execute function ezy_ledger_append_only();
+
+create trigger ezy_ledger_entry_no_changes
+before update or delete on ezy_ledger_entry
+for each row execute function ezy_ledger_append_only();
+
+/* Size of the intersection over the size of the union of two sets; 0 when both are empty */
+create function ezy_jaccard(a anyarray, b anyarray) returns float8 as $$
+    select coalesce(
+        (select count(*) from (select unnest(a) intersect select unnest(b)) i)::float8
+            / nullif((select count(*) from (select unnest(a) union select unnest(b)) u), 0),
+        0)
+$$ language sql immutable;
+
+create table ezy_course_translation
+(
+    course_id INT not null references ezy_course_c6(course_id) on delete cascade,
+    /* Lowercase BCP 47 tag, e.g. pt-br */
+    locale varchar(35) not null,
+    course_name varchar(140) not null,
+    course_description varchar(2000),
+    updated_at TIMESTAMPTZ not null default now(),
+    primary key (course_id, locale)
+);
+
+/* Every version of a course, numbered from 1 per course */
+create table ezy_course_revision (
+    course_id INT not null references ezy_course_c6(course_id) on delete cascade,
+    revision INT not null,
+    content jsonb not null,
+    actor varchar(100) not null,
+    restored_from INT,
+    created_at TIMESTAMPTZ not null default now(),
+    primary key (course_id, revision)
+);
+
+/* Health checks answered by all instances */
+create sequence ezy_visit_count_seq minvalue 0 start 0;
+
+/* Token buckets shared by all instances when rate_limit.store = "postgres" */
+create table ezy_rate_limit (
+    bucket_key varchar(300) primary key,
+    tokens double precision not null,
+    updated_at TIMESTAMPTZ not null default now()
+);
+
+/* Ids of change events, shared by all instances */
+create sequence ezy_change_id_seq;
+
+/* Every tutor and course mutation, written in the transaction making it */
+create table ezy_audit_log (
+    audit_id bigserial primary key,
+    actor varchar(100) not null,
+    action varchar(10) not null,
+    entity varchar(20) not null,
+    entity_id INT not null,
+    before_data jsonb,
+    after_data jsonb,
+    request_id varchar(128),
+    recorded_at TIMESTAMPTZ not null default now()
+);
+
+create index ezy_audit_log_entity on ezy_audit_log (entity, entity_id, audit_id);
+create index ezy_audit_log_actor on ezy_audit_log (actor, audit_id);
+create index ezy_audit_log_recorded_at on ezy_audit_log (recorded_at);
+
+/* The audit log is append-only */
+create function ezy_audit_log_append_only() returns trigger as $$
+begin
+    raise exception 'ezy_audit_log is append-only';
+end;
+$$ language plpgsql;
+
+create trigger ezy_audit_log_no_changes
+before update or delete on ezy_audit_log
+for each row execute function ezy_audit_log_append_only();
+
+create trigger ezy_audit_log_no_truncate
+before truncate on ezy_audit_log
+for each statement execute function ezy_audit_log_append_only();
+
+/* Partner endpoints notified of tutor and course events */
+create table ezy_webhook_subscription (
+    subscription_id serial primary key,
+    url varchar(2000) not null,
+    secret varchar(200) not null,
+    event_types varchar(40)[] not null,
+    active boolean not null default true,
+    created_at TIMESTAMPTZ not null default now()
+);
+
+/* Events written in the transaction making the change, then delivered asynchronously */
+create table ezy_webhook_outbox (
+    event_id bigserial primary key,
+    event_type varchar(40) not null,
+    payload jsonb not null,
+    created_at TIMESTAMPTZ not null default now()
+);
+
+/* One row per event and subscription: the delivery queue and its log */
+create table ezy_webhook_delivery (
+    delivery_id bigserial primary key,
+    event_id BIGINT not null references ezy_webhook_outbox(event_id) on delete cascade,
+    subscription_id INT not null references ezy_webhook_subscription(subscription_id) on delete cascade,
+    status varchar(20) not null default 'pending',
+    attempts INT not null default 0,
+    next_attempt_at TIMESTAMPTZ not null default now(),
+    last_status_code INT,
+    last_error varchar(2000),
+    created_at TIMESTAMPTZ not null default now(),
+    delivered_at TIMESTAMPTZ
+);
+
+create index ezy_webhook_delivery_due on ezy_webhook_delivery (next_attempt_at)
+where status = 'pending';
+create index ezy_webhook_delivery_subscription on ezy_webhook_delivery (subscription_id, delivery_id);
+
+create table ezy_webhook_attempt (
+    attempt_id bigserial primary key,
+    delivery_id BIGINT not null references ezy_webhook_delivery(delivery_id) on delete cascade,
+    attempted_at TIMESTAMPTZ not null default now(),
+    status_code INT,
+    error varchar(2000),
+    duration_ms INT not null
+);
+
+create index ezy_webhook_attempt_delivery on ezy_webhook_attempt (delivery_id);
+
 /* Load seed data for testing */
 insert into ezy_tutor_c6(tutor_id, tutor_name, tutor_pic_url,tutor_profile)
 values(1,'Merlene','http://s3.amazon.aws.com/pic1', 'Merlene is an experienced finance professional');
@@ -36,7 +489,23 @@ values(1,'Merlene','http://s3.amazon.aws.com/pic1', 'Merlene is an experienced f
 insert into ezy_tutor_c6(tutor_id, tutor_name, tutor_pic_url,tutor_profile)
 values(2,'Frank','http://s3.amazon.aws.com/pic2', 'Frank is an expert nuclear engineer');
 
-insert into ezy_course_c6(course_id,tutor_id, course_name,course_level, posted_time)
-values(1, 1, 'First course', 'Beginner' , '2021-04-12 05:40:00');
-insert into ezy_course_c6(course_id, tutor_id, course_name, course_format, posted_time)
-values(2, 1, 'Second course', 'ebook', '2021-04-12 05:45:00');
+insert into ezy_course_c6(course_id,tutor_id, course_name,course_level, posted_time, course_status)
+values(1, 1, 'First course', 'Beginner' , '2021-04-12 05:40:00', 'published');
+insert into ezy_course_c6(course_id, tutor_id, course_name, course_format, posted_time, course_status)
+values(2, 1, 'Second course', 'ebook', '2021-04-12 05:45:00', 'published');
+
+insert into ezy_tutor_rating(tutor_id, rater, rating) values(1, 'seed:1', 5), (1, 'seed:2', 4);
+
+insert into ezy_category(category_name) values('Finance');
+insert into ezy_category(parent_id, category_name) values(1, 'Personal finance');
+insert into ezy_course_category(course_id, category_id) values(1, 2);
+insert into ezy_tag(tag_name) values('budgeting');
+insert into ezy_course_tag(course_id, tag_id) values(1, 1);
+
+/* The seed courses start out at revision 1 */
+insert into ezy_course_revision(course_id, revision, content, actor)
+select course_id, 1, to_jsonb(c), 'system' from ezy_course_c6 c;
+
+/* Seed rows were given their ids, so new ones continue after them */
+select setval('ezy_tutor_c6_tutor_id_seq', (select max(tutor_id) from ezy_tutor_c6));
+select setval('ezy_course_c6_course_id_seq', (select max(course_id) from ezy_course_c6));
@@ -0,0 +1,325 @@
+use actix_web::http::header::{
+    CacheControl, CacheDirective, ContentType, ETag, EntityTag, Header, HttpDate, IfModifiedSince,
+    IfNoneMatch, LastModified, IF_NONE_MATCH,
+};
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::CacheConfig;
+use crate::errors::EzyTutorError;
+use crate::models::{ChangeEntity, ChangeEvent};
+
+/// A serialized JSON body together with the validators sent for it.
+#[derive(Debug)]
+pub struct CachedResponse {
+    pub body: Bytes,
+    pub etag: EntityTag,
+    pub last_modified: SystemTime,
+}
+
+impl CachedResponse {
+    fn new<T: Serialize>(
+        value: &T,
+        previous: Option<&CachedResponse>,
+    ) -> Result<Self, EzyTutorError> {
+        let body = Bytes::from(serde_json::to_vec(value).map_err(actix_web::Error::from)?);
+        let etag = EntityTag::new_strong(hex::encode(&Sha256::digest(&body)[..16]));
+        // Reloading unchanged data after the TTL runs out must not move Last-Modified.
+        let last_modified = match previous {
+            Some(previous) if previous.etag.strong_eq(&etag) => previous.last_modified,
+            _ => whole_seconds(SystemTime::now()),
+        };
+        Ok(Self {
+            body,
+            etag,
+            last_modified,
+        })
+    }
+
+    /// Answers `304 Not Modified` when the request's validators still match, and the full
+    /// body otherwise. `If-None-Match` takes precedence over `If-Modified-Since`.
+    pub fn respond(&self, req: &HttpRequest) -> HttpResponse {
+        let not_modified = if req.headers().contains_key(IF_NONE_MATCH) {
+            match IfNoneMatch::parse(req) {
+                Ok(IfNoneMatch::Any) => true,
+                Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&self.etag)),
+                Err(_) => false,
+            }
+        } else {
+            IfModifiedSince::parse(req)
+                .is_ok_and(|since| SystemTime::from(since.0) >= self.last_modified)
+        };
+
+        let mut builder = if not_modified {
+            HttpResponse::NotModified()
+        } else {
+            HttpResponse::Ok()
+        };
+        builder
+            .insert_header(ETag(self.etag.clone()))
+            .insert_header(LastModified(HttpDate::from(self.last_modified)))
+            .insert_header(CacheControl(vec![CacheDirective::NoCache]));
+
+        if not_modified {
+            builder.finish()
+        } else {
+            builder
+                .insert_header(ContentType::json())
+                .body(self.body.clone())
+        }
+    }
+}
+
+/// HTTP dates only carry whole seconds, so validators are compared at that precision.
+fn whole_seconds(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[derive(Debug)]
+struct Entry {
+    response: Arc<CachedResponse>,
+    loaded_at: Instant,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// Responses kept for `ttl` after loading, unless invalidated earlier.
+#[derive(Debug)]
+pub struct TtlCache<K> {
+    name: &'static str,
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<K, Entry>>,
+    /// Bumped by every invalidation so loads that raced with one are not stored.
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Hash + Eq> TtlCache<K> {
+    fn new(name: &'static str, config: &CacheConfig) -> Self {
+        Self {
+            name,
+            ttl: if config.enabled {
+                config.ttl()
+            } else {
+                Duration::ZERO
+            },
+            max_entries: config.max_entries,
+            entries: Mutex::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached response for `key`, calling `load` when there is no fresh one.
+    pub async fn get_or_load<T, F, Fut>(
+        &self,
+        key: K,
+        load: F,
+    ) -> Result<Arc<CachedResponse>, EzyTutorError>
+    where
+        T: Serialize,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, EzyTutorError>>,
+    {
+        let previous = {
+            let entries = self.entries.lock().unwrap();
+            match entries.get(&key) {
+                Some(entry) if entry.loaded_at.elapsed() < self.ttl => {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    tracing::debug!(cache = self.name, "Cache hit");
+                    return Ok(entry.response.clone());
+                }
+                entry => entry.map(|entry| entry.response.clone()),
+            }
+        };
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!(cache = self.name, "Cache miss");
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let value = load().await?;
+        let response = Arc::new(CachedResponse::new(&value, previous.as_deref())?);
+
+        if !self.ttl.is_zero() {
+            let mut entries = self.entries.lock().unwrap();
+            if self.generation.load(Ordering::Acquire) == generation {
+                if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+                    entries.retain(|_, entry| entry.loaded_at.elapsed() < self.ttl);
+                }
+                if entries.len() < self.max_entries || entries.contains_key(&key) {
+                    entries.insert(
+                        key,
+                        Entry {
+                            response: response.clone(),
+                            loaded_at: Instant::now(),
+                        },
+                    );
+                }
+            }
+        }
+        Ok(response)
+    }
+
+    pub fn invalidate(&self, key: &K) {
+        let mut entries = self.entries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        entries.remove(key);
+    }
+
+    /// Invalidates every key `stale` returns true for.
+    pub fn invalidate_where(&self, stale: impl Fn(&K) -> bool) {
+        let mut entries = self.entries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        entries.retain(|key, _| !stale(key));
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        entries.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap().len(),
+        }
+    }
+}
+
+/// Caches for the read-mostly tutor and course listings.
+#[derive(Debug)]
+pub struct ReadCache {
+    pub tutors: TtlCache<()>,
+    /// Published courses by tutor and the locales they were requested in.
+    pub courses_by_tutor: TtlCache<(i32, Vec<String>)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadCacheStats {
+    pub tutors: CacheStats,
+    pub courses_by_tutor: CacheStats,
+}
+
+impl ReadCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            tutors: TtlCache::new("tutors", config),
+            courses_by_tutor: TtlCache::new("courses_by_tutor", config),
+        }
+    }
+
+    /// Forgets everything derived from the tutor, including their courses, which are
+    /// removed along with a deleted tutor.
+    pub fn invalidate_tutor(&self, tutor_id: i32) {
+        self.tutors.invalidate(&());
+        self.invalidate_courses(tutor_id);
+    }
+
+    pub fn invalidate_courses(&self, tutor_id: i32) {
+        self.courses_by_tutor
+            .invalidate_where(|(key, _)| *key == tutor_id);
+    }
+
+    /// Invalidates whatever `event`, possibly made by another instance, made stale.
+    pub fn apply(&self, event: &ChangeEvent) {
+        match event.entity {
+            ChangeEntity::Tutor => self.invalidate_tutor(event.tutor_id),
+            ChangeEntity::Course => self.invalidate_courses(event.tutor_id),
+        }
+    }
+
+    pub fn clear(&self) {
+        self.tutors.clear();
+        self.courses_by_tutor.clear();
+    }
+
+    pub fn stats(&self) -> ReadCacheStats {
+        ReadCacheStats {
+            tutors: self.tutors.stats(),
+            courses_by_tutor: self.courses_by_tutor.stats(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::IF_MODIFIED_SINCE;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+
+    async fn load(value: &'static str) -> Result<&'static str, EzyTutorError> {
+        Ok(value)
+    }
+
+    #[actix_rt::test]
+    async fn serves_hits_until_invalidated() {
+        let cache = ReadCache::new(&CacheConfig::default());
+        let first = cache
+            .courses_by_tutor
+            .get_or_load((1, Vec::new()), || load("a"))
+            .await
+            .unwrap();
+        let second = cache
+            .courses_by_tutor
+            .get_or_load((1, Vec::new()), || load("b"))
+            .await
+            .unwrap();
+        assert_eq!(first.body, second.body);
+
+        cache.invalidate_tutor(1);
+        let third = cache
+            .courses_by_tutor
+            .get_or_load((1, Vec::new()), || load("b"))
+            .await
+            .unwrap();
+        assert_eq!(Bytes::from_static(b"\"b\""), third.body);
+
+        let stats = cache.courses_by_tutor.stats();
+        assert_eq!((1, 2, 1), (stats.hits, stats.misses, stats.entries));
+    }
+
+    #[actix_rt::test]
+    async fn answers_not_modified_for_matching_validators() {
+        let cached = CachedResponse::new(&"a", None).unwrap();
+
+        let req = test::TestRequest::default()
+            .insert_header((IF_NONE_MATCH, cached.etag.to_string()))
+            .to_http_request();
+        assert_eq!(StatusCode::NOT_MODIFIED, cached.respond(&req).status());
+
+        let req = test::TestRequest::default()
+            .insert_header((IF_NONE_MATCH, "\"other\""))
+            .insert_header((IF_MODIFIED_SINCE, HttpDate::from(cached.last_modified)))
+            .to_http_request();
+        assert_eq!(StatusCode::OK, cached.respond(&req).status());
+
+        let req = test::TestRequest::default()
+            .insert_header((IF_MODIFIED_SINCE, HttpDate::from(cached.last_modified)))
+            .to_http_request();
+        assert_eq!(StatusCode::NOT_MODIFIED, cached.respond(&req).status());
+    }
+}
@@ -0,0 +1,127 @@
+use tokio::sync::broadcast;
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::ChangesConfig;
+use crate::models::ChangeEvent;
+
+/// What in-process subscribers receive from the [`ChangeFeed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedItem {
+    Change(ChangeEvent),
+    /// Changes may have been missed, e.g. while the listener was reconnecting; whatever
+    /// subscribers derived from earlier changes has to be reloaded.
+    Reset,
+}
+
+/// A subscription starting right after the requested change.
+pub struct FeedSubscription {
+    /// Changes after the requested one, or `None` when it is no longer (or never was)
+    /// in the replay log and the subscriber has to start over.
+    pub replay: Option<Vec<ChangeEvent>>,
+    pub receiver: broadcast::Receiver<FeedItem>,
+}
+
+/// Changes made by any instance, as announced by the change listener, along with a
+/// bounded log of recent ones for subscribers resuming where they left off.
+#[derive(Debug)]
+pub struct ChangeFeed {
+    log: Mutex<VecDeque<ChangeEvent>>,
+    replay: usize,
+    sender: broadcast::Sender<FeedItem>,
+    heartbeat: Duration,
+}
+
+impl ChangeFeed {
+    pub fn new(config: &ChangesConfig) -> Self {
+        Self {
+            log: Mutex::new(VecDeque::with_capacity(config.replay)),
+            replay: config.replay,
+            sender: broadcast::channel(config.buffer).0,
+            heartbeat: config.heartbeat(),
+        }
+    }
+
+    /// How often idle streams of the feed should show they are still alive.
+    pub fn heartbeat(&self) -> Duration {
+        self.heartbeat
+    }
+
+    pub fn publish(&self, event: ChangeEvent) {
+        // Logging and sending under one lock keeps subscribe() free of gaps and repeats.
+        let mut log = self.log.lock().unwrap();
+        if self.replay > 0 {
+            if log.len() == self.replay {
+                log.pop_front();
+            }
+            log.push_back(event.clone());
+        }
+        // Nobody subscribed is not an error.
+        let _ = self.sender.send(FeedItem::Change(event));
+    }
+
+    /// Forgets the replay log, which may now have a gap, and tells subscribers so.
+    pub fn reset(&self) {
+        let mut log = self.log.lock().unwrap();
+        log.clear();
+        let _ = self.sender.send(FeedItem::Reset);
+    }
+
+    /// Subscribes to changes following `last_change_id`, or to new changes only when
+    /// there is none.
+    pub fn subscribe(&self, last_change_id: Option<i64>) -> FeedSubscription {
+        let log = self.log.lock().unwrap();
+        let replay = match last_change_id {
+            None => Some(Vec::new()),
+            // Ids are handed out before commit, so they need not arrive in order.
+            Some(id) => log
+                .iter()
+                .position(|event| event.change_id == Some(id))
+                .map(|pos| log.iter().skip(pos + 1).cloned().collect()),
+        };
+        FeedSubscription {
+            replay,
+            receiver: self.sender.subscribe(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ChangeAction;
+
+    fn change(change_id: i64) -> ChangeEvent {
+        ChangeEvent {
+            change_id: Some(change_id),
+            ..ChangeEvent::course(ChangeAction::Updated, 1, 1)
+        }
+    }
+
+    #[test]
+    fn resumes_after_known_changes_only() {
+        let feed = ChangeFeed::new(&ChangesConfig {
+            replay: 2,
+            ..Default::default()
+        });
+        feed.publish(change(1));
+        feed.publish(change(3));
+        feed.publish(change(2));
+
+        let mut subscription = feed.subscribe(Some(3));
+        assert_eq!(Some(vec![change(2)]), subscription.replay);
+        assert_eq!(None, feed.subscribe(Some(1)).replay);
+
+        feed.publish(change(4));
+        assert_eq!(
+            FeedItem::Change(change(4)),
+            subscription.receiver.try_recv().unwrap()
+        );
+
+        feed.reset();
+        assert_eq!(None, feed.subscribe(Some(4)).replay);
+        assert_eq!(FeedItem::Reset, subscription.receiver.try_recv().unwrap());
+    }
+}
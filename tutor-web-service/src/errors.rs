@@ -4,6 +4,8 @@ use actix_web::HttpResponse;
 
 use serde::Serialize;
 
+use crate::middleware::current_request_id;
+
 use std::fmt::{self, Display};
 
 #[derive(Debug)]
@@ -12,6 +14,13 @@ pub enum EzyTutorError {
     ActixError(actix_web::Error),
     NotFound(String),
     InvalidInput(String),
+    Conflict(String),
+    Unauthorized,
+    Forbidden(String),
+    TooManyRequests,
+    PayloadTooLarge(String),
+    UnsupportedMediaType(String),
+    StorageError(String),
 }
 
 impl Display for EzyTutorError {
@@ -21,6 +30,13 @@ impl Display for EzyTutorError {
             Self::ActixError(_) => write!(f, "Internal server error"),
             Self::NotFound(err) => write!(f, "{}", err),
             Self::InvalidInput(err) => write!(f, "{}", err),
+            Self::Conflict(err) => write!(f, "{}", err),
+            Self::Unauthorized => write!(f, "Valid credentials are required"),
+            Self::Forbidden(err) => write!(f, "{}", err),
+            Self::TooManyRequests => write!(f, "Too many requests, please retry later"),
+            Self::PayloadTooLarge(err) => write!(f, "{}", err),
+            Self::UnsupportedMediaType(err) => write!(f, "{}", err),
+            Self::StorageError(_) => write!(f, "Storage error"),
         }
     }
 }
@@ -40,15 +56,24 @@ impl From<actix_web::Error> for EzyTutorError {
 impl ResponseError for EzyTutorError {
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::DbError(_) | Self::ActixError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::DbError(_) | Self::ActixError(_) | Self::StorageError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
             Self::NotFound(_) => StatusCode::NOT_FOUND,
             Self::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
         }
     }
 
     fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
         HttpResponse::build(self.status_code()).json(MyErrorResponse {
             error_message: Self::error_response(self),
+            request_id: current_request_id(),
         })
     }
 }
@@ -56,10 +81,18 @@ impl ResponseError for EzyTutorError {
 impl EzyTutorError {
     fn error_response(&self) -> String {
         match self {
-            Self::DbError(err) => eprintln!("Database error occurred: {}", err),
-            Self::ActixError(err) => eprintln!("Server error occurred: {}", err),
-            Self::NotFound(err) => eprintln!("Not found error occurred: {}", err),
-            Self::InvalidInput(err) => eprintln!("Invalid parameters received: {}", err),
+            Self::DbError(err) => tracing::error!(error = %err, "Database error occurred"),
+            Self::ActixError(err) => tracing::error!(error = %err, "Server error occurred"),
+            Self::NotFound(err) => tracing::warn!(error = %err, "Not found error occurred"),
+            Self::InvalidInput(err) => tracing::warn!(error = %err, "Invalid parameters received"),
+            Self::Conflict(err) => tracing::warn!(error = %err, "Conflicting request received"),
+            Self::Unauthorized => tracing::warn!("Request rejected for missing credentials"),
+            Self::Forbidden(err) => tracing::warn!(error = %err, "Forbidden request received"),
+            Self::TooManyRequests => tracing::info!("Request rejected by rate limiter"),
+            Self::PayloadTooLarge(err) | Self::UnsupportedMediaType(err) => {
+                tracing::warn!(error = %err, "Upload rejected")
+            }
+            Self::StorageError(err) => tracing::error!(error = %err, "Storage error occurred"),
         }
 
         format!("{}", self)
@@ -69,4 +102,38 @@ impl EzyTutorError {
 #[derive(Debug, Serialize)]
 struct MyErrorResponse {
     error_message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::{request_id, REQUEST_ID_HEADER};
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App};
+
+    async fn not_found() -> Result<HttpResponse, EzyTutorError> {
+        Err(EzyTutorError::NotFound("Course id not found".to_string()))
+    }
+
+    #[actix_rt::test]
+    async fn error_body_carries_request_id() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(request_id))
+                .route("/", web::get().to(not_found)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((REQUEST_ID_HEADER, "abc-123"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::NOT_FOUND, resp.status());
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!("abc-123", body["request_id"]);
+    }
 }
@@ -0,0 +1,258 @@
+use super::{localized, optional_json, vary_by_language};
+use crate::config::{EarningsConfig, PaymentsConfig};
+use crate::errors::EzyTutorError;
+use crate::locale;
+use crate::models::{
+    AuditQuery, CourseListQuery, CourseStatus, NewWebhookSubscription, RefundDecision,
+    RefundListQuery, UpdateWebhookSubscription, WebhookDeliveryQuery,
+};
+use crate::payments::PaymentProvider;
+use crate::state::AppState;
+use crate::store;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+
+/// Lists the tutor's courses in `?status=`, every status when it is missing or `all`,
+/// translated into the requested locale where possible.
+pub async fn get_courses_for_review(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+    query: web::Query<CourseListQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id,) = params.into_inner();
+    let CourseListQuery { status, lang } = query.into_inner();
+    let locales = locale::requested_locales(lang.as_deref(), &req)?;
+    let status = match status.as_deref() {
+        None | Some("all") => None,
+        Some(status) => Some(CourseStatus::parse(status).ok_or_else(|| {
+            EzyTutorError::InvalidInput(format!("Unknown course status {:?}", status))
+        })?),
+    };
+    let pg_pool = &app_state.pg_pool;
+    let courses = store::get_courses_for_tutor(pg_pool, tutor_id, status).await?;
+    let courses = localized(pg_pool, courses, &locales).await?;
+    Ok(vary_by_language(HttpResponse::Ok().json(courses)))
+}
+
+/// The course whatever its status, for reviewing drafts and submitted courses.
+pub async fn get_course_for_review(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    store::get_course_details(&app_state.pg_pool, tutor_id, course_id, None)
+        .await
+        .map(|details| HttpResponse::Ok().json(details))
+}
+
+/// The course's full revision history, whatever its status.
+pub async fn get_course_revisions_for_review(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    store::get_course_revisions(&app_state.pg_pool, tutor_id, course_id, None)
+        .await
+        .map(|revisions| HttpResponse::Ok().json(revisions))
+}
+
+pub async fn get_audit_log(
+    app_state: web::Data<AppState>,
+    query: web::Query<AuditQuery>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::get_audit_entries(&app_state.pg_pool, query.into_inner())
+        .await
+        .map(|entries| HttpResponse::Ok().json(entries))
+}
+
+pub async fn post_new_webhook_subscription(
+    app_state: web::Data<AppState>,
+    new_subscription: web::Json<NewWebhookSubscription>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::post_new_webhook_subscription(&app_state.pg_pool, new_subscription.into_inner())
+        .await
+        .map(|subscription| HttpResponse::Ok().json(subscription))
+}
+
+pub async fn get_webhook_subscriptions(
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::get_webhook_subscriptions(&app_state.pg_pool)
+        .await
+        .map(|subscriptions| HttpResponse::Ok().json(subscriptions))
+}
+
+pub async fn update_webhook_subscription(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+    update: web::Json<UpdateWebhookSubscription>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (subscription_id,) = params.into_inner();
+    store::update_webhook_subscription(&app_state.pg_pool, subscription_id, update.into_inner())
+        .await
+        .map(|subscription| HttpResponse::Ok().json(subscription))
+}
+
+pub async fn delete_webhook_subscription(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (subscription_id,) = params.into_inner();
+    store::delete_webhook_subscription(&app_state.pg_pool, subscription_id)
+        .await
+        .map(|resp| HttpResponse::Ok().json(resp))
+}
+
+pub async fn get_webhook_deliveries(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+    query: web::Query<WebhookDeliveryQuery>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (subscription_id,) = params.into_inner();
+    store::get_webhook_deliveries(&app_state.pg_pool, subscription_id, query.into_inner())
+        .await
+        .map(|deliveries| HttpResponse::Ok().json(deliveries))
+}
+
+pub async fn redeliver_failed_webhooks(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (subscription_id,) = params.into_inner();
+    let redelivered = store::redeliver_failed_webhooks(&app_state.pg_pool, subscription_id).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "redelivered": redelivered })))
+}
+
+pub async fn get_webhook_delivery(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i64,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (delivery_id,) = params.into_inner();
+    store::get_webhook_delivery(&app_state.pg_pool, delivery_id)
+        .await
+        .map(|delivery| HttpResponse::Ok().json(delivery))
+}
+
+pub async fn redeliver_webhook(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i64,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (delivery_id,) = params.into_inner();
+    store::redeliver_webhook(&app_state.pg_pool, delivery_id)
+        .await
+        .map(|delivery| HttpResponse::Ok().json(delivery))
+}
+
+pub async fn get_refunds(
+    app_state: web::Data<AppState>,
+    query: web::Query<RefundListQuery>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::get_refunds(&app_state.pg_pool, query.into_inner())
+        .await
+        .map(|refunds| HttpResponse::Ok().json(refunds))
+}
+
+/// Makes the refund; the optional body's `note` is kept with the decision.
+pub async fn approve_refund(
+    app_state: web::Data<AppState>,
+    provider: web::Data<dyn PaymentProvider>,
+    params: web::Path<(i32,)>,
+    body: web::Bytes,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (refund_id,) = params.into_inner();
+    let decision: RefundDecision = optional_json(&body)?;
+    store::approve_refund(&app_state.pg_pool, provider.get_ref(), refund_id, decision)
+        .await
+        .map(|refund| HttpResponse::Ok().json(refund))
+}
+
+/// Runs a payout batch now rather than waiting for the scheduled one.
+pub async fn run_payouts(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+    earnings: web::Data<EarningsConfig>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::pay_out_earnings(
+        &app_state.pg_pool,
+        &payments.currency,
+        i64::from(earnings.min_payout),
+    )
+    .await
+    .map(|payouts| HttpResponse::Ok().json(payouts))
+}
+
+pub async fn reject_refund(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+    body: web::Bytes,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (refund_id,) = params.into_inner();
+    let decision: RefundDecision = optional_json(&body)?;
+    store::reject_refund(&app_state.pg_pool, refund_id, decision)
+        .await
+        .map(|refund| HttpResponse::Ok().json(refund))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::changes::ChangeFeed;
+    use crate::config::{CacheConfig, ChangesConfig};
+    use crate::models::{ChangeEntity, UpdateCourse};
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use sqlx::postgres::PgPool;
+    use std::env;
+
+    async fn new_app_state() -> web::Data<AppState> {
+        dotenvy::dotenv().ok();
+
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pg_pool = PgPool::connect(&database_url).await.unwrap();
+        web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            pg_pool,
+            cache: ReadCache::new(&CacheConfig::default()),
+            changes: ChangeFeed::new(&ChangesConfig::default()),
+        })
+    }
+
+    #[actix_rt::test]
+    async fn audit_log_records_course_updates() {
+        let app_state = new_app_state().await;
+        let update = UpdateCourse {
+            course_name: None,
+            course_description: None,
+            course_format: None,
+            course_structure: None,
+            course_duration: None,
+            course_price: Some(4200),
+            course_language: None,
+            course_level: None,
+            first_session_at: None,
+            category_ids: None,
+            tags: None,
+        };
+        store::update_course_datails(&app_state.pg_pool, 1, 1, update)
+            .await
+            .unwrap();
+
+        let query = web::Query(AuditQuery {
+            entity: Some(ChangeEntity::Course),
+            entity_id: Some(1),
+            limit: Some(1),
+            ..Default::default()
+        });
+        let resp = get_audit_log(app_state, query).await.unwrap();
+        assert_eq!(StatusCode::OK, resp.status());
+
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let entry = &entries[0];
+        assert_eq!("updated", entry["action"]);
+        assert_eq!("system", entry["actor"]);
+        assert_eq!(4200, entry["after"]["course_price"]);
+    }
+}
@@ -0,0 +1,344 @@
+use crate::errors::EzyTutorError;
+use crate::models::{BundleListQuery, NewBundle, UpdateBundle};
+use crate::state::AppState;
+use crate::store;
+
+use actix_web::{web, HttpResponse};
+
+pub async fn get_bundles(
+    app_state: web::Data<AppState>,
+    query: web::Query<BundleListQuery>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::get_bundles(&app_state.pg_pool, query.into_inner())
+        .await
+        .map(|bundles| HttpResponse::Ok().json(bundles))
+}
+
+pub async fn get_bundle(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (bundle_id,) = params.into_inner();
+    store::get_bundle(&app_state.pg_pool, bundle_id)
+        .await
+        .map(|bundle| HttpResponse::Ok().json(bundle))
+}
+
+pub async fn post_new_bundle(
+    app_state: web::Data<AppState>,
+    new_bundle: web::Json<NewBundle>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::post_new_bundle(&app_state.pg_pool, new_bundle.into_inner())
+        .await
+        .map(|bundle| HttpResponse::Ok().json(bundle))
+}
+
+pub async fn update_bundle(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+    update_bundle: web::Json<UpdateBundle>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (bundle_id,) = params.into_inner();
+    store::update_bundle(&app_state.pg_pool, bundle_id, update_bundle.into_inner())
+        .await
+        .map(|bundle| HttpResponse::Ok().json(bundle))
+}
+
+pub async fn delete_bundle(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (bundle_id,) = params.into_inner();
+    store::delete_bundle(&app_state.pg_pool, bundle_id)
+        .await
+        .map(|resp| HttpResponse::Ok().json(resp))
+}
+
+/// The caller's progress along the bundle's courses.
+pub async fn get_bundle_progress(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (bundle_id,) = params.into_inner();
+    store::get_bundle_progress(&app_state.pg_pool, bundle_id)
+        .await
+        .map(|progress| HttpResponse::Ok().json(progress))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::changes::ChangeFeed;
+    use crate::config::{
+        CacheConfig, ChangesConfig, EarningsConfig, EnrollmentConfig, PaymentsConfig,
+    };
+    use crate::handlers::{add_bundle_to_cart, checkout, payment_webhook};
+    use crate::middleware::actor;
+    use crate::payments::{FakePaymentProvider, PaymentEvent, PaymentOutcome, PaymentProvider};
+    use crate::webhooks::SIGNATURE_HEADER;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+    use sqlx::postgres::PgPool;
+    use std::env;
+    use std::sync::Arc;
+    use tutor_web_common::rate_limit::token_fingerprint;
+    use uuid::Uuid;
+
+    const WEBHOOK_SECRET: &str = "payments-0123456789";
+
+    async fn new_app_state() -> web::Data<AppState> {
+        dotenvy::dotenv().ok();
+
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pg_pool = PgPool::connect(&database_url).await.unwrap();
+        web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            pg_pool,
+            cache: ReadCache::new(&CacheConfig::default()),
+            changes: ChangeFeed::new(&ChangesConfig::default()),
+        })
+    }
+
+    async fn new_tutor(pg_pool: &PgPool) -> i32 {
+        sqlx::query_scalar!(
+            "INSERT INTO ezy_tutor_c6 (tutor_name, tutor_pic_url, tutor_profile)
+            VALUES ('Bundle test tutor', 'http://s3.amazon.aws.com/pic1', 'Teaches paths')
+            RETURNING tutor_id"
+        )
+        .fetch_one(pg_pool)
+        .await
+        .unwrap()
+    }
+
+    async fn new_course(pg_pool: &PgPool, tutor_id: i32, price: i32) -> i32 {
+        sqlx::query_scalar!(
+            "INSERT INTO ezy_course_c6 (tutor_id, course_name, course_price, course_status)
+            VALUES ($1, 'Bundle test course', $2, 'published')
+            RETURNING course_id",
+            tutor_id,
+            price,
+        )
+        .fetch_one(pg_pool)
+        .await
+        .unwrap()
+    }
+
+    async fn delete_tutors(pg_pool: &PgPool, tutor_ids: &[i32]) {
+        sqlx::query!(
+            "DELETE FROM ezy_tutor_c6 WHERE tutor_id = ANY($1)",
+            tutor_ids
+        )
+        .execute(pg_pool)
+        .await
+        .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn bundle_crud_and_path_progress() {
+        let app_state = new_app_state().await;
+        let pg_pool = app_state.pg_pool.clone();
+        let tutor_id = new_tutor(&pg_pool).await;
+        let first = new_course(&pg_pool, tutor_id, 1000).await;
+        let second = new_course(&pg_pool, tutor_id, 2000).await;
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(actor))
+                .app_data(app_state)
+                .route("/bundles", web::get().to(get_bundles))
+                .route("/bundles", web::post().to(post_new_bundle))
+                .route("/bundles/{bundle_id}", web::get().to(get_bundle))
+                .route("/bundles/{bundle_id}", web::put().to(update_bundle))
+                .route("/bundles/{bundle_id}", web::delete().to(delete_bundle))
+                .route(
+                    "/bundles/{bundle_id}/progress",
+                    web::get().to(get_bundle_progress),
+                ),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/bundles")
+            .set_json(json!({
+                "tutor_id": tutor_id,
+                "bundle_name": "  Finance path ",
+                "bundle_price": 2500,
+                "course_ids": [second, first],
+            }))
+            .to_request();
+        let bundle: Value = test::call_and_read_body_json(&app, req).await;
+        let bundle_id = bundle["bundle_id"].as_i64().unwrap();
+        assert_eq!("Finance path", bundle["bundle_name"]);
+        assert_eq!(second, bundle["courses"][0]["course_id"]);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/bundles/{}", bundle_id))
+            .set_json(json!({ "bundle_price": 2700, "course_ids": [first, second] }))
+            .to_request();
+        let bundle: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(2700, bundle["bundle_price"]);
+        assert_eq!(first, bundle["courses"][0]["course_id"]);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/bundles/{}", bundle_id))
+            .set_json(json!({ "course_ids": [first, first] }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+
+        // Bundles hold only their tutor's own courses and are never free.
+        let other_tutor = new_tutor(&pg_pool).await;
+        let other_course = new_course(&pg_pool, other_tutor, 5000).await;
+        for update in [
+            json!({ "course_ids": [first, other_course] }),
+            json!({ "bundle_price": 0 }),
+        ] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/bundles/{}", bundle_id))
+                .set_json(update)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+        }
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/bundles?tutor_id={}", tutor_id))
+            .to_request();
+        let bundles: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(1, bundles.as_array().unwrap().len());
+
+        // The student finished the first course of the path and started the second.
+        let token = format!("student-{}", Uuid::new_v4());
+        let student = format!("token:{}", token_fingerprint(&token));
+        sqlx::query!(
+            "INSERT INTO ezy_enrollment (course_id, student, completed_at)
+            VALUES ($1, $3, now()), ($2, $3, NULL)",
+            first,
+            second,
+            student,
+        )
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+        let req = test::TestRequest::get()
+            .uri(&format!("/bundles/{}/progress", bundle_id))
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let progress: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(1, progress["completed_courses"]);
+        assert_eq!(2, progress["total_courses"]);
+        assert_eq!(second, progress["next_course_id"]);
+        assert!(progress["courses"][1]["enrolled_at"].is_string());
+
+        // Somebody else has not started the path.
+        let req = test::TestRequest::get()
+            .uri(&format!("/bundles/{}/progress", bundle_id))
+            .insert_header(("x-api-key", "another-student-token"))
+            .to_request();
+        let progress: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(0, progress["completed_courses"]);
+        assert_eq!(first, progress["next_course_id"]);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/bundles/{}", bundle_id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        let req = test::TestRequest::get()
+            .uri(&format!("/bundles/{}", bundle_id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::NOT_FOUND, resp.status());
+
+        delete_tutors(&pg_pool, &[tutor_id, other_tutor]).await;
+    }
+
+    #[actix_rt::test]
+    async fn bundle_revenue_goes_to_its_tutor() {
+        let app_state = new_app_state().await;
+        let pg_pool = app_state.pg_pool.clone();
+        let seller = new_tutor(&pg_pool).await;
+        let first = new_course(&pg_pool, seller, 3000).await;
+        let second = new_course(&pg_pool, seller, 1000).await;
+        let bundle = store::post_new_bundle(
+            &pg_pool,
+            NewBundle {
+                tutor_id: seller,
+                bundle_name: "Finance path".to_string(),
+                bundle_description: None,
+                bundle_price: 2001,
+                course_ids: vec![first, second],
+            },
+        )
+        .await
+        .unwrap();
+
+        let provider = Arc::new(FakePaymentProvider::new(WEBHOOK_SECRET));
+        let payments = PaymentsConfig {
+            webhook_secret: WEBHOOK_SECRET.to_string(),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(actor))
+                .app_data(app_state)
+                .app_data(web::Data::new(payments.clone()))
+                .app_data(web::Data::new(EnrollmentConfig::default()))
+                .app_data(web::Data::new(EarningsConfig::default()))
+                .app_data(web::Data::from(provider.clone() as Arc<dyn PaymentProvider>))
+                .route(
+                    "/cart/bundles/{bundle_id}",
+                    web::put().to(add_bundle_to_cart),
+                )
+                .route("/cart/checkout", web::post().to(checkout))
+                .route("/payments/webhook", web::post().to(payment_webhook)),
+        )
+        .await;
+
+        let token = format!("student-{}", Uuid::new_v4());
+        let req = test::TestRequest::put()
+            .uri(&format!("/cart/bundles/{}", bundle.bundle.bundle_id))
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        let req = test::TestRequest::post()
+            .uri("/cart/checkout")
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(2001, order["total_amount"]);
+
+        let (signature, body) = provider.notification(&PaymentEvent {
+            payment_id: order["payment_id"].as_str().unwrap().to_string(),
+            outcome: PaymentOutcome::Succeeded,
+        });
+        let req = test::TestRequest::post()
+            .uri("/payments/webhook")
+            .insert_header((SIGNATURE_HEADER, signature))
+            .set_payload(body)
+            .to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!("paid", order["order_status"]);
+        let item_fee = sqlx::query_scalar!(
+            "SELECT platform_fee FROM ezy_order_item WHERE order_id = $1",
+            order["order_id"].as_i64().unwrap() as i32,
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        assert_eq!(200, item_fee);
+
+        let earned = store::get_earnings_balance(&pg_pool, seller, &payments.currency)
+            .await
+            .unwrap();
+        assert_eq!(
+            (2001, 200, 1801),
+            (earned.gross_sales, earned.platform_fees, earned.available)
+        );
+
+        delete_tutors(&pg_pool, &[seller]).await;
+    }
+}
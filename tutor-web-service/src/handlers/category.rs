@@ -0,0 +1,56 @@
+use crate::errors::EzyTutorError;
+use crate::models::{NewCategory, UpdateCategory};
+use crate::state::AppState;
+use crate::store;
+
+use actix_web::{web, HttpResponse};
+
+pub async fn get_categories(app_state: web::Data<AppState>) -> Result<HttpResponse, EzyTutorError> {
+    store::get_categories(&app_state.pg_pool)
+        .await
+        .map(|categories| HttpResponse::Ok().json(categories))
+}
+
+pub async fn get_category(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (category_id,) = params.into_inner();
+    store::get_category(&app_state.pg_pool, category_id)
+        .await
+        .map(|category| HttpResponse::Ok().json(category))
+}
+
+pub async fn post_new_category(
+    app_state: web::Data<AppState>,
+    new_category: web::Json<NewCategory>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::post_new_category(&app_state.pg_pool, new_category.into_inner())
+        .await
+        .map(|category| HttpResponse::Ok().json(category))
+}
+
+pub async fn update_category(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+    update_category: web::Json<UpdateCategory>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (category_id,) = params.into_inner();
+    store::update_category(
+        &app_state.pg_pool,
+        category_id,
+        update_category.into_inner(),
+    )
+    .await
+    .map(|category| HttpResponse::Ok().json(category))
+}
+
+pub async fn delete_category(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (category_id,) = params.into_inner();
+    store::delete_category(&app_state.pg_pool, category_id)
+        .await
+        .map(|resp| HttpResponse::Ok().json(resp))
+}
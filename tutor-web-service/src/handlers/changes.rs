@@ -0,0 +1,88 @@
+use crate::changes::FeedItem;
+use crate::errors::EzyTutorError;
+use crate::models::{ChangeEvent, ChangeStreamQuery};
+use crate::state::AppState;
+
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::web::Bytes;
+use actix_web::{mime, web, HttpRequest, HttpResponse};
+
+use futures_util::stream::{self, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+
+const LAST_EVENT_ID: &str = "last-event-id";
+/// How long browsers wait before reconnecting a dropped stream.
+const RETRY_MS: u32 = 3000;
+const RESET_EVENT: &str = "event: reset\ndata: {}\n\n";
+const KEEP_ALIVE: &str = ": keep-alive\n\n";
+
+/// Streams tutor and course changes as Server-Sent Events, optionally only those of one
+/// tutor. Clients resuming with `Last-Event-ID` first receive the changes they missed, or
+/// a `reset` event when those are no longer known and their state has to be reloaded.
+pub async fn stream_changes(
+    app_state: web::Data<AppState>,
+    query: web::Query<ChangeStreamQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, EzyTutorError> {
+    let ChangeStreamQuery {
+        tutor_id,
+        last_event_id,
+    } = query.into_inner();
+    let last_event_id = match req.headers().get(LAST_EVENT_ID) {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|id| id.trim().parse().ok())
+                .ok_or_else(|| {
+                    EzyTutorError::InvalidInput("Last-Event-ID must be a change id".to_string())
+                })?,
+        ),
+        None => last_event_id,
+    };
+    let wanted = move |event: &ChangeEvent| tutor_id.is_none_or(|id| id == event.tutor_id);
+
+    let subscription = app_state.changes.subscribe(last_event_id);
+    let mut head = format!("retry: {}\n\n", RETRY_MS);
+    match subscription.replay {
+        Some(replay) => replay
+            .iter()
+            .filter(|event| wanted(event))
+            .for_each(|event| head.push_str(&sse_event(event))),
+        None => head.push_str(RESET_EVENT),
+    }
+
+    let heartbeat = app_state.changes.heartbeat();
+    let live = stream::unfold(subscription.receiver, move |mut receiver| async move {
+        loop {
+            let chunk = match actix_web::rt::time::timeout(heartbeat, receiver.recv()).await {
+                Err(_) => KEEP_ALIVE.to_string(),
+                Ok(Ok(FeedItem::Change(event))) if wanted(&event) => sse_event(&event),
+                Ok(Ok(FeedItem::Change(_))) => continue,
+                Ok(Ok(FeedItem::Reset)) | Ok(Err(RecvError::Lagged(_))) => RESET_EVENT.to_string(),
+                Ok(Err(RecvError::Closed)) => return None,
+            };
+            return Some((Ok::<_, actix_web::Error>(Bytes::from(chunk)), receiver));
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentType(mime::TEXT_EVENT_STREAM))
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        // Keeps nginx from buffering the stream.
+        .insert_header(("x-accel-buffering", "no"))
+        .streaming(stream::once(async move { Ok(Bytes::from(head)) }).chain(live)))
+}
+
+fn sse_event(event: &ChangeEvent) -> String {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    match event.change_id {
+        Some(id) => format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            id,
+            event.event_type(),
+            data
+        ),
+        None => format!("event: {}\ndata: {}\n\n", event.event_type(), data),
+    }
+}
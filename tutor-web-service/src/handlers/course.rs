@@ -1,47 +1,143 @@
+use super::optional_json;
 use crate::errors::EzyTutorError;
-use crate::models::{NewCourse, UpdateCourse};
+use crate::locale;
+use crate::models::{
+    CatalogQuery, CourseDetails, CourseStatus, CourseTransition, LocaleQuery, NewCourse,
+    PublishCourse, PutCourseTranslation, SimilarCoursesQuery, UpdateCourse,
+};
 use crate::state::AppState;
 use crate::store;
 
-use actix_web::{web, HttpResponse};
+use actix_web::http::header::{HeaderValue, CONTENT_LANGUAGE, VARY};
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::postgres::PgPool;
 
 pub async fn post_new_course(
     app_state: web::Data<AppState>,
     new_course: web::Json<NewCourse>,
 ) -> Result<HttpResponse, EzyTutorError> {
-    store::post_new_course(&app_state.pg_pool, new_course.into_inner())
-        .await
-        .map(|course| HttpResponse::Ok().json(course))
+    let course = store::post_new_course(&app_state.pg_pool, new_course.into_inner()).await?;
+    app_state.cache.invalidate_courses(course.course.tutor_id);
+    Ok(HttpResponse::Ok().json(course))
 }
 
+/// Lists the tutor's published courses, translated into the requested locale where
+/// possible. Operators list courses in any status through `/admin/courses`.
 pub async fn get_courses_for_tutor(
     app_state: web::Data<AppState>,
     params: web::Path<(i32,)>,
+    query: web::Query<LocaleQuery>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, EzyTutorError> {
     let (tutor_id,) = params.into_inner();
-    store::get_courses_for_tutor(&app_state.pg_pool, tutor_id)
+    let locales = locale::requested_locales(query.lang.as_deref(), &req)?;
+    let pg_pool = &app_state.pg_pool;
+    let key = (tutor_id, locales.clone());
+    app_state
+        .cache
+        .courses_by_tutor
+        .get_or_load(key, || async {
+            let courses =
+                store::get_courses_for_tutor(pg_pool, tutor_id, Some(CourseStatus::Published))
+                    .await?;
+            localized(pg_pool, courses, &locales).await
+        })
         .await
-        .map(|courses| HttpResponse::Ok().json(courses))
+        .map(|courses| vary_by_language(courses.respond(&req)))
 }
 
+/// The published course, translated into the requested locale where possible. Operators
+/// see courses in any status through `/admin/courses`.
 pub async fn get_course_details(
     app_state: web::Data<AppState>,
     params: web::Path<(i32, i32)>,
+    query: web::Query<LocaleQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    let locales = locale::requested_locales(query.lang.as_deref(), &req)?;
+    let pg_pool = &app_state.pg_pool;
+    let mut details =
+        store::get_course_details(pg_pool, tutor_id, course_id, Some(CourseStatus::Published))
+            .await?;
+    let translations = store::get_translations_for_courses(
+        pg_pool,
+        &[course_id],
+        &locale::candidate_locales(&locales),
+    )
+    .await?;
+    let translations = translations
+        .iter()
+        .map(|translation| (translation.locale.as_str(), translation))
+        .collect();
+
+    let mut builder = HttpResponse::Ok();
+    builder.insert_header((VARY, "accept-language"));
+    if let Some(language) = locale::localize(&mut details.course, &translations, &locales) {
+        builder.insert_header((CONTENT_LANGUAGE, language));
+    }
+    Ok(builder.json(details))
+}
+
+pub async fn get_similar_courses(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+    query: web::Query<SimilarCoursesQuery>,
 ) -> Result<HttpResponse, EzyTutorError> {
     let (tutor_id, course_id) = params.into_inner();
-    store::get_course_details(&app_state.pg_pool, tutor_id, course_id)
+    store::get_similar_courses(&app_state.pg_pool, tutor_id, course_id, query.into_inner())
         .await
-        .map(|course| HttpResponse::Ok().json(course))
+        .map(|similar| HttpResponse::Ok().json(similar))
 }
 
-pub async fn delete_course(
+pub async fn get_course_prerequisites(
     app_state: web::Data<AppState>,
     params: web::Path<(i32, i32)>,
 ) -> Result<HttpResponse, EzyTutorError> {
     let (tutor_id, course_id) = params.into_inner();
-    store::delete_course(&app_state.pg_pool, tutor_id, course_id)
+    store::get_prerequisite_chain(&app_state.pg_pool, tutor_id, course_id)
+        .await
+        .map(|chain| HttpResponse::Ok().json(chain))
+}
+
+pub async fn put_course_prerequisite(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id, prerequisite_id) = params.into_inner();
+    store::add_course_prerequisite(&app_state.pg_pool, tutor_id, course_id, prerequisite_id)
+        .await
+        .map(|chain| HttpResponse::Ok().json(chain))
+}
+
+pub async fn delete_course_prerequisite(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id, prerequisite_id) = params.into_inner();
+    store::delete_course_prerequisite(&app_state.pg_pool, tutor_id, course_id, prerequisite_id)
         .await
-        .map(|resp| HttpResponse::Ok().json(resp))
+        .map(|chain| HttpResponse::Ok().json(chain))
+}
+
+/// Browses published courses by category, including its subcategories, and tag.
+pub async fn get_catalog(
+    app_state: web::Data<AppState>,
+    query: web::Query<CatalogQuery>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::get_catalog(&app_state.pg_pool, query.into_inner())
+        .await
+        .map(|catalog| HttpResponse::Ok().json(catalog))
+}
+
+pub async fn delete_course(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    let resp = store::delete_course(&app_state.pg_pool, tutor_id, course_id).await?;
+    app_state.cache.invalidate_courses(tutor_id);
+    Ok(HttpResponse::Ok().json(resp))
 }
 
 pub async fn update_course_details(
@@ -51,24 +147,193 @@ pub async fn update_course_details(
 ) -> Result<HttpResponse, EzyTutorError> {
     let (tutor_id, course_id) = params.into_inner();
 
-    store::update_course_datails(
+    let course = store::update_course_datails(
         &app_state.pg_pool,
         tutor_id,
         course_id,
         update_course.into_inner(),
     )
+    .await?;
+    app_state.cache.invalidate_courses(tutor_id);
+    Ok(HttpResponse::Ok().json(course))
+}
+
+/// The published course's revisions. Operators see those of courses in any status through
+/// `/admin/courses`.
+pub async fn get_course_revisions(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    let published = Some(CourseStatus::Published);
+    store::get_course_revisions(&app_state.pg_pool, tutor_id, course_id, published)
+        .await
+        .map(|revisions| HttpResponse::Ok().json(revisions))
+}
+
+pub async fn restore_course_revision(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id, revision) = params.into_inner();
+    let course =
+        store::restore_course_revision(&app_state.pg_pool, tutor_id, course_id, revision).await?;
+    app_state.cache.invalidate_courses(tutor_id);
+    Ok(HttpResponse::Ok().json(course))
+}
+
+pub async fn submit_course(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    transition_course(app_state, params, CourseTransition::Submit, None).await
+}
+
+pub async fn reject_course(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    transition_course(app_state, params, CourseTransition::Reject, None).await
+}
+
+/// Publishes the course now, or schedules it when the optional body's `publish_at` is in
+/// the future.
+pub async fn publish_course(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+    body: web::Bytes,
+) -> Result<HttpResponse, EzyTutorError> {
+    let publish: PublishCourse = optional_json(&body)?;
+    transition_course(
+        app_state,
+        params,
+        CourseTransition::Publish,
+        publish.publish_at,
+    )
     .await
-    .map(|course| HttpResponse::Ok().json(course))
+}
+
+pub async fn archive_course(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    transition_course(app_state, params, CourseTransition::Archive, None).await
+}
+
+pub async fn reopen_course(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    transition_course(app_state, params, CourseTransition::Reopen, None).await
+}
+
+async fn transition_course(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+    transition: CourseTransition,
+    publish_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    let course = store::transition_course(
+        &app_state.pg_pool,
+        tutor_id,
+        course_id,
+        transition,
+        publish_at,
+    )
+    .await?;
+    app_state.cache.invalidate_courses(tutor_id);
+    Ok(HttpResponse::Ok().json(course))
+}
+
+pub async fn get_course_translations(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    store::get_course_translations(&app_state.pg_pool, tutor_id, course_id)
+        .await
+        .map(|translations| HttpResponse::Ok().json(translations))
+}
+
+pub async fn put_course_translation(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32, String)>,
+    translation: web::Json<PutCourseTranslation>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id, locale) = params.into_inner();
+    let locale = locale::normalize_locale(&locale)
+        .ok_or_else(|| EzyTutorError::InvalidInput(format!("Invalid language {:?}", locale)))?;
+    let translation = store::put_course_translation(
+        &app_state.pg_pool,
+        tutor_id,
+        course_id,
+        &locale,
+        translation.into_inner(),
+    )
+    .await?;
+    app_state.cache.invalidate_courses(tutor_id);
+    Ok(HttpResponse::Ok().json(translation))
+}
+
+pub async fn delete_course_translation(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32, String)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id, locale) = params.into_inner();
+    let locale = locale::normalize_locale(&locale)
+        .ok_or_else(|| EzyTutorError::NotFound("Course translation not found".to_string()))?;
+    let resp =
+        store::delete_course_translation(&app_state.pg_pool, tutor_id, course_id, &locale).await?;
+    app_state.cache.invalidate_courses(tutor_id);
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+/// Translates `courses` into the best of `locales` each has a translation for.
+pub(super) async fn localized(
+    pg_pool: &PgPool,
+    mut courses: Vec<CourseDetails>,
+    locales: &[String],
+) -> Result<Vec<CourseDetails>, EzyTutorError> {
+    let course_ids: Vec<i32> = courses
+        .iter()
+        .map(|details| details.course.course_id)
+        .collect();
+    let translations = store::get_translations_for_courses(
+        pg_pool,
+        &course_ids,
+        &locale::candidate_locales(locales),
+    )
+    .await?;
+    locale::localize_all(&mut courses, &translations, locales);
+    Ok(courses)
+}
+
+pub(super) fn vary_by_language(mut resp: HttpResponse) -> HttpResponse {
+    resp.headers_mut()
+        .insert(VARY, HeaderValue::from_static("accept-language"));
+    resp
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cache::ReadCache;
+    use crate::changes::ChangeFeed;
+    use crate::config::{CacheConfig, ChangesConfig};
+    use crate::handlers::{
+        delete_course_sale, get_course_revisions_for_review, get_courses_for_review,
+        post_course_sale,
+    };
+    use crate::models::CourseListQuery;
+    use actix_web::body::to_bytes;
     use actix_web::http::StatusCode;
     use actix_web::ResponseError;
+    use actix_web::{test, App};
+    use chrono::{Duration, Utc};
+    use serde_json::{json, Value};
     use sqlx::postgres::PgPool;
     use std::env;
-    use std::sync::Mutex;
 
     async fn new_app_state() -> web::Data<AppState> {
         dotenvy::dotenv().ok();
@@ -77,8 +342,9 @@ mod tests {
         let pg_pool = PgPool::connect(&database_url).await.unwrap();
         web::Data::new(AppState {
             health_check_response: "".to_string(),
-            visit_count: Mutex::new(0),
             pg_pool,
+            cache: ReadCache::new(&CacheConfig::default()),
+            changes: ChangeFeed::new(&ChangesConfig::default()),
         })
     }
 
@@ -87,7 +353,11 @@ mod tests {
         let app_state = new_app_state().await;
 
         let params: web::Path<(i32,)> = web::Path::from((1,));
-        let resp = get_courses_for_tutor(app_state, params).await.unwrap();
+        let query = web::Query(LocaleQuery::default());
+        let req = test::TestRequest::default().to_http_request();
+        let resp = get_courses_for_tutor(app_state, params, query, req)
+            .await
+            .unwrap();
         assert_eq!(StatusCode::OK, resp.status());
     }
 
@@ -96,7 +366,11 @@ mod tests {
         let app_state = new_app_state().await;
 
         let params: web::Path<(i32, i32)> = web::Path::from((1, 2));
-        let resp = get_course_details(app_state, params).await.unwrap();
+        let query = web::Query(LocaleQuery::default());
+        let req = test::TestRequest::default().to_http_request();
+        let resp = get_course_details(app_state, params, query, req)
+            .await
+            .unwrap();
         assert_eq!(StatusCode::OK, resp.status());
     }
 
@@ -105,8 +379,108 @@ mod tests {
         let app_state = new_app_state().await;
 
         let params: web::Path<(i32, i32)> = web::Path::from((1, 21));
-        let resp = get_course_details(app_state, params).await.unwrap_err();
+        let query = web::Query(LocaleQuery::default());
+        let req = test::TestRequest::default().to_http_request();
+        let resp = get_course_details(app_state, params, query, req)
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::NOT_FOUND, resp.status_code());
+    }
+
+    #[actix_rt::test]
+    async fn unpublished_courses_are_not_public() {
+        let app_state = new_app_state().await;
+        let draft = store::post_new_course(
+            &app_state.pg_pool,
+            NewCourse {
+                tutor_id: 1,
+                course_name: "Draft course".to_string(),
+                course_description: None,
+                course_format: None,
+                course_level: None,
+                course_price: None,
+                course_duration: None,
+                course_language: None,
+                course_structure: None,
+                first_session_at: None,
+                category_ids: vec![],
+                tags: vec![],
+            },
+        )
+        .await
+        .unwrap();
+        let course_id = draft.course.course_id;
+        assert_eq!("draft", draft.course.course_status);
+
+        let params: web::Path<(i32, i32)> = web::Path::from((1, course_id));
+        let query = web::Query(LocaleQuery::default());
+        let req = test::TestRequest::default().to_http_request();
+        let resp = get_course_details(app_state.clone(), params, query, req)
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::NOT_FOUND, resp.status_code());
+        let details = store::get_course_details(&app_state.pg_pool, 1, course_id, None)
+            .await
+            .unwrap();
+        assert_eq!("Draft course", details.course.course_name);
+        let listed = |resp: HttpResponse| async move {
+            let body = to_bytes(resp.into_body()).await.unwrap();
+            let courses: Value = serde_json::from_slice(&body).unwrap();
+            courses
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|details| details["course_id"] == course_id)
+        };
+        let params: web::Path<(i32,)> = web::Path::from((1,));
+        let query = web::Query(LocaleQuery::default());
+        let req = test::TestRequest::default().to_http_request();
+        let resp = get_courses_for_tutor(app_state.clone(), params, query, req)
+            .await
+            .unwrap();
+        assert!(!listed(resp).await);
+        let params: web::Path<(i32,)> = web::Path::from((1,));
+        let query = web::Query(CourseListQuery {
+            status: Some("draft".to_string()),
+            lang: None,
+        });
+        let req = test::TestRequest::default().to_http_request();
+        let resp = get_courses_for_review(app_state.clone(), params, query, req)
+            .await
+            .unwrap();
+        assert!(listed(resp).await);
+        let params: web::Path<(i32, i32)> = web::Path::from((1, course_id));
+        let resp = get_course_revisions(app_state.clone(), params)
+            .await
+            .unwrap_err();
         assert_eq!(StatusCode::NOT_FOUND, resp.status_code());
+        let params: web::Path<(i32, i32)> = web::Path::from((1, course_id));
+        let resp = get_course_revisions_for_review(app_state.clone(), params)
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, resp.status());
+
+        // Webhook subscribers hear of the course once it is published, not before.
+        let events = || {
+            sqlx::query_scalar!(
+                r#"SELECT count(*) AS "count!" FROM ezy_webhook_outbox
+                WHERE event_type LIKE 'course.%' and (payload->>'course_id')::int = $1"#,
+                course_id,
+            )
+            .fetch_one(&app_state.pg_pool)
+        };
+        for transition in [CourseTransition::Submit, CourseTransition::Publish] {
+            assert_eq!(0, events().await.unwrap());
+            store::transition_course(&app_state.pg_pool, 1, course_id, transition, None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(1, events().await.unwrap());
+
+        store::delete_course(&app_state.pg_pool, 1, course_id)
+            .await
+            .unwrap();
+        assert_eq!(2, events().await.unwrap());
     }
 
     #[actix_rt::test]
@@ -124,6 +498,9 @@ mod tests {
             course_duration: None,
             course_language: Some("English".to_string()),
             course_structure: None,
+            first_session_at: None,
+            category_ids: vec![],
+            tags: vec![],
         });
         let resp = post_new_course(app_state, new_course).await.unwrap();
         assert_eq!(StatusCode::OK, resp.status());
@@ -142,6 +519,9 @@ mod tests {
             course_duration: None,
             course_language: Some("German".to_string()),
             course_structure: None,
+            first_session_at: None,
+            category_ids: None,
+            tags: None,
         });
         let params: web::Path<(i32, i32)> = web::Path::from((1, 2));
         let resp = update_course_details(app_state, params, update_course)
@@ -167,4 +547,151 @@ mod tests {
         let resp = delete_course(app_state, params).await.unwrap();
         assert_eq!(StatusCode::OK, resp.status());
     }
+
+    #[actix_rt::test]
+    async fn submit_published_course_conflicts() {
+        let app_state = new_app_state().await;
+
+        let params: web::Path<(i32, i32)> = web::Path::from((1, 1));
+        let resp = submit_course(app_state, params).await.unwrap_err();
+        assert_eq!(StatusCode::CONFLICT, resp.status_code());
+    }
+
+    #[actix_rt::test]
+    async fn prerequisite_cycles_conflict() {
+        let app_state = new_app_state().await;
+        let pg_pool = &app_state.pg_pool;
+
+        let chain = store::add_course_prerequisite(pg_pool, 1, 2, 1)
+            .await
+            .unwrap();
+        assert_eq!(1, chain[0].course.course_id);
+        let err = store::add_course_prerequisite(pg_pool, 1, 1, 2)
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::CONFLICT, err.status_code());
+        store::delete_course_prerequisite(pg_pool, 1, 2, 1)
+            .await
+            .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn catalog_includes_subcategories() {
+        let app_state = new_app_state().await;
+
+        let query = CatalogQuery {
+            category_id: Some(1),
+            ..Default::default()
+        };
+        let catalog = store::get_catalog(&app_state.pg_pool, query).await.unwrap();
+        let course = catalog
+            .courses
+            .iter()
+            .find(|details| details.course.course_id == 1)
+            .expect("course 1 is filed under a subcategory");
+        assert_eq!(vec![2], course.category_ids);
+        let finance = catalog
+            .facets
+            .categories
+            .iter()
+            .find(|facet| facet.category_id == 1)
+            .unwrap();
+        assert_eq!(catalog.total, finance.count);
+        // Every course falls in exactly one price bucket.
+        let priced: i64 = catalog
+            .facets
+            .prices
+            .iter()
+            .map(|bucket| bucket.count)
+            .sum();
+        assert_eq!(catalog.total, priced);
+    }
+
+    #[actix_rt::test]
+    async fn tutor_listing_shows_current_sale_price() {
+        let app_state = new_app_state().await;
+        let pg_pool = app_state.pg_pool.clone();
+        let tutor_id = sqlx::query_scalar!(
+            "INSERT INTO ezy_tutor_c6 (tutor_name, tutor_pic_url, tutor_profile)
+            VALUES ('Sale test tutor', 'http://s3.amazon.aws.com/pic1', 'Runs sales')
+            RETURNING tutor_id"
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        let course_id = sqlx::query_scalar!(
+            "INSERT INTO ezy_course_c6 (tutor_id, course_name, course_price, course_status)
+            VALUES ($1, 'Sale test course', 2000, 'published')
+            RETURNING course_id",
+            tutor_id,
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(app_state)
+                .route("/courses/{tutor_id}", web::get().to(get_courses_for_tutor))
+                .route(
+                    "/courses/{tutor_id}/{course_id}/sales",
+                    web::post().to(post_course_sale),
+                )
+                .route(
+                    "/courses/{tutor_id}/{course_id}/sales/{sale_id}",
+                    web::delete().to(delete_course_sale),
+                ),
+        )
+        .await;
+        let list = || {
+            test::TestRequest::get()
+                .uri(&format!("/courses/{}", tutor_id))
+                .to_request()
+        };
+
+        // Caches the listing before the sale starts.
+        let courses: Value = test::call_and_read_body_json(&app, list()).await;
+        assert_eq!(2000, courses[0]["pricing"]["final_price"]);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/courses/{}/{}/sales", tutor_id, course_id))
+            .set_json(json!({ "sale_price": 1500, "ends_at": Utc::now() + Duration::days(1) }))
+            .to_request();
+        let sale: Value = test::call_and_read_body_json(&app, req).await;
+        let courses: Value = test::call_and_read_body_json(&app, list()).await;
+        assert_eq!(
+            json!([2000, 500, 1500]),
+            json!([
+                courses[0]["pricing"]["original_price"],
+                courses[0]["pricing"]["discount"],
+                courses[0]["pricing"]["final_price"],
+            ])
+        );
+
+        let req = test::TestRequest::delete()
+            .uri(&format!(
+                "/courses/{}/{}/sales/{}",
+                tutor_id, course_id, sale["sale_id"]
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        let courses: Value = test::call_and_read_body_json(&app, list()).await;
+        assert_eq!(2000, courses[0]["pricing"]["final_price"]);
+
+        // Both changes of the price are audited as updates of the course.
+        let audited = sqlx::query_scalar!(
+            r#"SELECT count(*) AS "count!" FROM ezy_audit_log
+            WHERE entity = 'course' and entity_id = $1 and action = 'updated'"#,
+            course_id,
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        assert_eq!(2, audited);
+
+        sqlx::query!("DELETE FROM ezy_tutor_c6 WHERE tutor_id = $1", tutor_id)
+            .execute(&pg_pool)
+            .await
+            .unwrap();
+    }
 }
@@ -0,0 +1,280 @@
+use crate::config::PaymentsConfig;
+use crate::errors::EzyTutorError;
+use crate::models::StatementQuery;
+use crate::state::AppState;
+use crate::store;
+
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{mime, web, HttpResponse};
+
+pub async fn get_earnings_balance(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = params.0;
+    store::get_earnings_balance(&app_state.pg_pool, tutor_id, &payments.currency)
+        .await
+        .map(|balance| HttpResponse::Ok().json(balance))
+}
+
+pub async fn get_earnings_statement(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+    params: web::Path<(i32,)>,
+    query: web::Query<StatementQuery>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = params.0;
+    store::get_earnings_statement(
+        &app_state.pg_pool,
+        tutor_id,
+        &payments.currency,
+        query.into_inner(),
+    )
+    .await
+    .map(|statement| HttpResponse::Ok().json(statement))
+}
+
+/// The statement as a CSV download, for spreadsheets and accounting software.
+pub async fn get_earnings_statement_csv(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+    params: web::Path<(i32,)>,
+    query: web::Query<StatementQuery>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = params.0;
+    let statement = store::get_earnings_statement(
+        &app_state.pg_pool,
+        tutor_id,
+        &payments.currency,
+        query.into_inner(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentType(mime::TEXT_CSV_UTF_8))
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "earnings-{}.csv",
+                tutor_id
+            ))],
+        })
+        .body(statement.to_csv()))
+}
+
+pub async fn get_payouts(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = params.0;
+    store::get_payouts(&app_state.pg_pool, tutor_id)
+        .await
+        .map(|payouts| HttpResponse::Ok().json(payouts))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::ReadCache;
+    use crate::changes::ChangeFeed;
+    use crate::config::{
+        AdminConfig, CacheConfig, ChangesConfig, EarningsConfig, EnrollmentConfig, PaymentsConfig,
+    };
+    use crate::handlers::{
+        add_course_to_cart, approve_refund, checkout, payment_webhook, request_refund,
+    };
+    use crate::middleware::actor;
+    use crate::payments::{FakePaymentProvider, PaymentEvent, PaymentOutcome, PaymentProvider};
+    use crate::routes::tutor_routes;
+    use crate::state::AppState;
+    use crate::store;
+    use crate::webhooks::SIGNATURE_HEADER;
+    use actix_cors::Cors;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App};
+    use serde_json::{json, Value};
+    use sqlx::postgres::PgPool;
+    use std::env;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    const ADMIN_KEY: &str = "admin-key-0123456789";
+    const WEBHOOK_SECRET: &str = "payments-0123456789";
+
+    async fn new_app_state() -> web::Data<AppState> {
+        dotenvy::dotenv().ok();
+
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pg_pool = PgPool::connect(&database_url).await.unwrap();
+        web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            pg_pool,
+            cache: ReadCache::new(&CacheConfig::default()),
+            changes: ChangeFeed::new(&ChangesConfig::default()),
+        })
+    }
+
+    async fn new_course(pg_pool: &PgPool, tutor_id: i32, price: i32) -> i32 {
+        sqlx::query_scalar!(
+            "INSERT INTO ezy_course_c6 (tutor_id, course_name, course_price, course_status)
+            VALUES ($1, 'Earnings test course', $2, 'published')
+            RETURNING course_id",
+            tutor_id,
+            price,
+        )
+        .fetch_one(pg_pool)
+        .await
+        .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn balances_follow_sales_refunds_and_payouts() {
+        let app_state = new_app_state().await;
+        let pg_pool = app_state.pg_pool.clone();
+        let tutor_id = sqlx::query_scalar!(
+            "INSERT INTO ezy_tutor_c6 (tutor_name, tutor_pic_url, tutor_profile)
+            VALUES ('Earnings test tutor', 'http://s3.amazon.aws.com/pic1', 'Earns')
+            RETURNING tutor_id"
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        let kept = new_course(&pg_pool, tutor_id, 3000).await;
+        let refunded = new_course(&pg_pool, tutor_id, 1000).await;
+        let provider = Arc::new(FakePaymentProvider::new(WEBHOOK_SECRET));
+        // Payouts pay every tutor in the currency, so this test keeps its own.
+        let payments = PaymentsConfig {
+            currency: "xts".to_string(),
+            webhook_secret: WEBHOOK_SECRET.to_string(),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(actor))
+                .app_data(app_state)
+                .app_data(web::Data::new(payments.clone()))
+                .app_data(web::Data::new(EnrollmentConfig::default()))
+                .app_data(web::Data::new(EarningsConfig::default()))
+                .app_data(web::Data::new(AdminConfig {
+                    api_keys: vec![ADMIN_KEY.to_string()],
+                }))
+                .app_data(web::Data::from(provider.clone() as Arc<dyn PaymentProvider>))
+                .route(
+                    "/cart/courses/{course_id}",
+                    web::put().to(add_course_to_cart),
+                )
+                .route("/cart/checkout", web::post().to(checkout))
+                .route("/payments/webhook", web::post().to(payment_webhook))
+                .route("/orders/{order_id}/refunds", web::post().to(request_refund))
+                .route(
+                    "/admin/refunds/{refund_id}/approve",
+                    web::post().to(approve_refund),
+                )
+                .configure(|cfg| tutor_routes(cfg, Cors::permissive())),
+        )
+        .await;
+
+        let token = format!("student-{}", Uuid::new_v4());
+        for course_id in [kept, refunded] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cart/courses/{}", course_id))
+                .insert_header(("x-api-key", token.as_str()))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(StatusCode::OK, resp.status());
+        }
+        let req = test::TestRequest::post()
+            .uri("/cart/checkout")
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        let order_id = order["order_id"].as_i64().unwrap() as i32;
+        let (signature, body) = provider.notification(&PaymentEvent {
+            payment_id: order["payment_id"].as_str().unwrap().to_string(),
+            outcome: PaymentOutcome::Succeeded,
+        });
+        let req = test::TestRequest::post()
+            .uri("/payments/webhook")
+            .insert_header((SIGNATURE_HEADER, signature))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+
+        let line_no = sqlx::query_scalar!(
+            "SELECT line_no FROM ezy_order_item WHERE order_id = $1 and course_id = $2",
+            order_id,
+            refunded,
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        let req = test::TestRequest::post()
+            .uri(&format!("/orders/{}/refunds", order_id))
+            .insert_header(("x-api-key", token.as_str()))
+            .set_json(json!({ "line_nos": [line_no] }))
+            .to_request();
+        let refund: Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/refunds/{}/approve", refund["refund_id"]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+
+        let earnings = |key: Option<&str>| {
+            let mut req = test::TestRequest::get().uri(&format!("/tutors/{}/earnings", tutor_id));
+            if let Some(key) = key {
+                req = req.insert_header(("x-api-key", key));
+            }
+            req.to_request()
+        };
+        let resp = test::call_service(&app, earnings(None)).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+        let resp = test::call_service(&app, earnings(Some(token.as_str()))).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+
+        // 4000 sold less 1000 refunded, the platform keeping 10% of what was kept.
+        let balance: Value = test::call_and_read_body_json(&app, earnings(Some(ADMIN_KEY))).await;
+        assert_eq!(
+            json!([4000, 1000, 300, 2700, 0]),
+            json!([
+                balance["gross_sales"],
+                balance["refunds"],
+                balance["platform_fees"],
+                balance["available"],
+                balance["paid_out"],
+            ])
+        );
+
+        let payouts = store::pay_out_earnings(&pg_pool, &payments.currency, 1000)
+            .await
+            .unwrap();
+        let payout = payouts
+            .iter()
+            .find(|payout| payout.tutor_id == tutor_id)
+            .unwrap();
+        assert_eq!(2700, payout.amount);
+        let balance: Value = test::call_and_read_body_json(&app, earnings(Some(ADMIN_KEY))).await;
+        assert_eq!(
+            json!([0, 2700]),
+            json!([balance["available"], balance["paid_out"]])
+        );
+        let req = test::TestRequest::get()
+            .uri(&format!("/tutors/{}/payouts", tutor_id))
+            .insert_header(("x-api-key", ADMIN_KEY))
+            .to_request();
+        let payouts: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(payout.payout_id, payouts[0]["payout_id"]);
+        let req = test::TestRequest::get()
+            .uri(&format!("/tutors/{}/earnings/statement", tutor_id))
+            .insert_header(("x-api-key", ADMIN_KEY))
+            .to_request();
+        let statement: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(0, statement["closing_balance"]);
+
+        sqlx::query!("DELETE FROM ezy_tutor_c6 WHERE tutor_id = $1", tutor_id)
+            .execute(&pg_pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,39 @@
+use crate::config::EnrollmentConfig;
+use crate::errors::EzyTutorError;
+use crate::state::AppState;
+use crate::store;
+use actix_web::{web, HttpResponse};
+
+pub async fn get_enrollments(
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::get_enrollments(&app_state.pg_pool)
+        .await
+        .map(|enrollments| HttpResponse::Ok().json(enrollments))
+}
+
+pub async fn enroll_in_course(
+    app_state: web::Data<AppState>,
+    config: web::Data<EnrollmentConfig>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    store::enroll_in_course(
+        &app_state.pg_pool,
+        tutor_id,
+        course_id,
+        config.require_prerequisites,
+    )
+    .await
+    .map(|enrollment| HttpResponse::Ok().json(enrollment))
+}
+
+pub async fn complete_course(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    store::complete_course(&app_state.pg_pool, tutor_id, course_id)
+        .await
+        .map(|enrollment| HttpResponse::Ok().json(enrollment))
+}
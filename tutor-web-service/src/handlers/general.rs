@@ -1,12 +1,20 @@
 use crate::errors::EzyTutorError;
 use crate::state::AppState;
+use crate::store;
 use actix_web::{web, HttpResponse};
 
+/// Counts health checks across every instance, so the answer does not depend on which
+/// replica the load balancer picked.
 pub async fn health_check_handler(
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, EzyTutorError> {
-    let mut visit_count = app_state.visit_count.lock().unwrap();
-    let response = format!("{} {} times", app_state.health_check_response, *visit_count);
-    *visit_count += 1;
+    let visit_count = store::next_visit_count(&app_state.pg_pool).await?;
+    let response = format!("{} {} times", app_state.health_check_response, visit_count);
     Ok(HttpResponse::Ok().json(&response))
 }
+
+pub async fn cache_stats_handler(
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, EzyTutorError> {
+    Ok(HttpResponse::Ok().json(app_state.cache.stats()))
+}
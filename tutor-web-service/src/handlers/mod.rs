@@ -1,7 +1,39 @@
+mod admin;
+mod bundle;
+mod category;
+mod changes;
 mod course;
+mod earnings;
+mod enrollment;
 mod general;
+mod order;
+mod promotion;
+mod refund;
 mod tutor;
 
+pub use admin::*;
+pub use bundle::*;
+pub use category::*;
+pub use changes::*;
 pub use course::*;
+pub use earnings::*;
+pub use enrollment::*;
 pub use general::*;
+pub use order::*;
+pub use promotion::*;
+pub use refund::*;
 pub use tutor::*;
+
+use crate::errors::EzyTutorError;
+
+use actix_web::web;
+use serde::de::DeserializeOwned;
+
+/// Decodes a JSON body the client may leave empty to take the defaults.
+fn optional_json<T: DeserializeOwned + Default>(body: &web::Bytes) -> Result<T, EzyTutorError> {
+    if body.is_empty() {
+        return Ok(T::default());
+    }
+    serde_json::from_slice(body)
+        .map_err(|_| EzyTutorError::InvalidInput("Please provide valid Json input".to_string()))
+}
@@ -0,0 +1,421 @@
+use crate::config::{EarningsConfig, EnrollmentConfig, PaymentsConfig};
+use crate::errors::EzyTutorError;
+use crate::models::ApplyCoupon;
+use crate::payments::PaymentProvider;
+use crate::state::AppState;
+use crate::store;
+
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse};
+
+pub async fn get_cart(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::get_cart(&app_state.pg_pool, &payments.currency)
+        .await
+        .map(|cart| HttpResponse::Ok().json(cart))
+}
+
+pub async fn add_course_to_cart(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (course_id,) = params.into_inner();
+    store::add_course_to_cart(&app_state.pg_pool, course_id).await?;
+    get_cart(app_state, payments).await
+}
+
+pub async fn remove_course_from_cart(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (course_id,) = params.into_inner();
+    store::remove_from_cart(&app_state.pg_pool, Some(course_id), None).await?;
+    get_cart(app_state, payments).await
+}
+
+pub async fn add_bundle_to_cart(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (bundle_id,) = params.into_inner();
+    store::add_bundle_to_cart(&app_state.pg_pool, bundle_id).await?;
+    get_cart(app_state, payments).await
+}
+
+pub async fn remove_bundle_from_cart(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (bundle_id,) = params.into_inner();
+    store::remove_from_cart(&app_state.pg_pool, None, Some(bundle_id)).await?;
+    get_cart(app_state, payments).await
+}
+
+pub async fn apply_coupon_to_cart(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+    apply: web::Json<ApplyCoupon>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::apply_coupon_to_cart(&app_state.pg_pool, &apply.code).await?;
+    get_cart(app_state, payments).await
+}
+
+pub async fn remove_coupon_from_cart(
+    app_state: web::Data<AppState>,
+    payments: web::Data<PaymentsConfig>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::remove_coupon_from_cart(&app_state.pg_pool).await?;
+    get_cart(app_state, payments).await
+}
+
+/// Places an order for everything in the caller's cart.
+pub async fn checkout(
+    app_state: web::Data<AppState>,
+    provider: web::Data<dyn PaymentProvider>,
+    payments: web::Data<PaymentsConfig>,
+    enrollment: web::Data<EnrollmentConfig>,
+    earnings: web::Data<EarningsConfig>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::checkout(
+        &app_state.pg_pool,
+        provider.get_ref(),
+        &payments.currency,
+        enrollment.require_prerequisites,
+        earnings.platform_fee_percent,
+    )
+    .await
+    .map(|checkout| HttpResponse::Ok().json(checkout))
+}
+
+pub async fn get_orders(app_state: web::Data<AppState>) -> Result<HttpResponse, EzyTutorError> {
+    store::get_orders(&app_state.pg_pool)
+        .await
+        .map(|orders| HttpResponse::Ok().json(orders))
+}
+
+pub async fn get_order(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (order_id,) = params.into_inner();
+    store::get_order(&app_state.pg_pool, order_id)
+        .await
+        .map(|order| HttpResponse::Ok().json(order))
+}
+
+/// Receives the payment provider's notifications of settled payments.
+pub async fn payment_webhook(
+    app_state: web::Data<AppState>,
+    provider: web::Data<dyn PaymentProvider>,
+    req: HttpRequest,
+    body: Bytes,
+) -> Result<HttpResponse, EzyTutorError> {
+    let event = provider.parse_event(req.headers(), &body)?;
+    store::confirm_payment(&app_state.pg_pool, event)
+        .await
+        .map(|order| HttpResponse::Ok().json(order))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::changes::ChangeFeed;
+    use crate::config::{CacheConfig, ChangesConfig};
+    use crate::middleware::actor;
+    use crate::models::Order;
+    use crate::payments::{FakePaymentProvider, Payment, PaymentEvent, PaymentOutcome};
+    use crate::webhooks::SIGNATURE_HEADER;
+    use actix_web::http::header::HeaderMap;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+    use async_trait::async_trait;
+    use serde_json::Value;
+    use sqlx::postgres::PgPool;
+    use std::env;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    const WEBHOOK_SECRET: &str = "payments-0123456789";
+
+    /// The fake provider, checking that orders are committed before their payment starts.
+    struct CommittedOrdersProvider {
+        pg_pool: PgPool,
+        fake: FakePaymentProvider,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl PaymentProvider for CommittedOrdersProvider {
+        async fn create_payment(&self, order: &Order) -> Result<Payment, EzyTutorError> {
+            let status = sqlx::query_scalar!(
+                "SELECT order_status FROM ezy_order WHERE order_id = $1",
+                order.order_id,
+            )
+            .fetch_optional(&self.pg_pool)
+            .await?;
+            assert_eq!(Some("pending".to_string()), status);
+            if self.fail {
+                return Err(EzyTutorError::StorageError(
+                    "Provider unavailable".to_string(),
+                ));
+            }
+            self.fake.create_payment(order).await
+        }
+
+        async fn refund_payment(
+            &self,
+            refund_id: i32,
+            payment_id: &str,
+            amount: i32,
+        ) -> Result<String, EzyTutorError> {
+            self.fake
+                .refund_payment(refund_id, payment_id, amount)
+                .await
+        }
+
+        fn parse_event(
+            &self,
+            headers: &HeaderMap,
+            body: &[u8],
+        ) -> Result<PaymentEvent, EzyTutorError> {
+            self.fake.parse_event(headers, body)
+        }
+    }
+
+    async fn new_app_state() -> web::Data<AppState> {
+        dotenvy::dotenv().ok();
+
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pg_pool = PgPool::connect(&database_url).await.unwrap();
+        web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            pg_pool,
+            cache: ReadCache::new(&CacheConfig::default()),
+            changes: ChangeFeed::new(&ChangesConfig::default()),
+        })
+    }
+
+    async fn new_course(pg_pool: &PgPool) -> (i32, i32) {
+        let tutor_id = sqlx::query_scalar!(
+            "INSERT INTO ezy_tutor_c6 (tutor_name, tutor_pic_url, tutor_profile)
+            VALUES ('Order test tutor', 'http://s3.amazon.aws.com/pic1', 'Sells courses')
+            RETURNING tutor_id"
+        )
+        .fetch_one(pg_pool)
+        .await
+        .unwrap();
+        let course_id = sqlx::query_scalar!(
+            "INSERT INTO ezy_course_c6 (tutor_id, course_name, course_price, course_status)
+            VALUES ($1, 'Order test course', 1500, 'published')
+            RETURNING course_id",
+            tutor_id,
+        )
+        .fetch_one(pg_pool)
+        .await
+        .unwrap();
+        (tutor_id, course_id)
+    }
+
+    #[actix_rt::test]
+    async fn checkout_starts_the_payment_after_committing_the_order() {
+        let app_state = new_app_state().await;
+        let pg_pool = app_state.pg_pool.clone();
+        let (tutor_id, course_id) = new_course(&pg_pool).await;
+        let provider = Arc::new(CommittedOrdersProvider {
+            pg_pool: pg_pool.clone(),
+            fake: FakePaymentProvider::new(WEBHOOK_SECRET),
+            fail: false,
+        });
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(actor))
+                .app_data(app_state)
+                .app_data(web::Data::new(PaymentsConfig {
+                    webhook_secret: WEBHOOK_SECRET.to_string(),
+                    ..Default::default()
+                }))
+                .app_data(web::Data::new(EnrollmentConfig::default()))
+                .app_data(web::Data::new(EarningsConfig::default()))
+                .app_data(web::Data::from(provider.clone() as Arc<dyn PaymentProvider>))
+                .route("/cart", web::get().to(get_cart))
+                .route(
+                    "/cart/courses/{course_id}",
+                    web::put().to(add_course_to_cart),
+                )
+                .route("/cart/checkout", web::post().to(checkout))
+                .route("/payments/webhook", web::post().to(payment_webhook)),
+        )
+        .await;
+
+        // Callers known only by their address have no cart.
+        let req = test::TestRequest::put()
+            .uri(&format!("/cart/courses/{}", course_id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+
+        let token = format!("student-{}", Uuid::new_v4());
+        let req = test::TestRequest::put()
+            .uri(&format!("/cart/courses/{}", course_id))
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        let req = test::TestRequest::post()
+            .uri("/cart/checkout")
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        let order_id = order["order_id"].as_i64().unwrap();
+        assert_eq!("pending", order["order_status"]);
+        assert_eq!(format!("fake_order_{}", order_id), order["payment_id"]);
+
+        // Repeated notifications settle the order once.
+        let (signature, body) = provider.fake.notification(&PaymentEvent {
+            payment_id: order["payment_id"].as_str().unwrap().to_string(),
+            outcome: PaymentOutcome::Succeeded,
+        });
+        for _ in 0..2 {
+            let req = test::TestRequest::post()
+                .uri("/payments/webhook")
+                .insert_header((SIGNATURE_HEADER, signature.as_str()))
+                .set_payload(body.clone())
+                .to_request();
+            let order: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!("paid", order["order_status"]);
+        }
+        let enrollments = sqlx::query_scalar!(
+            r#"SELECT count(*) as "count!" FROM ezy_enrollment WHERE course_id = $1"#,
+            course_id,
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        assert_eq!(1, enrollments);
+
+        sqlx::query!("DELETE FROM ezy_tutor_c6 WHERE tutor_id = $1", tutor_id)
+            .execute(&pg_pool)
+            .await
+            .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn reconciliation_attaches_payments_to_stale_orders() {
+        let app_state = new_app_state().await;
+        let pg_pool = app_state.pg_pool.clone();
+        let student = format!("token:{}", Uuid::new_v4());
+        let new_order = |age_secs: f64| {
+            sqlx::query_scalar!(
+                "INSERT INTO ezy_order (student, original_amount, total_amount, currency, created_at)
+                VALUES ($1, 1000, 1000, 'usd', now() - $2 * interval '1 second')
+                RETURNING order_id",
+                student,
+                age_secs,
+            )
+            .fetch_one(&pg_pool)
+        };
+        let stale = new_order(3600.0).await.unwrap();
+        let fresh = new_order(0.0).await.unwrap();
+
+        let provider = FakePaymentProvider::new(WEBHOOK_SECRET);
+        let age = Duration::from_secs(300);
+        let reconciled = store::reconcile_checkouts(&pg_pool, &provider, age, 1000)
+            .await
+            .unwrap();
+        assert!(reconciled.contains(&stale));
+        assert!(!reconciled.contains(&fresh));
+        let payment_ids = sqlx::query_scalar!(
+            "SELECT payment_id FROM ezy_order WHERE student = $1 ORDER BY order_id",
+            student,
+        )
+        .fetch_all(&pg_pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            vec![Some(format!("fake_order_{}", stale)), None],
+            payment_ids
+        );
+
+        sqlx::query!("DELETE FROM ezy_order WHERE student = $1", student)
+            .execute(&pg_pool)
+            .await
+            .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn failed_payment_start_restores_the_cart() {
+        let app_state = new_app_state().await;
+        let pg_pool = app_state.pg_pool.clone();
+        let (tutor_id, course_id) = new_course(&pg_pool).await;
+        let provider = Arc::new(CommittedOrdersProvider {
+            pg_pool: pg_pool.clone(),
+            fake: FakePaymentProvider::new(WEBHOOK_SECRET),
+            fail: true,
+        });
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(actor))
+                .app_data(app_state)
+                .app_data(web::Data::new(PaymentsConfig {
+                    webhook_secret: WEBHOOK_SECRET.to_string(),
+                    ..Default::default()
+                }))
+                .app_data(web::Data::new(EnrollmentConfig::default()))
+                .app_data(web::Data::new(EarningsConfig::default()))
+                .app_data(web::Data::from(provider as Arc<dyn PaymentProvider>))
+                .route("/cart", web::get().to(get_cart))
+                .route(
+                    "/cart/courses/{course_id}",
+                    web::put().to(add_course_to_cart),
+                )
+                .route("/cart/checkout", web::post().to(checkout))
+                .route("/payments/webhook", web::post().to(payment_webhook)),
+        )
+        .await;
+
+        let token = format!("student-{}", Uuid::new_v4());
+        let req = test::TestRequest::put()
+            .uri(&format!("/cart/courses/{}", course_id))
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        let req = test::TestRequest::post()
+            .uri("/cart/checkout")
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, resp.status());
+
+        let status = sqlx::query_scalar!(
+            "SELECT o.order_status FROM ezy_order o
+            JOIN ezy_order_item i USING (order_id)
+            WHERE i.course_id = $1",
+            course_id,
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        assert_eq!("failed", status);
+        let req = test::TestRequest::get()
+            .uri("/cart")
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let cart: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(course_id, cart["items"][0]["course_id"]);
+
+        sqlx::query!("DELETE FROM ezy_tutor_c6 WHERE tutor_id = $1", tutor_id)
+            .execute(&pg_pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+use crate::errors::EzyTutorError;
+use crate::models::{NewCoupon, NewCourseSale};
+use crate::state::AppState;
+use crate::store;
+
+use actix_web::{web, HttpResponse};
+
+pub async fn get_course_sales(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    store::get_course_sales(&app_state.pg_pool, tutor_id, course_id)
+        .await
+        .map(|sales| HttpResponse::Ok().json(sales))
+}
+
+pub async fn post_course_sale(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+    new_sale: web::Json<NewCourseSale>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id) = params.into_inner();
+    let sale = store::post_course_sale(
+        &app_state.pg_pool,
+        tutor_id,
+        course_id,
+        new_sale.into_inner(),
+    )
+    .await?;
+    app_state.cache.invalidate_courses(tutor_id);
+    Ok(HttpResponse::Ok().json(sale))
+}
+
+pub async fn delete_course_sale(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, course_id, sale_id) = params.into_inner();
+    let resp = store::delete_course_sale(&app_state.pg_pool, tutor_id, course_id, sale_id).await?;
+    app_state.cache.invalidate_courses(tutor_id);
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+pub async fn get_coupons(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = params.0;
+    store::get_coupons(&app_state.pg_pool, tutor_id)
+        .await
+        .map(|coupons| HttpResponse::Ok().json(coupons))
+}
+
+pub async fn post_new_coupon(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+    new_coupon: web::Json<NewCoupon>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = params.0;
+    store::post_new_coupon(&app_state.pg_pool, tutor_id, new_coupon.into_inner())
+        .await
+        .map(|coupon| HttpResponse::Ok().json(coupon))
+}
+
+pub async fn delete_coupon(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (tutor_id, coupon_id) = params.into_inner();
+    store::delete_coupon(&app_state.pg_pool, tutor_id, coupon_id)
+        .await
+        .map(|resp| HttpResponse::Ok().json(resp))
+}
@@ -0,0 +1,305 @@
+use super::optional_json;
+use crate::errors::EzyTutorError;
+use crate::models::{NewRefund, PutCancellationPolicy};
+use crate::state::AppState;
+use crate::store;
+
+use actix_web::{web, HttpResponse};
+
+pub async fn get_cancellation_policy(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = params.0;
+    store::get_cancellation_policy(&app_state.pg_pool, tutor_id)
+        .await
+        .map(|policy| HttpResponse::Ok().json(policy))
+}
+
+pub async fn put_cancellation_policy(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+    policy: web::Json<PutCancellationPolicy>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = params.0;
+    store::put_cancellation_policy(&app_state.pg_pool, tutor_id, policy.into_inner())
+        .await
+        .map(|policy| HttpResponse::Ok().json(policy))
+}
+
+/// Asks for a refund of the caller's order; the body may be left out to refund all of it.
+pub async fn request_refund(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+    body: web::Bytes,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (order_id,) = params.into_inner();
+    let new_refund: NewRefund = optional_json(&body)?;
+    store::request_refund(&app_state.pg_pool, order_id, new_refund)
+        .await
+        .map(|refund| HttpResponse::Ok().json(refund))
+}
+
+pub async fn get_order_refunds(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let (order_id,) = params.into_inner();
+    store::get_order_refunds(&app_state.pg_pool, order_id)
+        .await
+        .map(|refunds| HttpResponse::Ok().json(refunds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::changes::ChangeFeed;
+    use crate::config::{
+        CacheConfig, ChangesConfig, EarningsConfig, EnrollmentConfig, PaymentsConfig,
+    };
+    use crate::handlers::{
+        add_course_to_cart, approve_refund, checkout, get_refunds, payment_webhook,
+    };
+    use crate::middleware::actor;
+    use crate::models::Order;
+    use crate::payments::{
+        FakePaymentProvider, Payment, PaymentEvent, PaymentOutcome, PaymentProvider,
+    };
+    use crate::webhooks::SIGNATURE_HEADER;
+    use actix_web::http::header::HeaderMap;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+    use async_trait::async_trait;
+    use serde_json::Value;
+    use sqlx::postgres::PgPool;
+    use std::env;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    const WEBHOOK_SECRET: &str = "payments-0123456789";
+
+    /// The fake provider, checking that refunds are committed as `refunding` before they
+    /// are made and failing them while `fail_refunds` is set.
+    struct FlakyRefundsProvider {
+        pg_pool: PgPool,
+        fake: FakePaymentProvider,
+        fail_refunds: AtomicBool,
+    }
+
+    #[async_trait]
+    impl PaymentProvider for FlakyRefundsProvider {
+        async fn create_payment(&self, order: &Order) -> Result<Payment, EzyTutorError> {
+            self.fake.create_payment(order).await
+        }
+
+        async fn refund_payment(
+            &self,
+            refund_id: i32,
+            payment_id: &str,
+            amount: i32,
+        ) -> Result<String, EzyTutorError> {
+            let status = sqlx::query_scalar!(
+                "SELECT refund_status FROM ezy_refund WHERE refund_id = $1",
+                refund_id,
+            )
+            .fetch_one(&self.pg_pool)
+            .await?;
+            assert_eq!("refunding", status);
+            if self.fail_refunds.load(Ordering::SeqCst) {
+                return Err(EzyTutorError::StorageError(
+                    "Provider unavailable".to_string(),
+                ));
+            }
+            self.fake
+                .refund_payment(refund_id, payment_id, amount)
+                .await
+        }
+
+        fn parse_event(
+            &self,
+            headers: &HeaderMap,
+            body: &[u8],
+        ) -> Result<PaymentEvent, EzyTutorError> {
+            self.fake.parse_event(headers, body)
+        }
+    }
+
+    async fn new_app_state() -> web::Data<AppState> {
+        dotenvy::dotenv().ok();
+
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pg_pool = PgPool::connect(&database_url).await.unwrap();
+        web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            pg_pool,
+            cache: ReadCache::new(&CacheConfig::default()),
+            changes: ChangeFeed::new(&ChangesConfig::default()),
+        })
+    }
+
+    #[actix_rt::test]
+    async fn approval_resumes_after_the_provider_fails() {
+        let app_state = new_app_state().await;
+        let pg_pool = app_state.pg_pool.clone();
+        let tutor_id = sqlx::query_scalar!(
+            "INSERT INTO ezy_tutor_c6 (tutor_name, tutor_pic_url, tutor_profile)
+            VALUES ('Refund test tutor', 'http://s3.amazon.aws.com/pic1', 'Refunds courses')
+            RETURNING tutor_id"
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        let course_id = sqlx::query_scalar!(
+            "INSERT INTO ezy_course_c6 (tutor_id, course_name, course_price, course_status)
+            VALUES ($1, 'Refund test course', 1500, 'published')
+            RETURNING course_id",
+            tutor_id,
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        let provider = Arc::new(FlakyRefundsProvider {
+            pg_pool: pg_pool.clone(),
+            fake: FakePaymentProvider::new(WEBHOOK_SECRET),
+            fail_refunds: AtomicBool::new(true),
+        });
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(actor))
+                .app_data(app_state)
+                .app_data(web::Data::new(PaymentsConfig {
+                    webhook_secret: WEBHOOK_SECRET.to_string(),
+                    ..Default::default()
+                }))
+                .app_data(web::Data::new(EnrollmentConfig::default()))
+                .app_data(web::Data::new(EarningsConfig::default()))
+                .app_data(web::Data::from(provider.clone() as Arc<dyn PaymentProvider>))
+                .route(
+                    "/cart/courses/{course_id}",
+                    web::put().to(add_course_to_cart),
+                )
+                .route("/cart/checkout", web::post().to(checkout))
+                .route("/payments/webhook", web::post().to(payment_webhook))
+                .route("/orders/{order_id}/refunds", web::post().to(request_refund))
+                .route("/admin/refunds", web::get().to(get_refunds))
+                .route(
+                    "/admin/refunds/{refund_id}/approve",
+                    web::post().to(approve_refund),
+                ),
+        )
+        .await;
+
+        let token = format!("student-{}", Uuid::new_v4());
+        let req = test::TestRequest::put()
+            .uri(&format!("/cart/courses/{}", course_id))
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        let req = test::TestRequest::post()
+            .uri("/cart/checkout")
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let order: Value = test::call_and_read_body_json(&app, req).await;
+        let order_id = order["order_id"].as_i64().unwrap() as i32;
+        let (signature, body) = provider.fake.notification(&PaymentEvent {
+            payment_id: order["payment_id"].as_str().unwrap().to_string(),
+            outcome: PaymentOutcome::Succeeded,
+        });
+        let req = test::TestRequest::post()
+            .uri("/payments/webhook")
+            .insert_header((SIGNATURE_HEADER, signature))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/orders/{}/refunds", order_id))
+            .insert_header(("x-api-key", token.as_str()))
+            .to_request();
+        let refund: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(1500, refund["amount"]);
+        let approve = || {
+            test::TestRequest::post()
+                .uri(&format!("/admin/refunds/{}/approve", refund["refund_id"]))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, approve()).await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, resp.status());
+        let refunded = sqlx::query!(
+            r#"SELECT r.refund_status, o.refunded_amount,
+                (SELECT count(*) FROM ezy_enrollment e WHERE e.order_id = o.order_id) AS "enrollments!"
+            FROM ezy_refund r
+            JOIN ezy_order o USING (order_id)
+            WHERE o.order_id = $1"#,
+            order_id,
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            ("refunding", 0, 1),
+            (
+                refunded.refund_status.as_str(),
+                refunded.refunded_amount,
+                refunded.enrollments
+            )
+        );
+
+        provider.fail_refunds.store(false, Ordering::SeqCst);
+        let refunded: Value = test::call_and_read_body_json(&app, approve()).await;
+        assert_eq!("refunded", refunded["refund_status"]);
+        assert_eq!(
+            format!("fake_refund_{}", refund["refund_id"]),
+            refunded["provider_refund_id"]
+        );
+        let refunded = sqlx::query!(
+            r#"SELECT o.refunded_amount,
+                (SELECT count(*) FROM ezy_enrollment e WHERE e.order_id = o.order_id) AS "enrollments!"
+            FROM ezy_order o
+            WHERE o.order_id = $1"#,
+            order_id,
+        )
+        .fetch_one(&pg_pool)
+        .await
+        .unwrap();
+        assert_eq!((1500, 0), (refunded.refunded_amount, refunded.enrollments));
+
+        let resp = test::call_service(&app, approve()).await;
+        assert_eq!(StatusCode::CONFLICT, resp.status());
+
+        // Pages of refunds come newest first, each continuing before the last one's id.
+        let refund_id = refund["refund_id"].as_i64().unwrap();
+        let list = |query: String| {
+            test::TestRequest::get()
+                .uri(&format!("/admin/refunds?{}", query))
+                .to_request()
+        };
+        let page: Value = test::call_and_read_body_json(
+            &app,
+            list(format!("before_id={}&limit=1", refund_id + 1)),
+        )
+        .await;
+        assert_eq!(1, page.as_array().unwrap().len());
+        assert_eq!(refund_id, page[0]["refund_id"]);
+        let page: Value =
+            test::call_and_read_body_json(&app, list(format!("before_id={}", refund_id))).await;
+        assert!(page
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|refund| refund["refund_id"].as_i64().unwrap() < refund_id));
+        let resp = test::call_service(&app, list("limit=0".to_string())).await;
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+
+        sqlx::query!("DELETE FROM ezy_tutor_c6 WHERE tutor_id = $1", tutor_id)
+            .execute(&pg_pool)
+            .await
+            .unwrap();
+    }
+}
@@ -1,13 +1,42 @@
 use crate::errors::EzyTutorError;
-use crate::models::{NewTutor, UpdateTutor};
+use crate::models::{NewTutor, RateTutor, TutorDirectoryQuery, TutorPicture, UpdateTutor};
+use crate::pictures::{self, Pictures};
 use crate::state::AppState;
 use crate::store;
-use actix_web::{web, HttpResponse};
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
 
-pub async fn get_all_tutors(app_state: web::Data<AppState>) -> Result<HttpResponse, EzyTutorError> {
-    store::get_all_tutors(&app_state.pg_pool)
+pub async fn get_all_tutors(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, EzyTutorError> {
+    let pg_pool = &app_state.pg_pool;
+    app_state
+        .cache
+        .tutors
+        .get_or_load((), || store::get_all_tutors(pg_pool))
         .await
-        .map(|tutors| HttpResponse::Ok().json(tutors))
+        .map(|tutors| tutors.respond(&req))
+}
+
+pub async fn get_tutor_directory(
+    app_state: web::Data<AppState>,
+    query: web::Query<TutorDirectoryQuery>,
+) -> Result<HttpResponse, EzyTutorError> {
+    store::get_tutor_directory(&app_state.pg_pool, query.into_inner())
+        .await
+        .map(|directory| HttpResponse::Ok().json(directory))
+}
+
+pub async fn rate_tutor(
+    app_state: web::Data<AppState>,
+    params: web::Path<(i32,)>,
+    rate: web::Json<RateTutor>,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = params.0;
+    store::rate_tutor(&app_state.pg_pool, tutor_id, rate.into_inner())
+        .await
+        .map(|rating| HttpResponse::Ok().json(rating))
 }
 
 pub async fn get_tutor_details(
@@ -24,9 +53,9 @@ pub async fn post_new_tutor(
     app_state: web::Data<AppState>,
     new_tutor: web::Json<NewTutor>,
 ) -> Result<HttpResponse, EzyTutorError> {
-    store::post_new_tutor(&app_state.pg_pool, new_tutor.into_inner())
-        .await
-        .map(|tutor| HttpResponse::Ok().json(tutor))
+    let tutor = store::post_new_tutor(&app_state.pg_pool, new_tutor.into_inner()).await?;
+    app_state.cache.invalidate_tutor(tutor.tutor_id);
+    Ok(HttpResponse::Ok().json(tutor))
 }
 
 pub async fn update_tutor_details(
@@ -35,9 +64,40 @@ pub async fn update_tutor_details(
     update_tutor: web::Json<UpdateTutor>,
 ) -> Result<HttpResponse, EzyTutorError> {
     let tutor_id = params.0;
-    store::update_tutor_details(&app_state.pg_pool, tutor_id, update_tutor.into_inner())
-        .await
-        .map(|tutor| HttpResponse::Ok().json(tutor))
+    let tutor =
+        store::update_tutor_details(&app_state.pg_pool, tutor_id, update_tutor.into_inner())
+            .await?;
+    app_state.cache.invalidate_tutor(tutor_id);
+    Ok(HttpResponse::Ok().json(tutor))
+}
+
+/// Accepts a `multipart/form-data` upload with a `picture` field, stores its thumbnails
+/// and points `tutor_pic_url` at them.
+pub async fn upload_tutor_picture(
+    app_state: web::Data<AppState>,
+    pictures: web::Data<Pictures>,
+    params: web::Path<(i32,)>,
+    multipart: Multipart,
+) -> Result<HttpResponse, EzyTutorError> {
+    let tutor_id = params.0;
+    let previous = store::get_tutor_details(&app_state.pg_pool, tutor_id).await?;
+    let upload = pictures.read_upload(multipart).await?;
+    let thumbnails = pictures.store_tutor_picture(tutor_id, upload).await?;
+
+    let pic_url = pictures::profile_url(&thumbnails);
+    let tutor = match store::update_tutor_pic_url(&app_state.pg_pool, tutor_id, pic_url).await {
+        Ok(tutor) => tutor,
+        Err(err) => {
+            pictures.discard_url(tutor_id, pic_url).await;
+            return Err(err);
+        }
+    };
+    app_state.cache.invalidate_tutor(tutor_id);
+    pictures
+        .discard_url(tutor_id, &previous.tutor_pic_url)
+        .await;
+
+    Ok(HttpResponse::Ok().json(TutorPicture { tutor, thumbnails }))
 }
 
 pub async fn delete_tutor(
@@ -45,18 +105,26 @@ pub async fn delete_tutor(
     params: web::Path<(i32,)>,
 ) -> Result<HttpResponse, EzyTutorError> {
     let tutor_id = params.0;
-    store::delete_tutor(&app_state.pg_pool, tutor_id)
-        .await
-        .map(|tutor| HttpResponse::Ok().json(tutor))
+    let resp = store::delete_tutor(&app_state.pg_pool, tutor_id).await?;
+    app_state.cache.invalidate_tutor(tutor_id);
+    Ok(HttpResponse::Ok().json(resp))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cache::ReadCache;
+    use crate::changes::ChangeFeed;
+    use crate::config::{CacheConfig, ChangesConfig};
+    use crate::middleware::actor;
     use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+    use serde_json::json;
     use sqlx::postgres::PgPool;
     use std::env;
-    use std::sync::Mutex;
+    use tutor_web_common::rate_limit::token_fingerprint;
+    use uuid::Uuid;
 
     async fn new_app_state() -> web::Data<AppState> {
         dotenvy::dotenv().ok();
@@ -65,18 +133,92 @@ mod tests {
         let pg_pool = PgPool::connect(&database_url).await.unwrap();
         web::Data::new(AppState {
             health_check_response: "".to_string(),
-            visit_count: Mutex::new(0),
             pg_pool,
+            cache: ReadCache::new(&CacheConfig::default()),
+            changes: ChangeFeed::new(&ChangesConfig::default()),
         })
     }
 
     #[actix_rt::test]
     async fn get_all_tutors_success_test() {
         let app_state = new_app_state().await;
-        let resp = get_all_tutors(app_state).await.unwrap();
+        let req = test::TestRequest::default().to_http_request();
+        let resp = get_all_tutors(app_state, req).await.unwrap();
         assert_eq!(StatusCode::OK, resp.status());
     }
 
+    #[actix_rt::test]
+    async fn tutor_directory_searches_profiles() {
+        let app_state = new_app_state().await;
+        let query = TutorDirectoryQuery {
+            q: Some("FINANCE merlene".to_string()),
+            min_rating: Some(4.0),
+            ..Default::default()
+        };
+        let directory = store::get_tutor_directory(&app_state.pg_pool, query)
+            .await
+            .unwrap();
+        assert_eq!(1, directory.total);
+        assert_eq!(1, directory.tutors[0].tutor.tutor_id);
+    }
+
+    #[actix_rt::test]
+    async fn only_enrolled_students_rate_tutors() {
+        let app_state = new_app_state().await;
+        let pg_pool = app_state.pg_pool.clone();
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(actor))
+                .app_data(app_state)
+                .route("/tutors/{tutor_id}/rating", web::put().to(rate_tutor)),
+        )
+        .await;
+        let token = format!("student-{}", Uuid::new_v4());
+        let student = format!("token:{}", token_fingerprint(&token));
+        let rate = |token: Option<&str>| {
+            let req = test::TestRequest::put()
+                .uri("/tutors/1/rating")
+                .set_json(json!({ "rating": 5 }));
+            match token {
+                Some(token) => req.insert_header(("x-api-key", token)),
+                None => req,
+            }
+            .to_request()
+        };
+
+        let resp = test::call_service(&app, rate(None)).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+        let resp = test::call_service(&app, rate(Some(&token))).await;
+        assert_eq!(StatusCode::FORBIDDEN, resp.status());
+
+        sqlx::query!(
+            "INSERT INTO ezy_enrollment (course_id, student) VALUES (1, $1)",
+            student,
+        )
+        .execute(&pg_pool)
+        .await
+        .unwrap();
+        let resp = test::call_service(&app, rate(Some(&token))).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        let rater = sqlx::query_scalar!(
+            "SELECT rater FROM ezy_tutor_rating WHERE tutor_id = 1 and rater = $1",
+            student,
+        )
+        .fetch_optional(&pg_pool)
+        .await
+        .unwrap();
+        assert_eq!(Some(student.clone()), rater);
+
+        sqlx::query!("DELETE FROM ezy_tutor_rating WHERE rater = $1", student)
+            .execute(&pg_pool)
+            .await
+            .unwrap();
+        sqlx::query!("DELETE FROM ezy_enrollment WHERE student = $1", student)
+            .execute(&pg_pool)
+            .await
+            .unwrap();
+    }
+
     #[actix_rt::test]
     async fn get_tutor_detail_success() {
         let app_state = new_app_state().await;
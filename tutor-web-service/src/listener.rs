@@ -0,0 +1,137 @@
+use actix_web::web;
+
+use sqlx::postgres::{PgListener, PgPool};
+
+use crate::config::DatabaseConfig;
+use crate::models::ChangeEvent;
+use crate::state::AppState;
+use crate::store::CHANGE_CHANNEL;
+use tutor_web_common::db::next_backoff;
+
+/// Applies tutor and course changes announced by any instance to the local caches and
+/// publishes them on the change feed. Runs until the pool is closed.
+///
+/// Notifications sent while the connection is down are lost, so the caches and feed are
+/// reset whenever it fails; the listener then reconnects and re-subscribes with backoff.
+pub async fn listen_for_changes(
+    pg_pool: PgPool,
+    app_state: web::Data<AppState>,
+    config: DatabaseConfig,
+) {
+    let mut backoff = config.connect_backoff();
+    let mut listener = loop {
+        match subscribe(&pg_pool).await {
+            Ok(listener) => break listener,
+            Err(sqlx::Error::PoolClosed) => return,
+            Err(err) => {
+                tracing::warn!(
+                    retry_in_ms = backoff.as_millis() as u64,
+                    error = %err,
+                    "Could not listen for changes, retrying"
+                );
+                actix_web::rt::time::sleep(backoff).await;
+                backoff = next_backoff(backoff, config.connect_backoff_max());
+            }
+        }
+    };
+    tracing::info!(channel = CHANGE_CHANNEL, "Listening for changes");
+
+    loop {
+        match listener.try_recv().await {
+            Ok(Some(notification)) => {
+                backoff = config.connect_backoff();
+                match serde_json::from_str::<ChangeEvent>(notification.payload()) {
+                    Ok(event) => {
+                        tracing::debug!(?event, "Change received");
+                        app_state.cache.apply(&event);
+                        app_state.changes.publish(event);
+                    }
+                    Err(err) => tracing::warn!(
+                        error = %err,
+                        payload = notification.payload(),
+                        "Ignoring malformed change event"
+                    ),
+                }
+            }
+            Ok(None) => {
+                tracing::warn!("Change listener lost its connection, reconnecting");
+                app_state.cache.clear();
+                app_state.changes.reset();
+            }
+            Err(sqlx::Error::PoolClosed) => return,
+            Err(err) => {
+                tracing::warn!(
+                    retry_in_ms = backoff.as_millis() as u64,
+                    error = %err,
+                    "Change listener could not reconnect, retrying"
+                );
+                app_state.cache.clear();
+                app_state.changes.reset();
+                actix_web::rt::time::sleep(backoff).await;
+                backoff = next_backoff(backoff, config.connect_backoff_max());
+            }
+        }
+    }
+}
+
+async fn subscribe(pg_pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pg_pool).await?;
+    listener.listen(CHANGE_CHANNEL).await?;
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadCache;
+    use crate::changes::{ChangeFeed, FeedItem};
+    use crate::config::{CacheConfig, ChangesConfig};
+    use crate::models::ChangeAction;
+    use crate::store::notify_change;
+    use std::env;
+    use std::time::Duration;
+
+    #[actix_rt::test]
+    async fn rebroadcasts_notified_changes() {
+        dotenvy::dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pg_pool = PgPool::connect(&database_url).await.unwrap();
+        let app_state = web::Data::new(AppState {
+            health_check_response: "".to_string(),
+            pg_pool: pg_pool.clone(),
+            cache: ReadCache::new(&CacheConfig::default()),
+            changes: ChangeFeed::new(&ChangesConfig::default()),
+        });
+        let mut changes = app_state.changes.subscribe(None).receiver;
+        let listener = actix_web::rt::spawn(listen_for_changes(
+            pg_pool.clone(),
+            app_state,
+            DatabaseConfig::default(),
+        ));
+
+        // Keep notifying until the listener has subscribed and passes one on.
+        let event = ChangeEvent::tutor(ChangeAction::Updated, 4242);
+        let received = loop {
+            let mut conn = pg_pool.acquire().await.unwrap();
+            notify_change(&mut conn, &event).await.unwrap();
+            let recv = actix_web::rt::time::timeout(Duration::from_millis(200), changes.recv());
+            if let Ok(received) = recv.await {
+                break received.unwrap();
+            }
+        };
+        let FeedItem::Change(received) = received else {
+            panic!("expected a change, got {:?}", received);
+        };
+        assert!(received.change_id.is_some());
+        assert_eq!(
+            event,
+            ChangeEvent {
+                change_id: None,
+                ..received
+            }
+        );
+
+        pg_pool.close().await;
+        listener.await.unwrap();
+    }
+}
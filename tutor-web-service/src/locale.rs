@@ -0,0 +1,164 @@
+use actix_web::http::header::{AcceptLanguage, Header, Preference, Quality};
+use actix_web::HttpRequest;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::errors::EzyTutorError;
+use crate::models::{Course, CourseDetails, CourseTranslation};
+
+/// Normalizes a BCP 47 language tag such as `pt_BR` to the lowercase `pt-br` form
+/// translations are stored under, or returns `None` when it is not one.
+pub fn normalize_locale(tag: &str) -> Option<String> {
+    let tag = tag.trim().replace('_', "-").to_ascii_lowercase();
+    let mut subtags = tag.split('-');
+    let primary = subtags.next()?;
+    let valid = tag.len() <= 35
+        && (2..=3).contains(&primary.len())
+        && primary.bytes().all(|b| b.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.bytes().all(|b| b.is_ascii_alphanumeric())
+        });
+    valid.then_some(tag)
+}
+
+/// The locales the client asked for, most preferred first: the `lang` query parameter
+/// when given, otherwise the `Accept-Language` header. An unparseable header is ignored,
+/// while an invalid `lang` is rejected.
+pub fn requested_locales(
+    lang: Option<&str>,
+    req: &HttpRequest,
+) -> Result<Vec<String>, EzyTutorError> {
+    if let Some(lang) = lang {
+        return normalize_locale(lang)
+            .map(|locale| vec![locale])
+            .ok_or_else(|| EzyTutorError::InvalidInput(format!("Invalid language {:?}", lang)));
+    }
+    let Ok(AcceptLanguage(mut items)) = AcceptLanguage::parse(req) else {
+        return Ok(Vec::new());
+    };
+    // Stable, so equally weighted languages keep the client's order.
+    items.sort_by_key(|item| Reverse(item.quality));
+    let mut locales = Vec::new();
+    for item in items {
+        if item.quality == Quality::ZERO {
+            continue;
+        }
+        if let Preference::Specific(tag) = item.item {
+            if let Some(locale) = normalize_locale(tag.as_str()) {
+                if !locales.contains(&locale) {
+                    locales.push(locale);
+                }
+            }
+        }
+    }
+    Ok(locales)
+}
+
+/// Every locale a translation may be stored under to satisfy `locales`: each tag followed
+/// by its primary language, so `pt-br` can be served by a `pt` translation.
+pub fn candidate_locales(locales: &[String]) -> Vec<String> {
+    let mut candidates = Vec::new();
+    for locale in locales {
+        let primary = locale.split('-').next().unwrap_or(locale);
+        for candidate in [locale.as_str(), primary] {
+            if !candidates.iter().any(|known| known == candidate) {
+                candidates.push(candidate.to_string());
+            }
+        }
+    }
+    candidates
+}
+
+/// Replaces the course's name and description with its best translation for `locales`,
+/// returning the language the course is now in. The course's own `course_language` wins
+/// wherever it ranks; with no match the untranslated content is kept.
+pub fn localize(
+    course: &mut Course,
+    translations: &HashMap<&str, &CourseTranslation>,
+    locales: &[String],
+) -> Option<String> {
+    let default = course.course_language.as_deref().and_then(normalize_locale);
+    for candidate in candidate_locales(locales) {
+        if default.as_deref() == Some(candidate.as_str()) {
+            break;
+        }
+        if let Some(translation) = translations.get(candidate.as_str()) {
+            course.course_name = translation.course_name.clone();
+            course.course_description = translation.course_description.clone();
+            return Some(translation.locale.clone());
+        }
+    }
+    default
+}
+
+/// Localizes each course with its own translations out of `translations`.
+pub fn localize_all(
+    courses: &mut [CourseDetails],
+    translations: &[CourseTranslation],
+    locales: &[String],
+) {
+    for CourseDetails { course, .. } in courses {
+        let own = translations
+            .iter()
+            .filter(|translation| translation.course_id == course.course_id)
+            .map(|translation| (translation.locale.as_str(), translation))
+            .collect();
+        localize(course, &own, locales);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::ACCEPT_LANGUAGE;
+    use actix_web::test::TestRequest;
+    use chrono::Utc;
+
+    fn translation(locale: &str, name: &str) -> CourseTranslation {
+        CourseTranslation {
+            course_id: 1,
+            locale: locale.to_string(),
+            course_name: name.to_string(),
+            course_description: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn negotiates_accept_language_and_lang() {
+        let req = TestRequest::default()
+            .insert_header((ACCEPT_LANGUAGE, "fr;q=0.5, pt-BR, de;q=0"))
+            .to_http_request();
+        assert_eq!(vec!["pt-br", "fr"], requested_locales(None, &req).unwrap());
+        assert_eq!(vec!["es"], requested_locales(Some("ES"), &req).unwrap());
+        assert!(requested_locales(Some("not a tag"), &req).is_err());
+    }
+
+    #[test]
+    fn falls_back_to_primary_language_then_default() {
+        let mut course: Course = serde_json::from_value(serde_json::json!({
+            "course_id": 1,
+            "tutor_id": 1,
+            "course_name": "Course",
+            "course_language": "en",
+        }))
+        .unwrap();
+        let pt = translation("pt", "Curso");
+        let de = translation("de", "Kurs");
+        let translations = HashMap::from([("pt", &pt), ("de", &de)]);
+
+        let mut localized = course.clone();
+        let locale = localize(&mut localized, &translations, &["en".into(), "de".into()]);
+        assert_eq!(
+            (Some("en".into()), "Course"),
+            (locale, localized.course_name.as_str())
+        );
+
+        let locale = localize(&mut course, &translations, &["pt-br".into(), "de".into()]);
+        assert_eq!(
+            (Some("pt".into()), "Curso"),
+            (locale, course.course_name.as_str())
+        );
+    }
+}
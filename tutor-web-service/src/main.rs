@@ -1,48 +1,216 @@
+mod cache;
+mod changes;
+mod config;
 mod errors;
 mod handlers;
+mod listener;
+mod locale;
+mod middleware;
 mod models;
+mod payments;
+mod payouts;
+mod pictures;
+mod pricing;
+mod publishing;
+mod reconciliation;
 mod routes;
 mod state;
+mod storage;
 mod store;
+mod webhooks;
 
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
 
+use tutor_web_common::{db, telemetry};
+
 use errors::EzyTutorError;
-use sqlx::postgres::PgPool;
 
-use std::env;
+use std::process::ExitCode;
 
+use cache::ReadCache;
+use changes::ChangeFeed;
+use config::{Args, Config};
+use pictures::Pictures;
 use routes::*;
 use state::AppState;
+use webhooks::WebhookDispatcher;
+
+/// The name spans are exported under.
+const SERVICE_NAME: &str = "tutor-web-service";
+/// Exit status for an unusable configuration, `EX_CONFIG` from sysexits(3).
+const EXIT_CONFIG: u8 = 78;
 
 #[actix_rt::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+async fn main() -> ExitCode {
     dotenvy::dotenv().ok();
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
-    let pg_pool = PgPool::connect(&database_url).await.unwrap();
+    let (args, config) = match Args::parse()
+        .and_then(|args| Config::load(args.config_path.as_deref()).map(|config| (args, config)))
+    {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_CONFIG);
+        }
+    };
+    if args.print_config {
+        return match config.to_redacted_toml() {
+            Ok(printed) => {
+                print!("{}", printed);
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                ExitCode::from(EXIT_CONFIG)
+            }
+        };
+    }
+
+    let telemetry = match telemetry::init_subscriber(
+        SERVICE_NAME,
+        config.log.format,
+        &config.log.trace_exporter(),
+    ) {
+        Ok(telemetry) => telemetry,
+        Err(err) => {
+            eprintln!("Failed to initialise telemetry: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = run(config).await;
+    if let Err(err) = &result {
+        tracing::error!(error = %err, "Server terminated with an error");
+    }
+    telemetry.shutdown().await;
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(_) => ExitCode::FAILURE,
+    }
+}
+
+async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = storage::from_config(&config.pictures)?;
+    let pictures = web::Data::new(Pictures::new(&config.pictures, storage));
+    let dispatcher = config
+        .webhooks
+        .dispatch
+        .then(|| WebhookDispatcher::new(&config.webhooks))
+        .transpose()?;
+    let pg_pool = db::connect(&config.database).await?;
     // Construct App State
     let shared_data = web::Data::new(AppState {
         health_check_response: "I'm good. You've already asked me ".to_string(),
-        visit_count: Default::default(),
-        pg_pool,
+        pg_pool: pg_pool.clone(),
+        cache: ReadCache::new(&config.cache),
+        changes: ChangeFeed::new(&config.changes),
+    });
+    let listener = config.changes.listen.then(|| {
+        actix_web::rt::spawn(listener::listen_for_changes(
+            pg_pool.clone(),
+            shared_data.clone(),
+            config.database.clone(),
+        ))
+    });
+    let dispatcher =
+        dispatcher.map(|dispatcher| actix_web::rt::spawn(dispatcher.run(pg_pool.clone())));
+    let publisher = config.publishing.schedule.then(|| {
+        actix_web::rt::spawn(publishing::publish_scheduled_courses(
+            pg_pool.clone(),
+            shared_data.clone(),
+            config.publishing.clone(),
+        ))
+    });
+    let payer = config.earnings.payouts.then(|| {
+        actix_web::rt::spawn(payouts::pay_out_periodically(
+            pg_pool.clone(),
+            config.earnings.clone(),
+            config.payments.currency.clone(),
+        ))
+    });
+    let payment_provider = payments::from_config(&config.payments);
+    let reconciler = config.payments.reconcile.then(|| {
+        actix_web::rt::spawn(reconciliation::reconcile_checkouts_periodically(
+            pg_pool.clone(),
+            payment_provider.clone(),
+            config.payments.clone(),
+        ))
     });
+    let rate_limiter = web::Data::new(config.rate_limit.limiter(&pg_pool));
+    let pruner = actix_web::rt::spawn(rate_limiter.clone().into_inner().prune_periodically());
 
     //Construct app and configure routes
+    let body_limit = config.server.body_limit;
+    let json_config = web::JsonConfig::default()
+        .limit(body_limit)
+        .error_handler(|_err, _req| {
+            EzyTutorError::InvalidInput("Please provide valid Json input".to_string()).into()
+        });
+    let query_config = web::QueryConfig::default()
+        .error_handler(|err, _req| EzyTutorError::InvalidInput(err.to_string()).into());
+    let admin = web::Data::new(config.admin.clone());
+    let enrollment = web::Data::new(config.enrollment.clone());
+    let payments = web::Data::new(config.payments.clone());
+    let earnings = web::Data::new(config.earnings.clone());
+    let payment_provider = web::Data::from(payment_provider);
+    let cors = config.cors.clone();
+    let pictures_config = config.pictures.clone();
     let app = move || {
         App::new()
+            .wrap(from_fn(middleware::actor))
+            .wrap(from_fn(middleware::rate_limit))
+            .wrap(from_fn(middleware::request_id))
             .app_data(shared_data.clone())
-            .app_data(web::JsonConfig::default().error_handler(|_err, _req| {
-                EzyTutorError::InvalidInput("Please provide valid Json input".to_string()).into()
-            }))
-            .configure(general_routes)
-            .configure(course_routes)
-            .configure(tutor_routes)
+            .app_data(rate_limiter.clone())
+            .app_data(pictures.clone())
+            .app_data(web::PayloadConfig::new(body_limit))
+            .app_data(json_config.clone())
+            .app_data(query_config.clone())
+            .app_data(admin.clone())
+            .app_data(enrollment.clone())
+            .app_data(payments.clone())
+            .app_data(earnings.clone())
+            .app_data(payment_provider.clone())
+            .configure(|cfg| general_routes(cfg, middleware::cors(&cors.policy())))
+            .configure(|cfg| change_routes(cfg, middleware::cors(&cors.policy())))
+            .configure(|cfg| course_routes(cfg, middleware::cors(&cors.courses_policy())))
+            .configure(|cfg| bundle_routes(cfg, middleware::cors(&cors.courses_policy())))
+            .configure(|cfg| enrollment_routes(cfg, middleware::cors(&cors.courses_policy())))
+            .configure(|cfg| cart_routes(cfg, middleware::cors(&cors.courses_policy())))
+            .configure(|cfg| order_routes(cfg, middleware::cors(&cors.courses_policy())))
+            .configure(payment_routes)
+            .configure(|cfg| category_routes(cfg, middleware::cors(&cors.courses_policy())))
+            .configure(|cfg| tutor_routes(cfg, middleware::cors(&cors.tutors_policy())))
+            .configure(admin_routes)
+            .configure(|cfg| media_routes(cfg, &pictures_config))
     };
 
-    // Start HTTP server
-    let host_port = env::var("HOST_PORT").expect("HOST:PORT address is not set in .env file");
-    HttpServer::new(app).bind(&host_port)?.run().await?;
+    // Start HTTP server. SIGTERM drains in-flight requests for up to `shutdown_timeout_secs`.
+    tracing::info!("Serving on: {}", config.server.bind_address);
+    HttpServer::new(app)
+        .bind(&config.server.bind_address)?
+        .shutdown_timeout(config.server.shutdown_timeout_secs)
+        .run()
+        .await?;
 
+    tracing::info!("Server stopped, closing database pool");
+    pg_pool.close().await;
+    // Closing the pool is what stops the change listener, webhook dispatcher, publisher,
+    // payouts, checkout reconciliation and rate limit pruning.
+    for task in [
+        listener,
+        dispatcher,
+        publisher,
+        payer,
+        reconciler,
+        Some(pruner),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let _ = task.await;
+    }
     Ok(())
 }
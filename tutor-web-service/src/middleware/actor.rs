@@ -0,0 +1,63 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, ResponseError};
+
+use tutor_web_common::rate_limit::{api_token, token_fingerprint};
+
+use crate::errors::EzyTutorError;
+
+/// Recorded for changes made outside of any request, e.g. by tests or maintenance tasks.
+pub const SYSTEM_ACTOR: &str = "system";
+
+const TOKEN_ACTOR_PREFIX: &str = "token:";
+
+tokio::task_local! {
+    static ACTOR: String;
+}
+
+/// Returns who is making the current request, or [`SYSTEM_ACTOR`] outside of one.
+pub fn current_actor() -> String {
+    ACTOR
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| SYSTEM_ACTOR.to_string())
+}
+
+/// Returns the student making the current request. Only callers presenting an API token
+/// are students: the address identifying everybody else may be shared by many of them.
+pub fn current_student() -> Result<String, EzyTutorError> {
+    ACTOR
+        .try_with(|actor| actor.starts_with(TOKEN_ACTOR_PREFIX).then(|| actor.clone()))
+        .ok()
+        .flatten()
+        .ok_or(EzyTutorError::Unauthorized)
+}
+
+/// Identifies the caller of every request for the audit log: clients presenting an API
+/// token by its fingerprint, everybody else by the address they connect from.
+pub async fn actor(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let actor = match api_token(req.headers()) {
+        Some(token) => format!("{}{}", TOKEN_ACTOR_PREFIX, token_fingerprint(token)),
+        None => match req.peer_addr() {
+            Some(addr) => format!("ip:{}", addr.ip()),
+            None => "ip:unknown".to_string(),
+        },
+    };
+    ACTOR.scope(actor, next.call(req)).await
+}
+
+/// Lets a request through only when it carries an API token identifying the student whose
+/// cart, orders or enrollments it reaches.
+pub async fn require_student(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if api_token(req.headers()).is_none() {
+        let res = EzyTutorError::Unauthorized.error_response();
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
@@ -0,0 +1,78 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, ResponseError};
+
+use sha2::{Digest, Sha256};
+use tutor_web_common::rate_limit::api_token;
+
+use crate::config::AdminConfig;
+use crate::errors::EzyTutorError;
+
+/// Lets a request through only when it carries one of the configured admin API keys.
+pub async fn require_admin(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let allowed = match (
+        req.app_data::<web::Data<AdminConfig>>(),
+        api_token(req.headers()),
+    ) {
+        (Some(config), Some(token)) => is_admin_key(config, token),
+        _ => false,
+    };
+    if !allowed {
+        let res = EzyTutorError::Unauthorized.error_response();
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// Compares digests so response timing reveals nothing about the keys themselves.
+fn is_admin_key(config: &AdminConfig, token: &str) -> bool {
+    let token = Sha256::digest(token.as_bytes());
+    config
+        .api_keys
+        .iter()
+        .any(|key| Sha256::digest(key.trim().as_bytes()) == token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App, HttpResponse};
+
+    #[actix_rt::test]
+    async fn admits_configured_keys_only() {
+        let config = AdminConfig {
+            api_keys: vec!["admin-key-0123456789".to_string()],
+        };
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(config)).service(
+                web::scope("/admin")
+                    .wrap(from_fn(require_admin))
+                    .route("/audit", web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let get = |token: Option<&str>| {
+            let mut req = test::TestRequest::get().uri("/admin/audit");
+            if let Some(token) = token {
+                req = req.insert_header((AUTHORIZATION, format!("Bearer {}", token)));
+            }
+            req.to_request()
+        };
+        let resp = test::call_service(&app, get(Some("admin-key-0123456789"))).await;
+        assert_eq!(StatusCode::OK, resp.status());
+
+        let resp = test::call_service(&app, get(Some("wrong-key"))).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+
+        let resp = test::call_service(&app, get(None)).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+    }
+}
@@ -0,0 +1,74 @@
+use actix_cors::Cors;
+
+use crate::config::CorsPolicy;
+
+/// Builds the CORS middleware for one policy. With no allowed origins, cross-origin
+/// responses carry no CORS headers and browsers keep the API to same-origin pages.
+/// Policies are checked when the configuration is loaded, so building cannot fail here.
+pub fn cors(policy: &CorsPolicy) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(policy.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(policy.allowed_headers.iter().map(String::as_str))
+        .expose_headers(policy.expose_headers.iter().map(String::as_str))
+        .max_age(policy.max_age_secs as usize);
+
+    for origin in &policy.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    if policy.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CorsConfig;
+    use actix_web::http::header::{
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
+        ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+    };
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[actix_rt::test]
+    async fn answers_preflight_for_allowed_origin_only() {
+        let config = CorsConfig {
+            allowed_origins: vec!["https://app.example.com".to_string()],
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new().service(
+                web::scope("/courses")
+                    .wrap(cors(&config.courses_policy()))
+                    .route("/{tutor_id}", web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let preflight = |origin: &str| {
+            test::TestRequest::default()
+                .method(actix_web::http::Method::OPTIONS)
+                .uri("/courses/1")
+                .insert_header((ORIGIN, origin))
+                .insert_header((ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+                .to_request()
+        };
+        let resp = test::call_service(&app, preflight("https://app.example.com")).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(
+            "https://app.example.com",
+            resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap()
+        );
+        assert!(resp.headers().contains_key(ACCESS_CONTROL_ALLOW_METHODS));
+        assert_eq!("3600", resp.headers().get(ACCESS_CONTROL_MAX_AGE).unwrap());
+
+        let resp = test::call_service(&app, preflight("https://evil.example.com")).await;
+        assert!(!resp.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
@@ -0,0 +1,11 @@
+mod actor;
+mod admin;
+mod cors;
+mod rate_limit;
+
+pub use actor::*;
+pub use admin::*;
+pub use cors::*;
+pub use rate_limit::*;
+
+pub use tutor_web_common::request_id::*;
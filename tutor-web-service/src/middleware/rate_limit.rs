@@ -0,0 +1,89 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, ResponseError};
+
+use tutor_web_common::rate_limit::RateLimiter;
+
+use crate::errors::EzyTutorError;
+
+/// Enforces the configured per-route limits, answering `429 Too Many Requests` once a
+/// client's bucket is empty. Requests to limited routes carry `RateLimit-*` headers.
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let decision = match req.app_data::<web::Data<RateLimiter>>() {
+        Some(limiter) => limiter.check(&req).await,
+        None => None,
+    };
+
+    match decision {
+        Some(decision) if !decision.allowed => {
+            let mut res = EzyTutorError::TooManyRequests.error_response();
+            decision.insert_headers(res.headers_mut());
+            Ok(req.into_response(res).map_into_right_body())
+        }
+        decision => {
+            let mut res = next.call(req).await?;
+            if let Some(decision) = decision {
+                decision.insert_headers(res.headers_mut());
+            }
+            Ok(res.map_into_left_body())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{RateLimitConfig, RateLimitRule};
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App, HttpResponse};
+    use sqlx::postgres::PgPoolOptions;
+
+    #[actix_rt::test]
+    async fn rejects_requests_over_the_limit() {
+        let config = RateLimitConfig {
+            routes: vec![RateLimitRule {
+                method: "POST".to_string(),
+                path: "/tutors/".to_string(),
+                requests: 1,
+                period_secs: 60,
+            }],
+            ..Default::default()
+        };
+        // The in-memory store never touches the pool.
+        let pg_pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config.limiter(&pg_pool)))
+                .wrap(from_fn(rate_limit))
+                .route("/tutors/", web::post().to(HttpResponse::Ok))
+                .route("/tutors/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let post = || {
+            test::TestRequest::post()
+                .uri("/tutors/")
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .to_request()
+        };
+        let resp = test::call_service(&app, post()).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!("0", resp.headers().get("ratelimit-remaining").unwrap());
+
+        let resp = test::call_service(&app, post()).await;
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, resp.status());
+        assert_eq!("60", resp.headers().get("retry-after").unwrap());
+
+        let get = test::TestRequest::get().uri("/tutors/").to_request();
+        let resp = test::call_service(&app, get).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        assert!(resp.headers().get("ratelimit-limit").is_none());
+    }
+}
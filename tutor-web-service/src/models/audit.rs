@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::ChangeEntity;
+
+/// One recorded mutation, with the entity as it was before and after it.
+#[derive(Debug, Serialize, Clone)]
+pub struct AuditEntry {
+    pub audit_id: i64,
+    pub actor: String,
+    pub action: String,
+    pub entity: String,
+    pub entity_id: i32,
+    #[serde(rename = "before")]
+    pub before_data: Option<serde_json::Value>,
+    #[serde(rename = "after")]
+    pub after_data: Option<serde_json::Value>,
+    pub request_id: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Filters for the audit log; entries come newest first, `before_id` continues a page.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AuditQuery {
+    pub entity: Option<ChangeEntity>,
+    pub entity_id: Option<i32>,
+    pub actor: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
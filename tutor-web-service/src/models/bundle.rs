@@ -0,0 +1,67 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::Course;
+
+/// Courses sold together as one product and meant to be taken in order, such as a
+/// learning path. They are all taught by the tutor selling the bundle.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Bundle {
+    pub bundle_id: i32,
+    pub tutor_id: i32,
+    pub bundle_name: String,
+    pub bundle_description: Option<String>,
+    pub bundle_price: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A bundle with its courses in path order.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleDetails {
+    #[serde(flatten)]
+    pub bundle: Bundle,
+    pub courses: Vec<Course>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NewBundle {
+    pub tutor_id: i32,
+    pub bundle_name: String,
+    pub bundle_description: Option<String>,
+    pub bundle_price: i32,
+    /// The courses in the order they are to be taken.
+    pub course_ids: Vec<i32>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct UpdateBundle {
+    pub bundle_name: Option<String>,
+    pub bundle_description: Option<String>,
+    pub bundle_price: Option<i32>,
+    /// Replaces the bundle's courses and their order when given.
+    pub course_ids: Option<Vec<i32>>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct BundleListQuery {
+    pub tutor_id: Option<i32>,
+}
+
+/// How far the caller is along a bundle's path.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleProgress {
+    pub bundle_id: i32,
+    pub completed_courses: usize,
+    pub total_courses: usize,
+    /// The first course of the path not completed yet, `None` once all are.
+    pub next_course_id: Option<i32>,
+    pub courses: Vec<BundleCourseProgress>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleCourseProgress {
+    pub course_id: i32,
+    pub position: i32,
+    pub enrolled_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
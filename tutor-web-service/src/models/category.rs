@@ -0,0 +1,115 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::models::CourseDetails;
+
+/// A node in the subject tree courses are filed under.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Category {
+    pub category_id: i32,
+    /// `None` for top-level categories.
+    pub parent_id: Option<i32>,
+    pub category_name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NewCategory {
+    pub category_name: String,
+    pub parent_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct UpdateCategory {
+    pub category_name: Option<String>,
+    /// Moves the category under another parent, or to the top level when `null`.
+    #[serde(default, deserialize_with = "present")]
+    pub parent_id: Option<Option<i32>>,
+}
+
+/// Tells a field set to `null` (`Some(None)`) apart from one left out (`None`).
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Published courses filed under `category_id` or any of its descendants, tagged `tag`
+/// and matching the other given filters, newest first. Prices are inclusive bounds.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct CatalogQuery {
+    pub category_id: Option<i32>,
+    pub tag: Option<String>,
+    pub level: Option<String>,
+    pub format: Option<String>,
+    pub language: Option<String>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Matching courses in a category, counting those filed under its descendants.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CategoryCount {
+    pub category_id: i32,
+    pub parent_id: Option<i32>,
+    pub category_name: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+/// Matching courses with one value of a field such as `course_level`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: i64,
+}
+
+/// Matching courses priced from `min_price` up to and including `max_price`, which is
+/// `None` for the most expensive bucket.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PriceBucketCount {
+    pub min_price: i32,
+    pub max_price: Option<i32>,
+    pub count: i64,
+}
+
+/// Counts over every course matching the catalog query, not just the returned page.
+/// Values no matching course has are left out.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CatalogFacets {
+    pub categories: Vec<CategoryCount>,
+    pub tags: Vec<TagCount>,
+    pub levels: Vec<ValueCount>,
+    pub formats: Vec<ValueCount>,
+    pub languages: Vec<ValueCount>,
+    pub prices: Vec<PriceBucketCount>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Catalog {
+    pub total: i64,
+    pub courses: Vec<CourseDetails>,
+    pub facets: CatalogFacets,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distinguishes_null_parent_from_missing() {
+        let update: UpdateCategory = serde_json::from_str(r#"{"parent_id": null}"#).unwrap();
+        assert_eq!(Some(None), update.parent_id);
+        let update: UpdateCategory = serde_json::from_str(r#"{"category_name": "a"}"#).unwrap();
+        assert_eq!(None, update.parent_id);
+    }
+}
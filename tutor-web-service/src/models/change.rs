@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeEntity {
+    Tutor,
+    Course,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl ChangeEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tutor => "tutor",
+            Self::Course => "course",
+        }
+    }
+}
+
+impl ChangeAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Updated => "updated",
+            Self::Deleted => "deleted",
+        }
+    }
+}
+
+/// A committed tutor or course mutation, as broadcast to every instance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    pub entity: ChangeEntity,
+    pub action: ChangeAction,
+    pub tutor_id: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub course_id: Option<i32>,
+    /// Assigned when the change is announced; the same on every instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change_id: Option<i64>,
+}
+
+impl ChangeEvent {
+    pub fn tutor(action: ChangeAction, tutor_id: i32) -> Self {
+        Self {
+            entity: ChangeEntity::Tutor,
+            action,
+            tutor_id,
+            course_id: None,
+            change_id: None,
+        }
+    }
+
+    pub fn course(action: ChangeAction, tutor_id: i32, course_id: i32) -> Self {
+        Self {
+            entity: ChangeEntity::Course,
+            action,
+            tutor_id,
+            course_id: Some(course_id),
+            change_id: None,
+        }
+    }
+
+    /// The webhook event type, e.g. `course.updated`.
+    pub fn event_type(&self) -> String {
+        format!("{}.{}", self.entity.as_str(), self.action.as_str())
+    }
+}
+
+/// Query string of the `/changes` stream. `last_event_id` stands in for the
+/// `Last-Event-ID` header, which browsers cannot set on a first connection.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ChangeStreamQuery {
+    pub tutor_id: Option<i32>,
+    pub last_event_id: Option<i64>,
+}
@@ -1,8 +1,10 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDateTime, Utc};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Clone)]
+use crate::models::CoursePricing;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Course {
     pub course_id: i32,
     pub tutor_id: i32,
@@ -14,7 +16,106 @@ pub struct Course {
     pub course_price: Option<i32>,
     pub course_language: Option<String>,
     pub course_level: Option<String>,
+    /// When the course was first published.
     pub posted_time: Option<NaiveDateTime>,
+    /// One of the [`CourseStatus`] values. Missing from revisions older than the
+    /// workflow.
+    #[serde(default)]
+    pub course_status: String,
+    /// When a scheduled course goes live.
+    #[serde(default)]
+    pub publish_at: Option<DateTime<Utc>>,
+    /// When the first live session takes place, `None` for self-paced courses.
+    #[serde(default)]
+    pub first_session_at: Option<DateTime<Utc>>,
+}
+
+/// Where a course is in its lifecycle; only published courses are listed publicly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CourseStatus {
+    Draft,
+    InReview,
+    /// Approved, waiting for its `publish_at` time.
+    Scheduled,
+    Published,
+    Archived,
+}
+
+impl CourseStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Draft => "draft",
+            Self::InReview => "in_review",
+            Self::Scheduled => "scheduled",
+            Self::Published => "published",
+            Self::Archived => "archived",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        [
+            Self::Draft,
+            Self::InReview,
+            Self::Scheduled,
+            Self::Published,
+            Self::Archived,
+        ]
+        .into_iter()
+        .find(|candidate| candidate.as_str() == status)
+    }
+}
+
+/// The explicit moves between [`CourseStatus`]es.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CourseTransition {
+    /// Draft to in review.
+    Submit,
+    /// Back to draft from review or a pending schedule.
+    Reject,
+    /// Approves a course in review, or reschedules a scheduled one, to go live at
+    /// `publish_at` (now when left out).
+    Publish,
+    Archive,
+    /// Archived back to draft.
+    Reopen,
+}
+
+impl CourseTransition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Submit => "submit",
+            Self::Reject => "reject",
+            Self::Publish => "publish",
+            Self::Archive => "archive",
+            Self::Reopen => "reopen",
+        }
+    }
+
+    /// Statuses the transition may start from.
+    pub fn allowed_from(&self) -> &'static [CourseStatus] {
+        use CourseStatus::*;
+        match self {
+            Self::Submit => &[Draft],
+            Self::Reject => &[InReview, Scheduled],
+            Self::Publish => &[InReview, Scheduled],
+            Self::Archive => &[Draft, InReview, Scheduled, Published],
+            Self::Reopen => &[Archived],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PublishCourse {
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+/// `status` is a [`CourseStatus`] or `all`, the default, for operators listing courses.
+/// `lang` picks the locale to list them in, overriding `Accept-Language`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct CourseListQuery {
+    pub status: Option<String>,
+    pub lang: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -28,6 +129,11 @@ pub struct NewCourse {
     pub course_price: Option<i32>,
     pub course_language: Option<String>,
     pub course_level: Option<String>,
+    pub first_session_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub category_ids: Vec<i32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -40,4 +146,56 @@ pub struct UpdateCourse {
     pub course_price: Option<i32>,
     pub course_language: Option<String>,
     pub course_level: Option<String>,
+    pub first_session_at: Option<DateTime<Utc>>,
+    /// Replaces the course's categories when given.
+    pub category_ids: Option<Vec<i32>>,
+    /// Replaces the course's tags when given.
+    pub tags: Option<Vec<String>>,
+}
+
+/// A course together with the categories and tags it is filed under and its current price.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CourseDetails {
+    #[serde(flatten)]
+    pub course: Course,
+    pub category_ids: Vec<i32>,
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub pricing: CoursePricing,
+}
+
+/// `limit` defaults to 5; `exclude_same_tutor` leaves out the tutor's other courses.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SimilarCoursesQuery {
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub exclude_same_tutor: bool,
+}
+
+/// A published course and how similar it is to the requested one, from 0 to 1.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SimilarCourse {
+    #[serde(flatten)]
+    pub course: Course,
+    pub score: f64,
+}
+
+/// A field whose value differs from the previous revision.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: serde_json::Value,
+    pub to: serde_json::Value,
+}
+
+/// A stored version of a course and what changed since the one before it.
+#[derive(Debug, Serialize, Clone)]
+pub struct CourseRevision {
+    pub revision: i32,
+    pub actor: String,
+    /// The revision whose content this one restored, if any.
+    pub restored_from: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub course: serde_json::Value,
+    pub changes: Vec<FieldChange>,
 }
@@ -0,0 +1,150 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use std::fmt::Write;
+
+/// What the tutor has earned, from the ledger. `available` is what the next payout would
+/// pay; refunds made after a payout may leave it negative until later sales make up for
+/// them.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EarningsBalance {
+    pub tutor_id: i32,
+    pub currency: String,
+    /// What students paid for the tutor's items, before refunds.
+    pub gross_sales: i64,
+    /// What students got back.
+    pub refunds: i64,
+    /// The platform's fees on sales, less those handed back with refunds.
+    pub platform_fees: i64,
+    pub available: i64,
+    pub paid_out: i64,
+}
+
+/// Ledger transactions recorded from `from` up to but excluding `to`, either of which may
+/// be left out.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct StatementQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// One ledger transaction as the tutor sees it. Refunds and payouts have negative net
+/// amounts; `balance` is the available balance after the transaction.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StatementLine {
+    pub transaction_id: i64,
+    /// `sale`, `refund` or `payout`.
+    pub kind: String,
+    /// The order, refund or payout, by kind.
+    pub reference_id: i32,
+    pub recorded_at: DateTime<Utc>,
+    /// What the student paid, or got back when negative.
+    pub gross_amount: i64,
+    pub platform_fee: i64,
+    pub net_amount: i64,
+    pub paid_out: i64,
+    pub balance: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EarningsStatement {
+    pub tutor_id: i32,
+    pub currency: String,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// The available balance before the first line.
+    pub opening_balance: i64,
+    pub closing_balance: i64,
+    pub lines: Vec<StatementLine>,
+}
+
+impl EarningsStatement {
+    /// The statement's lines as CSV, with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "transaction_id,kind,reference_id,recorded_at,currency,gross_amount,platform_fee,net_amount,paid_out,balance\n",
+        );
+        for line in &self.lines {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{},{}",
+                line.transaction_id,
+                line.kind,
+                line.reference_id,
+                line.recorded_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+                self.currency,
+                line.gross_amount,
+                line.platform_fee,
+                line.net_amount,
+                line.paid_out,
+                line.balance,
+            );
+        }
+        csv
+    }
+}
+
+/// Earnings moved from the tutor's available balance to paid out by a payout batch.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Payout {
+    pub payout_id: i32,
+    pub tutor_id: i32,
+    pub amount: i32,
+    pub currency: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn statement_csv_has_a_row_per_line() {
+        let statement = EarningsStatement {
+            tutor_id: 1,
+            currency: "usd".to_string(),
+            from: None,
+            to: None,
+            opening_balance: 0,
+            closing_balance: 2780,
+            lines: vec![
+                StatementLine {
+                    transaction_id: 7,
+                    kind: "sale".to_string(),
+                    reference_id: 3,
+                    recorded_at: Utc.with_ymd_and_hms(2026, 5, 1, 9, 30, 0).unwrap(),
+                    gross_amount: 4200,
+                    platform_fee: 420,
+                    net_amount: 3780,
+                    paid_out: 0,
+                    balance: 3780,
+                },
+                StatementLine {
+                    transaction_id: 9,
+                    kind: "payout".to_string(),
+                    reference_id: 1,
+                    recorded_at: Utc.with_ymd_and_hms(2026, 5, 2, 0, 0, 0).unwrap(),
+                    gross_amount: 0,
+                    platform_fee: 0,
+                    net_amount: -1000,
+                    paid_out: 1000,
+                    balance: 2780,
+                },
+            ],
+        };
+        let csv = statement.to_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(3, rows.len());
+        assert!(rows[0].starts_with("transaction_id,kind,"));
+        assert_eq!(
+            "7,sale,3,2026-05-01T09:30:00Z,usd,4200,420,3780,0,3780",
+            rows[1]
+        );
+        assert_eq!(
+            "9,payout,1,2026-05-02T00:00:00Z,usd,0,0,-1000,1000,2780",
+            rows[2]
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A student taking a course. Students are identified like audit log actors.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Enrollment {
+    pub course_id: i32,
+    pub student: String,
+    pub enrolled_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// The order that paid for the enrollment, `None` for free courses.
+    pub order_id: Option<i32>,
+}
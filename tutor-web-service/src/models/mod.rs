@@ -1,5 +1,29 @@
+mod audit;
+mod bundle;
+mod category;
+mod change;
 mod course;
+mod earnings;
+mod enrollment;
+mod order;
+mod prerequisite;
+mod promotion;
+mod refund;
+mod translation;
 mod tutor;
+mod webhook;
 
+pub use audit::*;
+pub use bundle::*;
+pub use category::*;
+pub use change::*;
 pub use course::*;
+pub use earnings::*;
+pub use enrollment::*;
+pub use order::*;
+pub use prerequisite::*;
+pub use promotion::*;
+pub use refund::*;
+pub use translation::*;
 pub use tutor::*;
+pub use webhook::*;
@@ -0,0 +1,78 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::PriceBreakdown;
+
+/// A course or bundle the caller means to buy.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CartItem {
+    pub course_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub tutor_id: i32,
+    pub item_name: String,
+    #[serde(flatten)]
+    pub price: PriceBreakdown,
+    /// The courses buying the item enrolls in.
+    pub course_ids: Vec<i32>,
+    pub added_at: DateTime<Utc>,
+}
+
+/// Amounts are in the minor unit of `currency`, e.g. cents.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Cart {
+    pub items: Vec<CartItem>,
+    /// The coupon applied to the cart, which may no longer take anything off.
+    pub coupon_code: Option<String>,
+    pub original_amount: i64,
+    pub discount_amount: i64,
+    pub total_amount: i64,
+    pub currency: String,
+}
+
+/// A checkout, `pending` until its payment succeeds (`paid`) or fails (`failed`).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Order {
+    pub order_id: i32,
+    pub student: String,
+    pub order_status: String,
+    pub original_amount: i32,
+    pub discount_amount: i32,
+    pub total_amount: i32,
+    pub currency: String,
+    /// `None` once the coupon is deleted, unlike `coupon_code`.
+    pub coupon_id: Option<i32>,
+    pub coupon_code: Option<String>,
+    pub payment_id: Option<String>,
+    /// Returned to the student through refunds so far.
+    pub refunded_amount: i32,
+    pub created_at: DateTime<Utc>,
+    pub paid_at: Option<DateTime<Utc>>,
+}
+
+/// A bought course or bundle, as it was at checkout.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderItem {
+    pub line_no: i32,
+    pub course_id: Option<i32>,
+    pub bundle_id: Option<i32>,
+    pub tutor_id: i32,
+    pub item_name: String,
+    #[serde(flatten)]
+    pub price: PriceBreakdown,
+    pub course_ids: Vec<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderDetails {
+    #[serde(flatten)]
+    pub order: Order,
+    pub items: Vec<OrderItem>,
+}
+
+/// A new order, with where to complete its payment when the provider has such a page.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Checkout {
+    #[serde(flatten)]
+    pub order: OrderDetails,
+    pub checkout_url: Option<String>,
+}
@@ -0,0 +1,11 @@
+use crate::models::Course;
+
+use serde::{Deserialize, Serialize};
+
+/// A course in another's prerequisite chain, with the courses it directly requires.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrerequisiteCourse {
+    #[serde(flatten)]
+    pub course: Course,
+    pub prerequisite_ids: Vec<i32>,
+}
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// What something costs before and after its discounts, in the currency's minor unit.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PriceBreakdown {
+    pub original_price: i32,
+    pub discount: i32,
+    pub final_price: i32,
+}
+
+/// A course's price right now.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct CoursePricing {
+    #[serde(flatten)]
+    pub price: PriceBreakdown,
+    /// When the sale lowering the price ends, if the course is on sale.
+    pub sale_ends_at: Option<DateTime<Utc>>,
+}
+
+/// The course selling for `sale_price` from `starts_at` until `ends_at`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CourseSale {
+    pub sale_id: i32,
+    pub course_id: i32,
+    pub sale_price: i32,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+}
+
+/// `starts_at` defaults to now.
+#[derive(Debug, Deserialize, Clone)]
+pub struct NewCourseSale {
+    pub sale_price: i32,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: DateTime<Utc>,
+}
+
+/// A discount code for one of the tutor's courses, or for everything they sell when
+/// `course_id` is `None`. Exactly one of `percent_off` and `amount_off` is set.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Coupon {
+    pub coupon_id: i32,
+    pub code: String,
+    pub tutor_id: i32,
+    pub course_id: Option<i32>,
+    pub percent_off: Option<i32>,
+    /// Taken once off the items the coupon applies to, not off each of them.
+    pub amount_off: Option<i32>,
+    /// Unlimited when `None`.
+    pub max_redemptions: Option<i32>,
+    /// Orders placed with the coupon, not counting those whose payment failed.
+    pub redemptions: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NewCoupon {
+    pub code: String,
+    pub course_id: Option<i32>,
+    pub percent_off: Option<i32>,
+    pub amount_off: Option<i32>,
+    pub max_redemptions: Option<i32>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApplyCoupon {
+    pub code: String,
+}
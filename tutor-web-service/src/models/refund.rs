@@ -0,0 +1,73 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How much of what the tutor sold students get back when they cancel. Refunds come in
+/// full within `full_refund_days` of paying, and at `partial_refund_percent` after that
+/// while none of the item's courses has had its first session. Self-paced courses, which
+/// have no sessions, count as started.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CancellationPolicy {
+    pub tutor_id: i32,
+    pub full_refund_days: i32,
+    pub partial_refund_percent: i32,
+    /// `None` for tutors who kept the default policy.
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PutCancellationPolicy {
+    pub full_refund_days: i32,
+    pub partial_refund_percent: i32,
+}
+
+/// A refund the student asked for, `requested` until an operator rejects it or approves
+/// it, making it `refunding` while the provider pays it back and `refunded` after.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Refund {
+    pub refund_id: i32,
+    pub order_id: i32,
+    pub refund_status: String,
+    pub reason: Option<String>,
+    /// What the cancellation policies granted when the refund was requested.
+    pub amount: i32,
+    pub provider_refund_id: Option<String>,
+    pub requested_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub decision_note: Option<String>,
+}
+
+/// An order item the refund cancels, and what the student gets back for it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RefundItem {
+    pub line_no: i32,
+    pub amount: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RefundDetails {
+    #[serde(flatten)]
+    pub refund: Refund,
+    pub items: Vec<RefundItem>,
+}
+
+/// Cancels the given order items, or all of those not refunded yet when `line_nos` is
+/// left out.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct NewRefund {
+    pub line_nos: Option<Vec<i32>>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RefundDecision {
+    pub note: Option<String>,
+}
+
+/// Refunds in `status`, or all of them when it is `None`, newest first; `before_id`
+/// continues a page.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RefundListQuery {
+    pub status: Option<String>,
+    pub before_id: Option<i32>,
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A course's name and description in a locale other than its `course_language`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CourseTranslation {
+    pub course_id: i32,
+    /// Lowercase BCP 47 tag, e.g. `pt-br`.
+    pub locale: String,
+    pub course_name: String,
+    pub course_description: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PutCourseTranslation {
+    pub course_name: String,
+    pub course_description: Option<String>,
+}
+
+/// `lang` picks the locale to read courses in, overriding `Accept-Language`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct LocaleQuery {
+    pub lang: Option<String>,
+}
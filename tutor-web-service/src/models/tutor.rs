@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Tutor {
     pub tutor_id: i32,
     pub tutor_name: String,
@@ -21,3 +21,85 @@ pub struct UpdateTutor {
     pub tutor_pic_url: Option<String>,
     pub tutor_profile: Option<String>,
 }
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Thumbnail {
+    pub size: String,
+    pub width: u32,
+    pub height: u32,
+    pub url: String,
+}
+
+/// Response to a picture upload; `tutor.tutor_pic_url` points at one of the thumbnails.
+#[derive(Debug, Serialize, Clone)]
+pub struct TutorPicture {
+    pub tutor: Tutor,
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+/// Order of the tutor directory. `courses` and `rating` list the highest first.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TutorSort {
+    #[default]
+    Name,
+    Courses,
+    Rating,
+}
+
+impl TutorSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Courses => "courses",
+            Self::Rating => "rating",
+        }
+    }
+}
+
+/// Tutors whose name or profile contains every word of `q` and who have a published
+/// course in `category_id` (or a subcategory), tagged `tag` and taught in `language`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct TutorDirectoryQuery {
+    pub q: Option<String>,
+    pub category_id: Option<i32>,
+    pub tag: Option<String>,
+    pub language: Option<String>,
+    pub min_rating: Option<f64>,
+    #[serde(default)]
+    pub sort: TutorSort,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// A tutor as listed in the directory, with what their published courses cover.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TutorListing {
+    #[serde(flatten)]
+    pub tutor: Tutor,
+    pub course_count: i64,
+    pub category_ids: Vec<i32>,
+    pub languages: Vec<String>,
+    pub average_rating: Option<f64>,
+    pub rating_count: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TutorDirectory {
+    pub total: i64,
+    pub tutors: Vec<TutorListing>,
+}
+
+/// A rating from 1 to 5; each client has one rating per tutor, replaced when they rate
+/// again.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RateTutor {
+    pub rating: i16,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TutorRating {
+    pub tutor_id: i32,
+    pub average_rating: Option<f64>,
+    pub rating_count: i64,
+}
@@ -0,0 +1,84 @@
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+/// Event types subscriptions may ask for; `tutor.*`, `course.*` and `*` match several.
+pub const WEBHOOK_EVENT_TYPES: [&str; 9] = [
+    "*",
+    "tutor.*",
+    "tutor.created",
+    "tutor.updated",
+    "tutor.deleted",
+    "course.*",
+    "course.created",
+    "course.updated",
+    "course.deleted",
+];
+
+#[derive(Debug, Serialize, Clone)]
+pub struct WebhookSubscription {
+    pub subscription_id: i32,
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    /// Only returned when the subscription is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NewWebhookSubscription {
+    pub url: String,
+    /// Generated when left out.
+    pub secret: Option<String>,
+    pub event_types: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct UpdateWebhookSubscription {
+    pub url: Option<String>,
+    pub event_types: Option<Vec<String>>,
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct WebhookDelivery {
+    pub delivery_id: i64,
+    pub event_id: i64,
+    pub event_type: String,
+    pub subscription_id: i32,
+    /// `pending`, `succeeded` or `failed`.
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct WebhookAttempt {
+    pub attempt_id: i64,
+    pub attempted_at: DateTime<Utc>,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+/// A delivery together with the event sent and every attempt made so far.
+#[derive(Debug, Serialize, Clone)]
+pub struct WebhookDeliveryDetail {
+    #[serde(flatten)]
+    pub delivery: WebhookDelivery,
+    pub payload: serde_json::Value,
+    pub attempts_log: Vec<WebhookAttempt>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct WebhookDeliveryQuery {
+    pub status: Option<String>,
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
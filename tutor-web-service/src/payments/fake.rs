@@ -0,0 +1,116 @@
+use actix_web::http::header::HeaderMap;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{Payment, PaymentEvent, PaymentProvider};
+use crate::errors::EzyTutorError;
+use crate::models::Order;
+use crate::webhooks::SIGNATURE_HEADER;
+
+/// How far a notification's timestamp may be from now, in seconds.
+const SIGNATURE_TOLERANCE_SECS: i64 = 300;
+
+/// Collects nothing: its payments are settled only by [`PaymentEvent`]s POSTed as JSON,
+/// signed like our own webhooks with the shared secret, and its refunds succeed at once.
+pub struct FakePaymentProvider {
+    secret: String,
+}
+
+impl FakePaymentProvider {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            secret: secret.to_string(),
+        }
+    }
+
+    fn mac(&self, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts any key length");
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        mac
+    }
+
+    /// A notification settling a payment as `event` says, with its signature header.
+    #[cfg(test)]
+    pub fn notification(&self, event: &PaymentEvent) -> (String, Vec<u8>) {
+        let body = serde_json::to_vec(event).unwrap();
+        let timestamp = Utc::now().timestamp();
+        let mac = self.mac(timestamp, &body).finalize().into_bytes();
+        (format!("t={},v1={}", timestamp, hex::encode(mac)), body)
+    }
+}
+
+#[async_trait]
+impl PaymentProvider for FakePaymentProvider {
+    async fn create_payment(&self, order: &Order) -> Result<Payment, EzyTutorError> {
+        Ok(Payment {
+            payment_id: format!("fake_order_{}", order.order_id),
+            checkout_url: None,
+        })
+    }
+
+    async fn refund_payment(
+        &self,
+        refund_id: i32,
+        _payment_id: &str,
+        _amount: i32,
+    ) -> Result<String, EzyTutorError> {
+        Ok(format!("fake_refund_{}", refund_id))
+    }
+
+    fn parse_event(&self, headers: &HeaderMap, body: &[u8]) -> Result<PaymentEvent, EzyTutorError> {
+        if self.secret.is_empty() {
+            return Err(EzyTutorError::Unauthorized);
+        }
+        let (timestamp, sent) = headers
+            .get(SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("t="))
+            .and_then(|value| value.split_once(",v1="))
+            .and_then(|(timestamp, sent)| Some((timestamp.parse::<i64>().ok()?, sent)))
+            .ok_or(EzyTutorError::Unauthorized)?;
+        let sent = hex::decode(sent).map_err(|_| EzyTutorError::Unauthorized)?;
+        if (Utc::now().timestamp() - timestamp).abs() > SIGNATURE_TOLERANCE_SECS
+            || self.mac(timestamp, body).verify_slice(&sent).is_err()
+        {
+            return Err(EzyTutorError::Unauthorized);
+        }
+
+        serde_json::from_slice(body)
+            .map_err(|err| EzyTutorError::InvalidInput(format!("Invalid payment event: {}", err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payments::PaymentOutcome;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    #[test]
+    fn accepts_only_signed_events() {
+        let provider = FakePaymentProvider::new("payments-0123456789");
+        let event = PaymentEvent {
+            payment_id: "fake_1".to_string(),
+            outcome: PaymentOutcome::Succeeded,
+        };
+        let (signature, body) = provider.notification(&event);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static(SIGNATURE_HEADER),
+            HeaderValue::from_str(&signature).unwrap(),
+        );
+        assert_eq!(event, provider.parse_event(&headers, &body).unwrap());
+
+        let forged = br#"{"payment_id":"fake_2","outcome":"succeeded"}"#;
+        assert!(provider.parse_event(&headers, forged).is_err());
+        let other = FakePaymentProvider::new("another-0123456789");
+        assert!(other.parse_event(&headers, &body).is_err());
+        assert!(provider.parse_event(&HeaderMap::new(), &body).is_err());
+    }
+}
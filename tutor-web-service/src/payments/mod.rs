@@ -0,0 +1,65 @@
+use actix_web::http::header::HeaderMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use std::sync::Arc;
+
+use crate::config::{PaymentProviderKind, PaymentsConfig};
+use crate::errors::EzyTutorError;
+use crate::models::Order;
+
+mod fake;
+
+pub use fake::*;
+
+/// A payment started for an order, settled later by a [`PaymentEvent`].
+#[derive(Debug, Clone)]
+pub struct Payment {
+    /// The provider's id for the payment, unique across orders.
+    pub payment_id: String,
+    /// Where the student completes the payment, for providers with such a page.
+    pub checkout_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentOutcome {
+    Succeeded,
+    Failed,
+}
+
+/// The provider telling us how a payment ended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentEvent {
+    pub payment_id: String,
+    pub outcome: PaymentOutcome,
+}
+
+/// Collects payments for orders.
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// Starts collecting the order's `total_amount`. The order id is the idempotency key:
+    /// asking again for the same order returns the payment already started.
+    async fn create_payment(&self, order: &Order) -> Result<Payment, EzyTutorError>;
+
+    /// Returns `amount` of a succeeded payment to the student, giving the provider's id
+    /// for the refund. The refund id is the idempotency key: refunding again for the same
+    /// refund returns the refund already made.
+    async fn refund_payment(
+        &self,
+        refund_id: i32,
+        payment_id: &str,
+        amount: i32,
+    ) -> Result<String, EzyTutorError>;
+
+    /// Authenticates and decodes a notification the provider POSTed to us, failing with
+    /// `Unauthorized` when it cannot be shown to come from the provider.
+    fn parse_event(&self, headers: &HeaderMap, body: &[u8]) -> Result<PaymentEvent, EzyTutorError>;
+}
+
+pub fn from_config(config: &PaymentsConfig) -> Arc<dyn PaymentProvider> {
+    match config.provider {
+        PaymentProviderKind::Fake => Arc::new(FakePaymentProvider::new(&config.webhook_secret)),
+    }
+}
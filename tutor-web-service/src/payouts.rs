@@ -0,0 +1,33 @@
+use sqlx::postgres::PgPool;
+
+use crate::config::EarningsConfig;
+use crate::errors::EzyTutorError;
+use crate::store;
+
+/// Pays tutors their available balances every `payout_interval_secs`. Runs until the pool
+/// is closed.
+pub async fn pay_out_periodically(pg_pool: PgPool, config: EarningsConfig, currency: String) {
+    tracing::info!("Paying out tutor earnings");
+    loop {
+        // Waits first, so restarting the server does not pay out early, and stops waiting
+        // as soon as the pool is closed rather than holding up shutdown.
+        let wait = actix_web::rt::time::sleep(config.payout_interval());
+        if pg_pool.close_event().do_until(wait).await.is_err() {
+            return;
+        }
+        match store::pay_out_earnings(&pg_pool, &currency, i64::from(config.min_payout)).await {
+            Ok(payouts) => {
+                for payout in &payouts {
+                    tracing::info!(
+                        tutor_id = payout.tutor_id,
+                        payout_id = payout.payout_id,
+                        amount = payout.amount,
+                        "Paid out tutor earnings"
+                    );
+                }
+            }
+            Err(EzyTutorError::DbError(sqlx::Error::PoolClosed)) => return,
+            Err(err) => tracing::warn!(error = %err, "Could not pay out tutor earnings"),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use actix_multipart::{Multipart, MultipartError};
+use actix_web::web::{self, Bytes, BytesMut};
+
+use futures_util::StreamExt;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageError, ImageFormat, ImageReader, Limits};
+use uuid::Uuid;
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use crate::config::PicturesConfig;
+use crate::errors::EzyTutorError;
+use crate::models::Thumbnail;
+use crate::storage::ObjectStorage;
+
+/// Multipart field carrying the uploaded picture.
+pub const PICTURE_FIELD: &str = "picture";
+
+/// Square thumbnails generated for every upload, by name and edge length in pixels.
+pub const THUMBNAIL_SIZES: [(&str, u32); 3] = [("small", 64), ("medium", 256), ("large", 512)];
+
+/// The thumbnail `tutor_pic_url` points at.
+const PROFILE_SIZE: &str = "medium";
+const JPEG_QUALITY: u8 = 85;
+
+/// Validates, resizes and stores tutor pictures.
+pub struct Pictures {
+    storage: Arc<dyn ObjectStorage>,
+    max_bytes: usize,
+    max_dimension: u32,
+}
+
+impl Pictures {
+    pub fn new(config: &PicturesConfig, storage: Arc<dyn ObjectStorage>) -> Self {
+        Self {
+            storage,
+            max_bytes: config.max_bytes,
+            max_dimension: config.max_dimension,
+        }
+    }
+
+    /// Reads the `picture` field of a multipart upload, refusing it as soon as it grows
+    /// past the size limit.
+    pub async fn read_upload(&self, mut multipart: Multipart) -> Result<Bytes, EzyTutorError> {
+        while let Some(field) = multipart.next().await {
+            let mut field = field.map_err(invalid_multipart)?;
+            if field.name() != Some(PICTURE_FIELD) {
+                continue;
+            }
+
+            let mut bytes = BytesMut::new();
+            while let Some(chunk) = field.next().await {
+                let chunk = chunk.map_err(invalid_multipart)?;
+                if bytes.len() + chunk.len() > self.max_bytes {
+                    return Err(EzyTutorError::PayloadTooLarge(format!(
+                        "Picture must not exceed {} bytes",
+                        self.max_bytes
+                    )));
+                }
+                bytes.extend_from_slice(&chunk);
+            }
+            return Ok(bytes.freeze());
+        }
+        Err(EzyTutorError::InvalidInput(format!(
+            "Expected a multipart field named {:?}",
+            PICTURE_FIELD
+        )))
+    }
+
+    /// Stores every thumbnail of `upload` under a fresh version, so earlier pictures stay
+    /// valid until they are discarded.
+    pub async fn store_tutor_picture(
+        &self,
+        tutor_id: i32,
+        upload: Bytes,
+    ) -> Result<Vec<Thumbnail>, EzyTutorError> {
+        let format = sniff(&upload)?;
+        let max_dimension = self.max_dimension;
+        let images = web::block(move || thumbnails(&upload, format, max_dimension))
+            .await
+            .map_err(actix_web::Error::from)?
+            .map_err(|err| match err {
+                ImageError::Limits(_) => EzyTutorError::PayloadTooLarge(format!(
+                    "Picture must not exceed {0}x{0} pixels",
+                    max_dimension
+                )),
+                ImageError::Encoding(err) => {
+                    actix_web::error::ErrorInternalServerError(err.to_string()).into()
+                }
+                err => {
+                    EzyTutorError::InvalidInput(format!("Picture could not be decoded: {}", err))
+                }
+            })?;
+
+        let version = Uuid::new_v4().simple().to_string();
+        let mut stored = Vec::with_capacity(images.len());
+        for (size, edge, bytes) in images {
+            let key = thumbnail_key(tutor_id, &version, size);
+            if let Err(err) = self.storage.put(&key, "image/jpeg", bytes).await {
+                self.discard(tutor_id, &version).await;
+                return Err(err);
+            }
+            stored.push(Thumbnail {
+                size: size.to_string(),
+                width: edge,
+                height: edge,
+                url: self.storage.url(&key),
+            });
+        }
+        Ok(stored)
+    }
+
+    /// Deletes the thumbnails behind `url` if it points at a picture stored for the tutor;
+    /// URLs entered by hand are left alone. Failures are logged, not returned.
+    pub async fn discard_url(&self, tutor_id: i32, url: &str) {
+        let prefix = self.storage.url(&format!("tutors/{}/", tutor_id));
+        let version = url
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.split('/').next());
+        if let Some(version) = version {
+            self.discard(tutor_id, version).await;
+        }
+    }
+
+    async fn discard(&self, tutor_id: i32, version: &str) {
+        for (size, _) in THUMBNAIL_SIZES {
+            let key = thumbnail_key(tutor_id, version, size);
+            if let Err(err) = self.storage.delete(&key).await {
+                tracing::warn!(key, error = %err, "Could not delete picture");
+            }
+        }
+    }
+}
+
+/// The URL to save as `tutor_pic_url` for freshly stored thumbnails.
+pub fn profile_url(thumbnails: &[Thumbnail]) -> &str {
+    thumbnails
+        .iter()
+        .find(|thumbnail| thumbnail.size == PROFILE_SIZE)
+        .map(|thumbnail| thumbnail.url.as_str())
+        .unwrap_or_default()
+}
+
+fn thumbnail_key(tutor_id: i32, version: &str, size: &str) -> String {
+    format!("tutors/{}/{}/{}.jpg", tutor_id, version, size)
+}
+
+fn invalid_multipart(err: MultipartError) -> EzyTutorError {
+    EzyTutorError::InvalidInput(format!("Invalid multipart upload: {}", err))
+}
+
+/// Identifies the image format from its leading bytes; the declared content type is
+/// not trusted.
+fn sniff(bytes: &[u8]) -> Result<ImageFormat, EzyTutorError> {
+    match infer::get(bytes).map(|kind| kind.mime_type()) {
+        Some("image/jpeg") => Ok(ImageFormat::Jpeg),
+        Some("image/png") => Ok(ImageFormat::Png),
+        Some("image/webp") => Ok(ImageFormat::WebP),
+        _ => Err(EzyTutorError::UnsupportedMediaType(
+            "Picture must be a JPEG, PNG or WebP image".to_string(),
+        )),
+    }
+}
+
+fn thumbnails(
+    bytes: &[u8],
+    format: ImageFormat,
+    max_dimension: u32,
+) -> Result<Vec<(&'static str, u32, Bytes)>, ImageError> {
+    let mut reader = ImageReader::new(Cursor::new(bytes));
+    reader.set_format(format);
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(max_dimension);
+    limits.max_image_height = Some(max_dimension);
+    reader.limits(limits);
+    let image = reader.decode()?;
+
+    THUMBNAIL_SIZES
+        .iter()
+        .map(|&(size, edge)| {
+            let thumbnail = image.resize_to_fill(edge, edge, FilterType::Lanczos3);
+            let mut jpeg = Vec::new();
+            DynamicImage::ImageRgb8(thumbnail.to_rgb8())
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))?;
+            Ok((size, edge, Bytes::from(jpeg)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = ImageBuffer::from_pixel(width, height, Rgba([200u8, 80, 20, 255]));
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn sniffs_content_instead_of_trusting_names() {
+        assert_eq!(ImageFormat::Png, sniff(&png(1, 1)).unwrap());
+        assert!(matches!(
+            sniff(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"),
+            Err(EzyTutorError::UnsupportedMediaType(_))
+        ));
+    }
+
+    #[test]
+    fn resizes_to_square_jpeg_thumbnails() {
+        let thumbnails = thumbnails(&png(800, 600), ImageFormat::Png, 1000).unwrap();
+        assert_eq!(THUMBNAIL_SIZES.len(), thumbnails.len());
+        for (size, edge, bytes) in thumbnails {
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert_eq!(
+                (edge, edge),
+                (decoded.width(), decoded.height()),
+                "{}",
+                size
+            );
+            assert_eq!(
+                Some("image/jpeg"),
+                infer::get(&bytes).map(|kind| kind.mime_type())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_dimensions() {
+        assert!(matches!(
+            thumbnails(&png(300, 20), ImageFormat::Png, 200),
+            Err(ImageError::Limits(_))
+        ));
+    }
+}
@@ -0,0 +1,228 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::errors::EzyTutorError;
+use crate::models::{CancellationPolicy, Coupon, PriceBreakdown};
+
+/// The price of something listed at `list_price`, lowered to `sale_price` while it is on
+/// sale. A sale never raises a price.
+pub fn sale_price(list_price: i32, sale_price: Option<i32>) -> PriceBreakdown {
+    let final_price = sale_price.map_or(list_price, |sale_price| sale_price.min(list_price));
+    PriceBreakdown {
+        original_price: list_price,
+        discount: list_price - final_price,
+        final_price,
+    }
+}
+
+/// Whether the coupon takes anything off an item the tutor sells, `course_id` being the
+/// course it is or `None` for a bundle.
+pub fn coupon_applies(coupon: &Coupon, tutor_id: i32, course_id: Option<i32>) -> bool {
+    coupon.tutor_id == tutor_id && (coupon.course_id.is_none() || coupon.course_id == course_id)
+}
+
+/// Fails with `Conflict` once the coupon has expired or been used up.
+pub fn check_redeemable(coupon: &Coupon, now: DateTime<Utc>) -> Result<(), EzyTutorError> {
+    if coupon
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= now)
+    {
+        return Err(EzyTutorError::Conflict(format!(
+            "Coupon {} has expired",
+            coupon.code
+        )));
+    }
+    if coupon
+        .max_redemptions
+        .is_some_and(|max_redemptions| coupon.redemptions >= max_redemptions)
+    {
+        return Err(EzyTutorError::Conflict(format!(
+            "Coupon {} has been used up",
+            coupon.code
+        )));
+    }
+
+    Ok(())
+}
+
+/// Takes the coupon off prices it applies to, on top of any sale, and returns how much it
+/// took off in all. A percentage comes off each price, rounded down, while a fixed amount
+/// comes off the prices in turn until it is used up; no price drops below zero.
+pub fn apply_coupon<'a>(
+    coupon: &Coupon,
+    prices: impl IntoIterator<Item = &'a mut PriceBreakdown>,
+) -> i64 {
+    let mut amount_left = coupon.amount_off.unwrap_or(0);
+    let mut taken_off = 0;
+    for price in prices {
+        let off = match coupon.percent_off {
+            Some(percent_off) => {
+                (i64::from(price.final_price) * i64::from(percent_off) / 100) as i32
+            }
+            None => {
+                let off = amount_left.min(price.final_price);
+                amount_left -= off;
+                off
+            }
+        };
+        price.discount += off;
+        price.final_price -= off;
+        taken_off += i64::from(off);
+    }
+    taken_off
+}
+
+/// What the policy refunds of an item paid `price` for at `paid_at` and cancelled at
+/// `now`. `first_session_at` is the earliest first session among the item's courses, or
+/// `None` when any of them is self-paced.
+pub fn refund_amount(
+    policy: &CancellationPolicy,
+    price: i32,
+    paid_at: DateTime<Utc>,
+    first_session_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> i32 {
+    if now < paid_at + Duration::days(i64::from(policy.full_refund_days)) {
+        price
+    } else if first_session_at.is_some_and(|first_session_at| now < first_session_at) {
+        (i64::from(price) * i64::from(policy.partial_refund_percent) / 100) as i32
+    } else {
+        0
+    }
+}
+
+/// The platform's share of an item sold for `price`, rounded down in the tutor's favour.
+pub fn platform_fee(price: i32, platform_fee_percent: u32) -> i32 {
+    (i64::from(price) * i64::from(platform_fee_percent) / 100) as i32
+}
+
+/// The part of the platform's `fee` on an item sold for `price` handed back when `refunded`
+/// of the price is, so the tutor and the platform bear a partial refund in proportion.
+pub fn refunded_fee(fee: i32, price: i32, refunded: i32) -> i32 {
+    if price == 0 {
+        return 0;
+    }
+    (i64::from(fee) * i64::from(refunded) / i64::from(price)) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coupon(course_id: Option<i32>, percent_off: Option<i32>, amount_off: Option<i32>) -> Coupon {
+        Coupon {
+            coupon_id: 1,
+            code: "SPRING".to_string(),
+            tutor_id: 1,
+            course_id,
+            percent_off,
+            amount_off,
+            max_redemptions: None,
+            redemptions: 0,
+            expires_at: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn sales_only_lower_prices() {
+        assert_eq!(
+            PriceBreakdown {
+                original_price: 1000,
+                discount: 250,
+                final_price: 750,
+            },
+            sale_price(1000, Some(750))
+        );
+        assert_eq!(0, sale_price(1000, Some(1200)).discount);
+        assert_eq!(1000, sale_price(1000, None).final_price);
+    }
+
+    #[test]
+    fn percent_off_comes_off_each_price() {
+        let mut prices = [sale_price(999, None), sale_price(1000, Some(500))];
+        assert_eq!(
+            374,
+            apply_coupon(&coupon(None, Some(25), None), &mut prices)
+        );
+        assert_eq!((249, 750), (prices[0].discount, prices[0].final_price));
+        assert_eq!((625, 375), (prices[1].discount, prices[1].final_price));
+    }
+
+    #[test]
+    fn amount_off_is_spread_until_used_up() {
+        let mut prices = [sale_price(300, None), sale_price(1000, None)];
+        assert_eq!(
+            500,
+            apply_coupon(&coupon(None, None, Some(500)), &mut prices)
+        );
+        assert_eq!([0, 800], prices.map(|price| price.final_price));
+
+        let mut prices = [sale_price(300, None)];
+        assert_eq!(
+            300,
+            apply_coupon(&coupon(None, None, Some(500)), &mut prices)
+        );
+        assert_eq!(0, prices[0].final_price);
+    }
+
+    #[test]
+    fn course_coupons_apply_to_their_course_only() {
+        let tutor_wide = coupon(None, Some(10), None);
+        assert!(coupon_applies(&tutor_wide, 1, Some(7)));
+        assert!(coupon_applies(&tutor_wide, 1, None));
+        assert!(!coupon_applies(&tutor_wide, 2, Some(7)));
+
+        let course = coupon(Some(7), Some(10), None);
+        assert!(coupon_applies(&course, 1, Some(7)));
+        assert!(!coupon_applies(&course, 1, Some(8)));
+        assert!(!coupon_applies(&course, 1, None));
+    }
+
+    #[test]
+    fn expired_and_used_up_coupons_are_not_redeemable() {
+        let now = Utc::now();
+        let mut spring = coupon(None, Some(10), None);
+        assert!(check_redeemable(&spring, now).is_ok());
+        spring.expires_at = Some(now - Duration::minutes(1));
+        assert!(check_redeemable(&spring, now).is_err());
+
+        spring.expires_at = Some(now + Duration::days(1));
+        spring.max_redemptions = Some(2);
+        spring.redemptions = 1;
+        assert!(check_redeemable(&spring, now).is_ok());
+        spring.redemptions = 2;
+        assert!(check_redeemable(&spring, now).is_err());
+    }
+
+    #[test]
+    fn refunds_follow_the_cancellation_policy() {
+        let policy = CancellationPolicy {
+            tutor_id: 1,
+            full_refund_days: 14,
+            partial_refund_percent: 50,
+            updated_at: None,
+        };
+        let now = Utc::now();
+        let paid_at = now - Duration::days(20);
+        let next_week = Some(now + Duration::days(7));
+        assert_eq!(
+            1000,
+            refund_amount(&policy, 1000, now - Duration::days(3), None, now)
+        );
+        assert_eq!(500, refund_amount(&policy, 1000, paid_at, next_week, now));
+        let last_week = Some(now - Duration::days(7));
+        assert_eq!(0, refund_amount(&policy, 1000, paid_at, last_week, now));
+        assert_eq!(0, refund_amount(&policy, 1000, paid_at, None, now));
+    }
+
+    #[test]
+    fn refunds_hand_back_fees_in_proportion() {
+        assert_eq!(420, platform_fee(4200, 10));
+        assert_eq!(99, platform_fee(999, 10));
+        assert_eq!(0, platform_fee(4200, 0));
+
+        assert_eq!(420, refunded_fee(420, 4200, 4200));
+        assert_eq!(210, refunded_fee(420, 4200, 2100));
+        assert_eq!(0, refunded_fee(0, 0, 0));
+    }
+}
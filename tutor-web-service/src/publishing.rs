@@ -0,0 +1,38 @@
+use actix_web::web;
+
+use sqlx::postgres::PgPool;
+
+use crate::config::PublishingConfig;
+use crate::errors::EzyTutorError;
+use crate::state::AppState;
+use crate::store;
+
+/// Publishes scheduled courses once their `publish_at` has passed. Runs until the pool is
+/// closed.
+pub async fn publish_scheduled_courses(
+    pg_pool: PgPool,
+    app_state: web::Data<AppState>,
+    config: PublishingConfig,
+) {
+    tracing::info!("Publishing scheduled courses");
+    loop {
+        match store::publish_due_courses(&pg_pool, i64::from(config.batch_size)).await {
+            Ok(published) => {
+                for course in &published {
+                    tracing::info!(
+                        tutor_id = course.tutor_id,
+                        course_id = course.course_id,
+                        "Published scheduled course"
+                    );
+                    app_state.cache.invalidate_courses(course.tutor_id);
+                }
+                if published.len() == config.batch_size as usize {
+                    continue;
+                }
+            }
+            Err(EzyTutorError::DbError(sqlx::Error::PoolClosed)) => return,
+            Err(err) => tracing::warn!(error = %err, "Could not publish scheduled courses"),
+        }
+        actix_web::rt::time::sleep(config.interval()).await;
+    }
+}
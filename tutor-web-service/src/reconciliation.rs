@@ -0,0 +1,37 @@
+use sqlx::postgres::PgPool;
+
+use std::sync::Arc;
+
+use crate::config::PaymentsConfig;
+use crate::errors::EzyTutorError;
+use crate::payments::PaymentProvider;
+use crate::store;
+
+/// Most orders looked up with the provider at once.
+const BATCH_SIZE: i64 = 50;
+
+/// Attaches payments that checkout started but could not attach to their orders, every
+/// `reconcile_interval_secs`. Runs until the pool is closed.
+pub async fn reconcile_checkouts_periodically(
+    pg_pool: PgPool,
+    provider: Arc<dyn PaymentProvider>,
+    config: PaymentsConfig,
+) {
+    tracing::info!("Reconciling checkouts");
+    loop {
+        let wait = actix_web::rt::time::sleep(config.reconcile_interval());
+        if pg_pool.close_event().do_until(wait).await.is_err() {
+            return;
+        }
+        let age = config.reconcile_after();
+        match store::reconcile_checkouts(&pg_pool, provider.as_ref(), age, BATCH_SIZE).await {
+            Ok(order_ids) => {
+                for order_id in order_ids {
+                    tracing::info!(order_id, "Attached payment to order");
+                }
+            }
+            Err(EzyTutorError::DbError(sqlx::Error::PoolClosed)) => return,
+            Err(err) => tracing::warn!(error = %err, "Could not reconcile checkouts"),
+        }
+    }
+}
@@ -1,32 +1,345 @@
 use crate::handlers::*;
 
+use crate::config::{PicturesConfig, StorageKind};
+use crate::middleware;
+
+use actix_cors::Cors;
+use actix_files::Files;
+use actix_web::middleware::from_fn;
 use actix_web::web::{self, ServiceConfig};
 
-pub fn general_routes(cfg: &mut ServiceConfig) {
-    cfg.route("/health", web::get().to(health_check_handler));
+pub fn general_routes(cfg: &mut ServiceConfig, cors: Cors) {
+    cfg.service(
+        web::scope("/health")
+            .wrap(cors)
+            .route("", web::get().to(health_check_handler))
+            .route("/cache", web::get().to(cache_stats_handler)),
+    );
+}
+
+/// The Server-Sent Events stream of tutor and course changes.
+pub fn change_routes(cfg: &mut ServiceConfig, cors: Cors) {
+    cfg.service(
+        web::scope("/changes")
+            .wrap(cors)
+            .route("", web::get().to(stream_changes)),
+    );
 }
 
-pub fn course_routes(cfg: &mut ServiceConfig) {
+pub fn course_routes(cfg: &mut ServiceConfig, cors: Cors) {
     cfg.service(
         web::scope("/courses")
+            .wrap(cors)
             .route("", web::post().to(post_new_course))
+            .route("/catalog", web::get().to(get_catalog))
             .route("/{tutor_id}", web::get().to(get_courses_for_tutor))
             .route("/{tutor_id}/{course_id}", web::get().to(get_course_details))
             .route(
                 "/{tutor_id}/{course_id}",
                 web::put().to(update_course_details),
             )
-            .route("/{tutor_id}/{course_id}", web::delete().to(delete_course)),
+            .route("/{tutor_id}/{course_id}", web::delete().to(delete_course))
+            .route(
+                "/{tutor_id}/{course_id}/revisions",
+                web::get().to(get_course_revisions),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/revisions/{revision}/restore",
+                web::post().to(restore_course_revision),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/prerequisites",
+                web::get().to(get_course_prerequisites),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/prerequisites/{prerequisite_id}",
+                web::put().to(put_course_prerequisite),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/prerequisites/{prerequisite_id}",
+                web::delete().to(delete_course_prerequisite),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/enroll",
+                web::post().to(enroll_in_course),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/complete",
+                web::post().to(complete_course),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/sales",
+                web::get().to(get_course_sales),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/sales",
+                web::post()
+                    .to(post_course_sale)
+                    .wrap(from_fn(middleware::require_admin)),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/sales/{sale_id}",
+                web::delete()
+                    .to(delete_course_sale)
+                    .wrap(from_fn(middleware::require_admin)),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/similar",
+                web::get().to(get_similar_courses),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/translations",
+                web::get().to(get_course_translations),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/translations/{locale}",
+                web::put().to(put_course_translation),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/translations/{locale}",
+                web::delete().to(delete_course_translation),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/submit",
+                web::post().to(submit_course),
+            )
+            // Only operators review submitted courses.
+            .route(
+                "/{tutor_id}/{course_id}/reject",
+                web::post()
+                    .to(reject_course)
+                    .wrap(from_fn(middleware::require_admin)),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/publish",
+                web::post()
+                    .to(publish_course)
+                    .wrap(from_fn(middleware::require_admin)),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/archive",
+                web::post().to(archive_course),
+            )
+            .route(
+                "/{tutor_id}/{course_id}/reopen",
+                web::post().to(reopen_course),
+            ),
+    );
+}
+
+/// Bundles anybody may browse and only operators may change.
+pub fn bundle_routes(cfg: &mut ServiceConfig, cors: Cors) {
+    cfg.service(
+        web::scope("/bundles")
+            .wrap(cors)
+            .route("", web::get().to(get_bundles))
+            .route(
+                "",
+                web::post()
+                    .to(post_new_bundle)
+                    .wrap(from_fn(middleware::require_admin)),
+            )
+            .route("/{bundle_id}", web::get().to(get_bundle))
+            .route(
+                "/{bundle_id}",
+                web::put()
+                    .to(update_bundle)
+                    .wrap(from_fn(middleware::require_admin)),
+            )
+            .route(
+                "/{bundle_id}",
+                web::delete()
+                    .to(delete_bundle)
+                    .wrap(from_fn(middleware::require_admin)),
+            )
+            .route("/{bundle_id}/progress", web::get().to(get_bundle_progress)),
     );
 }
 
-pub fn tutor_routes(cfg: &mut ServiceConfig) {
+/// The caller's cart, which checking out turns into an order.
+pub fn cart_routes(cfg: &mut ServiceConfig, cors: Cors) {
+    cfg.service(
+        web::scope("/cart")
+            .wrap(from_fn(middleware::require_student))
+            .wrap(cors)
+            .route("", web::get().to(get_cart))
+            .route("/checkout", web::post().to(checkout))
+            .route("/coupon", web::put().to(apply_coupon_to_cart))
+            .route("/coupon", web::delete().to(remove_coupon_from_cart))
+            .route("/courses/{course_id}", web::put().to(add_course_to_cart))
+            .route(
+                "/courses/{course_id}",
+                web::delete().to(remove_course_from_cart),
+            )
+            .route("/bundles/{bundle_id}", web::put().to(add_bundle_to_cart))
+            .route(
+                "/bundles/{bundle_id}",
+                web::delete().to(remove_bundle_from_cart),
+            ),
+    );
+}
+
+/// The caller's order history and the refunds they asked for.
+pub fn order_routes(cfg: &mut ServiceConfig, cors: Cors) {
+    cfg.service(
+        web::scope("/orders")
+            .wrap(from_fn(middleware::require_student))
+            .wrap(cors)
+            .route("", web::get().to(get_orders))
+            .route("/{order_id}", web::get().to(get_order))
+            .route("/{order_id}/refunds", web::get().to(get_order_refunds))
+            .route("/{order_id}/refunds", web::post().to(request_refund)),
+    );
+}
+
+/// Notifications from the payment provider, authenticated by the provider itself.
+pub fn payment_routes(cfg: &mut ServiceConfig) {
+    cfg.service(web::scope("/payments").route("/webhook", web::post().to(payment_webhook)));
+}
+
+/// The caller's own enrollments.
+pub fn enrollment_routes(cfg: &mut ServiceConfig, cors: Cors) {
+    cfg.service(
+        web::scope("/enrollments")
+            .wrap(from_fn(middleware::require_student))
+            .wrap(cors)
+            .route("", web::get().to(get_enrollments)),
+    );
+}
+
+pub fn category_routes(cfg: &mut ServiceConfig, cors: Cors) {
+    cfg.service(
+        web::scope("/categories")
+            .wrap(cors)
+            .route("", web::get().to(get_categories))
+            .route("", web::post().to(post_new_category))
+            .route("/{category_id}", web::get().to(get_category))
+            .route("/{category_id}", web::put().to(update_category))
+            .route("/{category_id}", web::delete().to(delete_category)),
+    );
+}
+
+pub fn tutor_routes(cfg: &mut ServiceConfig, cors: Cors) {
     cfg.service(
         web::scope("/tutors")
+            .wrap(cors)
             .route("/", web::post().to(post_new_tutor))
             .route("/", web::get().to(get_all_tutors))
+            .route("/directory", web::get().to(get_tutor_directory))
             .route("/{tutor_id}", web::get().to(get_tutor_details))
             .route("/{tutor_id}", web::put().to(update_tutor_details))
-            .route("/{tutor_id}", web::delete().to(delete_tutor)),
+            .route("/{tutor_id}", web::delete().to(delete_tutor))
+            .route("/{tutor_id}/picture", web::post().to(upload_tutor_picture))
+            .route("/{tutor_id}/rating", web::put().to(rate_tutor))
+            .route(
+                "/{tutor_id}/cancellation-policy",
+                web::get().to(get_cancellation_policy),
+            )
+            .route(
+                "/{tutor_id}/cancellation-policy",
+                web::put().to(put_cancellation_policy),
+            )
+            // Earnings are private to the tutor, so only operators see them for now.
+            .service(
+                web::resource("/{tutor_id}/earnings")
+                    .wrap(from_fn(middleware::require_admin))
+                    .get(get_earnings_balance),
+            )
+            .service(
+                web::resource("/{tutor_id}/earnings/statement")
+                    .wrap(from_fn(middleware::require_admin))
+                    .get(get_earnings_statement),
+            )
+            .service(
+                web::resource("/{tutor_id}/earnings/statement.csv")
+                    .wrap(from_fn(middleware::require_admin))
+                    .get(get_earnings_statement_csv),
+            )
+            .service(
+                web::resource("/{tutor_id}/payouts")
+                    .wrap(from_fn(middleware::require_admin))
+                    .get(get_payouts),
+            )
+            // Coupon codes are secrets handed out by their tutor, so only operators see them.
+            .route(
+                "/{tutor_id}/coupons",
+                web::get()
+                    .to(get_coupons)
+                    .wrap(from_fn(middleware::require_admin)),
+            )
+            .route(
+                "/{tutor_id}/coupons",
+                web::post()
+                    .to(post_new_coupon)
+                    .wrap(from_fn(middleware::require_admin)),
+            )
+            .route(
+                "/{tutor_id}/coupons/{coupon_id}",
+                web::delete()
+                    .to(delete_coupon)
+                    .wrap(from_fn(middleware::require_admin)),
+            ),
     );
 }
+
+/// Operator endpoints, open only to callers presenting an admin API key.
+pub fn admin_routes(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .wrap(from_fn(middleware::require_admin))
+            .route("/audit", web::get().to(get_audit_log))
+            .route("/courses/{tutor_id}", web::get().to(get_courses_for_review))
+            .route(
+                "/courses/{tutor_id}/{course_id}",
+                web::get().to(get_course_for_review),
+            )
+            .route(
+                "/courses/{tutor_id}/{course_id}/revisions",
+                web::get().to(get_course_revisions_for_review),
+            )
+            .route("/refunds", web::get().to(get_refunds))
+            .route(
+                "/refunds/{refund_id}/approve",
+                web::post().to(approve_refund),
+            )
+            .route("/refunds/{refund_id}/reject", web::post().to(reject_refund))
+            .route("/payouts", web::post().to(run_payouts))
+            .route("/webhooks", web::post().to(post_new_webhook_subscription))
+            .route("/webhooks", web::get().to(get_webhook_subscriptions))
+            .route(
+                "/webhooks/deliveries/{delivery_id}",
+                web::get().to(get_webhook_delivery),
+            )
+            .route(
+                "/webhooks/deliveries/{delivery_id}/redeliver",
+                web::post().to(redeliver_webhook),
+            )
+            .route(
+                "/webhooks/{subscription_id}",
+                web::put().to(update_webhook_subscription),
+            )
+            .route(
+                "/webhooks/{subscription_id}",
+                web::delete().to(delete_webhook_subscription),
+            )
+            .route(
+                "/webhooks/{subscription_id}/deliveries",
+                web::get().to(get_webhook_deliveries),
+            )
+            .route(
+                "/webhooks/{subscription_id}/redeliver",
+                web::post().to(redeliver_failed_webhooks),
+            ),
+    );
+}
+
+/// Serves locally stored pictures when their public URL is a path on this service.
+pub fn media_routes(cfg: &mut ServiceConfig, pictures: &PicturesConfig) {
+    let local = &pictures.local;
+    if pictures.storage == StorageKind::Local && local.public_url.starts_with('/') {
+        cfg.service(Files::new(
+            local.public_url.trim_end_matches('/'),
+            &local.path,
+        ));
+    }
+}
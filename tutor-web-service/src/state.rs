@@ -1,9 +1,12 @@
 use sqlx::postgres::PgPool;
 
-use std::sync::Mutex;
+use crate::cache::ReadCache;
+use crate::changes::ChangeFeed;
 
 pub struct AppState {
     pub health_check_response: String,
-    pub visit_count: Mutex<i32>,
     pub pg_pool: PgPool,
+    pub cache: ReadCache,
+    /// Tutor and course changes made by any instance, fed by the change listener.
+    pub changes: ChangeFeed,
 }
@@ -0,0 +1,90 @@
+use actix_web::web::Bytes;
+
+use async_trait::async_trait;
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{join_url, ObjectStorage};
+use crate::config::LocalStorageConfig;
+use crate::errors::EzyTutorError;
+
+/// Stores objects as files below a directory.
+pub struct LocalStorage {
+    root: PathBuf,
+    public_url: String,
+}
+
+impl LocalStorage {
+    pub fn new(config: &LocalStorageConfig) -> Self {
+        Self {
+            root: config.path.clone(),
+            public_url: config.public_url.clone(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        key.split('/')
+            .fold(self.root.clone(), |path, part| path.join(part))
+    }
+}
+
+#[async_trait]
+impl ObjectStorage for LocalStorage {
+    async fn put(&self, key: &str, _content_type: &str, bytes: Bytes) -> Result<(), EzyTutorError> {
+        let path = self.path(key);
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(storage_error(dir))?;
+        }
+        tokio::fs::write(&path, bytes)
+            .await
+            .map_err(storage_error(&path))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), EzyTutorError> {
+        let path = self.path(key);
+        match tokio::fs::remove_file(&path).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(storage_error(&path)(err)),
+            _ => Ok(()),
+        }
+    }
+
+    fn url(&self, key: &str) -> String {
+        join_url(&self.public_url, key)
+    }
+}
+
+fn storage_error(path: &Path) -> impl FnOnce(io::Error) -> EzyTutorError + '_ {
+    move |err| EzyTutorError::StorageError(format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn stores_and_deletes_files() {
+        let root = std::env::temp_dir().join(format!("ezy-media-{}", uuid::Uuid::new_v4()));
+        let storage = LocalStorage::new(&LocalStorageConfig {
+            path: root.clone(),
+            public_url: "/media/".to_string(),
+        });
+
+        storage
+            .put("tutors/1/a.jpg", "image/jpeg", Bytes::from_static(b"jpeg"))
+            .await
+            .unwrap();
+        assert_eq!(
+            b"jpeg",
+            &std::fs::read(root.join("tutors/1/a.jpg")).unwrap()[..]
+        );
+        assert_eq!("/media/tutors/1/a.jpg", storage.url("tutors/1/a.jpg"));
+
+        storage.delete("tutors/1/a.jpg").await.unwrap();
+        storage.delete("tutors/1/a.jpg").await.unwrap();
+        assert!(!root.join("tutors/1/a.jpg").exists());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
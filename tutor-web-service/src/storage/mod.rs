@@ -0,0 +1,38 @@
+use actix_web::web::Bytes;
+
+use async_trait::async_trait;
+
+use std::sync::Arc;
+
+use crate::config::{PicturesConfig, StorageKind};
+use crate::errors::EzyTutorError;
+
+mod local;
+mod s3;
+
+pub use local::*;
+pub use s3::*;
+
+/// Where uploaded assets live. Keys are `/`-separated relative paths chosen by the
+/// service, such as `tutors/1/<version>/small.jpg`.
+#[async_trait]
+pub trait ObjectStorage: Send + Sync {
+    async fn put(&self, key: &str, content_type: &str, bytes: Bytes) -> Result<(), EzyTutorError>;
+
+    /// Removes `key`; removing a missing object is not an error.
+    async fn delete(&self, key: &str) -> Result<(), EzyTutorError>;
+
+    /// URL clients fetch `key` from.
+    fn url(&self, key: &str) -> String;
+}
+
+pub fn from_config(config: &PicturesConfig) -> Result<Arc<dyn ObjectStorage>, object_store::Error> {
+    Ok(match config.storage {
+        StorageKind::Local => Arc::new(LocalStorage::new(&config.local)),
+        StorageKind::S3 => Arc::new(S3Storage::new(&config.s3)?),
+    })
+}
+
+fn join_url(base: &str, key: &str) -> String {
+    format!("{}/{}", base.trim_end_matches('/'), key)
+}
@@ -0,0 +1,157 @@
+use actix_web::web::Bytes;
+
+use async_trait::async_trait;
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path;
+use object_store::{Attribute, Attributes, ObjectStore, PutOptions};
+
+use super::{join_url, ObjectStorage};
+use crate::config::S3StorageConfig;
+use crate::errors::EzyTutorError;
+
+/// Objects are never overwritten in place, so clients may cache them indefinitely.
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Stores objects in a bucket of an S3-compatible service.
+pub struct S3Storage {
+    store: AmazonS3,
+    public_url: String,
+}
+
+impl S3Storage {
+    pub fn new(config: &S3StorageConfig) -> Result<Self, object_store::Error> {
+        let mut builder = AmazonS3Builder::new()
+            .with_bucket_name(&config.bucket)
+            .with_region(&config.region);
+        if !config.access_key_id.is_empty() {
+            builder = builder
+                .with_access_key_id(&config.access_key_id)
+                .with_secret_access_key(&config.secret_access_key);
+        }
+        if let Some(endpoint) = &config.endpoint {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        Ok(Self {
+            store: builder.build()?,
+            public_url: config.public_url.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl ObjectStorage for S3Storage {
+    async fn put(&self, key: &str, content_type: &str, bytes: Bytes) -> Result<(), EzyTutorError> {
+        let mut attributes = Attributes::new();
+        attributes.insert(Attribute::ContentType, content_type.to_string().into());
+        attributes.insert(Attribute::CacheControl, CACHE_CONTROL.into());
+        let options = PutOptions {
+            attributes,
+            ..Default::default()
+        };
+        self.store
+            .put_opts(&Path::from(key), bytes.into(), options)
+            .await
+            .map(|_| ())
+            .map_err(storage_error)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), EzyTutorError> {
+        match self.store.delete(&Path::from(key)).await {
+            Err(object_store::Error::NotFound { .. }) | Ok(()) => Ok(()),
+            Err(err) => Err(storage_error(err)),
+        }
+    }
+
+    fn url(&self, key: &str) -> String {
+        join_url(&self.public_url, key)
+    }
+}
+
+fn storage_error(err: object_store::Error) -> EzyTutorError {
+    EzyTutorError::StorageError(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{CONTENT_TYPE, ETAG};
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    type Objects = web::Data<Mutex<HashMap<String, (String, Bytes)>>>;
+
+    /// Just enough of the S3 API, addressed path-style, to stand in for a real bucket.
+    async fn put_object(req: HttpRequest, body: Bytes, objects: Objects) -> HttpResponse {
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let etag = format!("\"{}\"", body.len());
+        objects
+            .lock()
+            .unwrap()
+            .insert(req.path().to_string(), (content_type, body));
+        HttpResponse::Ok().insert_header((ETAG, etag)).finish()
+    }
+
+    async fn delete_object(req: HttpRequest, objects: Objects) -> HttpResponse {
+        objects.lock().unwrap().remove(req.path());
+        HttpResponse::NoContent().finish()
+    }
+
+    #[actix_rt::test]
+    async fn stores_objects_in_an_s3_compatible_service() {
+        let objects: Objects = web::Data::new(Mutex::new(HashMap::new()));
+        let app_objects = objects.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_objects.clone())
+                .route("/{key:.*}", web::put().to(put_object))
+                .route("/{key:.*}", web::delete().to(delete_object))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let storage = S3Storage::new(&S3StorageConfig {
+            bucket: "pictures".to_string(),
+            endpoint: Some(format!("http://{}", addr)),
+            access_key_id: "key".to_string(),
+            secret_access_key: "secret".to_string(),
+            public_url: "https://cdn.example.com".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        storage
+            .put("tutors/1/a.jpg", "image/jpeg", Bytes::from_static(b"jpeg"))
+            .await
+            .unwrap();
+        let stored = objects
+            .lock()
+            .unwrap()
+            .get("/pictures/tutors/1/a.jpg")
+            .cloned();
+        assert_eq!(
+            Some(("image/jpeg".to_string(), Bytes::from_static(b"jpeg"))),
+            stored
+        );
+        assert_eq!(
+            "https://cdn.example.com/tutors/1/a.jpg",
+            storage.url("tutors/1/a.jpg")
+        );
+
+        storage.delete("tutors/1/a.jpg").await.unwrap();
+        assert!(objects.lock().unwrap().is_empty());
+        handle.stop(true).await;
+    }
+}
@@ -0,0 +1,94 @@
+use crate::errors::EzyTutorError;
+use crate::middleware::{current_actor, current_request_id};
+use crate::models::{AuditEntry, AuditQuery, ChangeEntity, ChangeEvent};
+
+use serde::Serialize;
+use sqlx::postgres::{PgConnection, PgPool};
+
+const DEFAULT_AUDIT_LIMIT: i64 = 100;
+const MAX_AUDIT_LIMIT: i64 = 1000;
+
+/// Appends `event` to the audit log together with the entity as it was `before` and
+/// `after` it, attributed to the current request's actor. Run it inside the transaction
+/// making the change so the log never disagrees with the data.
+pub async fn record_audit<T: Serialize>(
+    conn: &mut PgConnection,
+    event: &ChangeEvent,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<(), EzyTutorError> {
+    let entity_id = match event.entity {
+        ChangeEntity::Tutor => event.tutor_id,
+        ChangeEntity::Course => event.course_id.unwrap_or_default(),
+    };
+    let to_json = |value: Option<&T>| {
+        value
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(actix_web::Error::from)
+    };
+
+    sqlx::query!(
+        "INSERT INTO ezy_audit_log (
+        actor, action, entity, entity_id,
+        before_data, after_data, request_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        current_actor(),
+        event.action.as_str(),
+        event.entity.as_str(),
+        entity_id,
+        to_json(before)?,
+        to_json(after)?,
+        current_request_id(),
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Returns matching entries newest first. `from` is inclusive and `to` exclusive.
+pub async fn get_audit_entries(
+    pg_pool: &PgPool,
+    query: AuditQuery,
+) -> Result<Vec<AuditEntry>, EzyTutorError> {
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT);
+    if !(1..=MAX_AUDIT_LIMIT).contains(&limit) {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "limit must be between 1 and {}",
+            MAX_AUDIT_LIMIT
+        )));
+    }
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Err(EzyTutorError::InvalidInput(
+                "from must not be later than to".to_string(),
+            ));
+        }
+    }
+
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        "SELECT audit_id, actor, action, entity, entity_id,
+        before_data, after_data, request_id, recorded_at
+        FROM ezy_audit_log
+        WHERE ($1::varchar IS NULL OR entity = $1)
+        AND ($2::int IS NULL OR entity_id = $2)
+        AND ($3::varchar IS NULL OR actor = $3)
+        AND ($4::timestamptz IS NULL OR recorded_at >= $4)
+        AND ($5::timestamptz IS NULL OR recorded_at < $5)
+        AND ($6::bigint IS NULL OR audit_id < $6)
+        ORDER BY audit_id DESC
+        LIMIT $7",
+        query.entity.map(|entity| entity.as_str()),
+        query.entity_id,
+        query.actor,
+        query.from,
+        query.to,
+        query.before_id,
+        limit,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(entries)
+}
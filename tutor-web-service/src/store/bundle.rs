@@ -0,0 +1,291 @@
+use crate::errors::EzyTutorError;
+use crate::middleware::current_student;
+use crate::models::{
+    BundleCourseProgress, BundleDetails, BundleListQuery, BundleProgress, NewBundle, UpdateBundle,
+};
+
+use sqlx::postgres::{PgConnection, PgPool};
+
+/// Most courses one bundle may hold.
+const MAX_BUNDLE_COURSES: usize = 50;
+const MAX_BUNDLE_NAME_LEN: usize = 140;
+
+pub async fn get_bundles(
+    pg_pool: &PgPool,
+    query: BundleListQuery,
+) -> Result<Vec<BundleDetails>, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    load_bundles(&mut conn, query.tutor_id, None).await
+}
+
+pub async fn get_bundle(pg_pool: &PgPool, bundle_id: i32) -> Result<BundleDetails, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    load_bundle(&mut conn, bundle_id).await
+}
+
+pub async fn post_new_bundle(
+    pg_pool: &PgPool,
+    new_bundle: NewBundle,
+) -> Result<BundleDetails, EzyTutorError> {
+    let name = bundle_name(&new_bundle.bundle_name)?;
+    bundle_price(new_bundle.bundle_price)?;
+    let mut tx = pg_pool.begin().await?;
+    sqlx::query_scalar!(
+        "SELECT tutor_id FROM ezy_tutor_c6 WHERE tutor_id = $1",
+        new_bundle.tutor_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| {
+        EzyTutorError::InvalidInput(format!("Tutor {} does not exist", new_bundle.tutor_id))
+    })?;
+    let bundle_id = sqlx::query_scalar!(
+        "INSERT INTO ezy_bundle (tutor_id, bundle_name, bundle_description, bundle_price)
+        VALUES ($1, $2, $3, $4)
+        RETURNING bundle_id",
+        new_bundle.tutor_id,
+        name,
+        new_bundle.bundle_description,
+        new_bundle.bundle_price,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    set_bundle_courses(
+        &mut tx,
+        new_bundle.tutor_id,
+        bundle_id,
+        &new_bundle.course_ids,
+    )
+    .await?;
+    let bundle = load_bundle(&mut tx, bundle_id).await?;
+    tx.commit().await?;
+
+    Ok(bundle)
+}
+
+pub async fn update_bundle(
+    pg_pool: &PgPool,
+    bundle_id: i32,
+    update_bundle: UpdateBundle,
+) -> Result<BundleDetails, EzyTutorError> {
+    let name = update_bundle
+        .bundle_name
+        .as_deref()
+        .map(bundle_name)
+        .transpose()?;
+    update_bundle.bundle_price.map(bundle_price).transpose()?;
+    let mut tx = pg_pool.begin().await?;
+    let tutor_id = sqlx::query_scalar!(
+        "UPDATE ezy_bundle SET
+            bundle_name = coalesce($1, bundle_name),
+            bundle_description = coalesce($2, bundle_description),
+            bundle_price = coalesce($3, bundle_price)
+        WHERE bundle_id = $4
+        RETURNING tutor_id",
+        name,
+        update_bundle.bundle_description,
+        update_bundle.bundle_price,
+        bundle_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Bundle id not found".to_string()))?;
+    if let Some(course_ids) = &update_bundle.course_ids {
+        set_bundle_courses(&mut tx, tutor_id, bundle_id, course_ids).await?;
+    }
+    let bundle = load_bundle(&mut tx, bundle_id).await?;
+    tx.commit().await?;
+
+    Ok(bundle)
+}
+
+pub async fn delete_bundle(pg_pool: &PgPool, bundle_id: i32) -> Result<String, EzyTutorError> {
+    let res = sqlx::query!("DELETE FROM ezy_bundle WHERE bundle_id = $1", bundle_id)
+        .execute(pg_pool)
+        .await?;
+    if res.rows_affected() == 0 {
+        return Err(EzyTutorError::NotFound("Bundle id not found".to_string()));
+    }
+
+    Ok(format!("Deleted {:?} record", res))
+}
+
+/// The caller's enrollment in and completion of each course of the bundle.
+pub async fn get_bundle_progress(
+    pg_pool: &PgPool,
+    bundle_id: i32,
+) -> Result<BundleProgress, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    sqlx::query_scalar!(
+        "SELECT bundle_id FROM ezy_bundle WHERE bundle_id = $1",
+        bundle_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Bundle id not found".to_string()))?;
+    let courses = sqlx::query_as!(
+        BundleCourseProgress,
+        r#"SELECT bc.course_id, bc.position,
+            e.enrolled_at AS "enrolled_at?", e.completed_at
+        FROM ezy_bundle_course bc
+        LEFT JOIN ezy_enrollment e ON e.course_id = bc.course_id and e.student = $2
+        WHERE bc.bundle_id = $1
+        ORDER BY bc.position"#,
+        bundle_id,
+        current_student()?,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(BundleProgress {
+        bundle_id,
+        completed_courses: courses
+            .iter()
+            .filter(|course| course.completed_at.is_some())
+            .count(),
+        total_courses: courses.len(),
+        next_course_id: courses
+            .iter()
+            .find(|course| course.completed_at.is_none())
+            .map(|course| course.course_id),
+        courses,
+    })
+}
+
+async fn load_bundle(
+    conn: &mut PgConnection,
+    bundle_id: i32,
+) -> Result<BundleDetails, EzyTutorError> {
+    load_bundles(conn, None, Some(bundle_id))
+        .await?
+        .pop()
+        .ok_or_else(|| EzyTutorError::NotFound("Bundle id not found".to_string()))
+}
+
+/// Bundles sold by `tutor_id` or with `bundle_id`, all of them when both are `None`.
+async fn load_bundles(
+    conn: &mut PgConnection,
+    tutor_id: Option<i32>,
+    bundle_id: Option<i32>,
+) -> Result<Vec<BundleDetails>, EzyTutorError> {
+    let bundles = sqlx::query_scalar!(
+        r#"SELECT coalesce(jsonb_agg(
+            to_jsonb(b) || jsonb_build_object('courses', (
+                SELECT coalesce(jsonb_agg(to_jsonb(c) ORDER BY bc.position), '[]')
+                FROM ezy_bundle_course bc
+                JOIN ezy_course_c6 c USING (course_id)
+                WHERE bc.bundle_id = b.bundle_id))
+            ORDER BY b.bundle_id), '[]') AS "bundles!"
+        FROM ezy_bundle b
+        WHERE ($1::int IS NULL or b.tutor_id = $1) and ($2::int IS NULL or b.bundle_id = $2)"#,
+        tutor_id,
+        bundle_id,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(serde_json::from_value(bundles).map_err(actix_web::Error::from)?)
+}
+
+/// Replaces the bundle's courses with `course_ids`, in that order, which must all be the
+/// bundle tutor's own. A course may not come before another course of the path it
+/// requires, directly or not.
+async fn set_bundle_courses(
+    conn: &mut PgConnection,
+    tutor_id: i32,
+    bundle_id: i32,
+    course_ids: &[i32],
+) -> Result<(), EzyTutorError> {
+    if course_ids.is_empty() || course_ids.len() > MAX_BUNDLE_COURSES {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "A bundle must have 1 to {} courses",
+            MAX_BUNDLE_COURSES
+        )));
+    }
+    if let Some((_, id)) = course_ids
+        .iter()
+        .enumerate()
+        .find(|(i, id)| course_ids[..*i].contains(id))
+    {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "Course {} is listed more than once",
+            id
+        )));
+    }
+    let own = sqlx::query_scalar!(
+        "SELECT course_id FROM ezy_course_c6 WHERE course_id = ANY($1) and tutor_id = $2",
+        course_ids,
+        tutor_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    if let Some(other) = course_ids.iter().find(|id| !own.contains(id)) {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "Course {} does not exist or is not taught by tutor {}",
+            other, tutor_id
+        )));
+    }
+
+    let required = sqlx::query!(
+        r#"WITH RECURSIVE required (course_id, prerequisite_id) AS (
+            SELECT course_id, prerequisite_id FROM ezy_course_prerequisite
+            WHERE course_id = ANY($1)
+            UNION
+            SELECT r.course_id, p.prerequisite_id FROM ezy_course_prerequisite p
+            JOIN required r ON p.course_id = r.prerequisite_id
+        )
+        SELECT course_id AS "course_id!", prerequisite_id AS "prerequisite_id!"
+        FROM required WHERE prerequisite_id = ANY($1)"#,
+        course_ids,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let position = |id: i32| course_ids.iter().position(|other| *other == id);
+    if let Some(edge) = required
+        .iter()
+        .find(|edge| position(edge.prerequisite_id) > position(edge.course_id))
+    {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "Course {} must come after its prerequisite {}",
+            edge.course_id, edge.prerequisite_id
+        )));
+    }
+
+    sqlx::query!(
+        "DELETE FROM ezy_bundle_course WHERE bundle_id = $1",
+        bundle_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO ezy_bundle_course (bundle_id, course_id, position)
+        SELECT $1, course_id, position::int
+        FROM unnest($2::int[]) WITH ORDINALITY AS path (course_id, position)",
+        bundle_id,
+        course_ids,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+fn bundle_name(name: &str) -> Result<String, EzyTutorError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_BUNDLE_NAME_LEN {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "bundle_name must be 1 to {} characters long",
+            MAX_BUNDLE_NAME_LEN
+        )));
+    }
+    Ok(name.to_string())
+}
+
+fn bundle_price(price: i32) -> Result<(), EzyTutorError> {
+    if price <= 0 {
+        return Err(EzyTutorError::InvalidInput(
+            "bundle_price must be positive".to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,342 @@
+use crate::errors::EzyTutorError;
+use crate::models::{Category, Course, CourseDetails, CoursePricing, NewCategory, UpdateCategory};
+use crate::pricing::sale_price;
+use crate::store::current_sales;
+
+use sqlx::postgres::{PgConnection, PgPool};
+
+use std::collections::HashMap;
+
+/// Most tags one course may carry.
+const MAX_TAGS: usize = 20;
+const MAX_TAG_LEN: usize = 50;
+const MAX_CATEGORY_NAME_LEN: usize = 100;
+
+pub async fn get_categories(pg_pool: &PgPool) -> Result<Vec<Category>, EzyTutorError> {
+    let categories = sqlx::query_as!(
+        Category,
+        "SELECT * FROM ezy_category
+        ORDER BY parent_id NULLS FIRST, lower(category_name)"
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(categories)
+}
+
+pub async fn get_category(pg_pool: &PgPool, category_id: i32) -> Result<Category, EzyTutorError> {
+    sqlx::query_as!(
+        Category,
+        "SELECT * FROM ezy_category WHERE category_id = $1",
+        category_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Category id not found".to_string()))
+}
+
+pub async fn post_new_category(
+    pg_pool: &PgPool,
+    new_category: NewCategory,
+) -> Result<Category, EzyTutorError> {
+    let name = category_name(&new_category.category_name)?;
+    let mut tx = pg_pool.begin().await?;
+    if let Some(parent_id) = new_category.parent_id {
+        ensure_parent(&mut tx, parent_id).await?;
+    }
+    let category = sqlx::query_as!(
+        Category,
+        "INSERT INTO ezy_category (parent_id, category_name)
+        VALUES ($1, $2)
+        RETURNING *",
+        new_category.parent_id,
+        name,
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(duplicate_name)?;
+    tx.commit().await?;
+
+    Ok(category)
+}
+
+/// Renames or moves the category. It cannot be moved under itself or its descendants.
+pub async fn update_category(
+    pg_pool: &PgPool,
+    category_id: i32,
+    update_category: UpdateCategory,
+) -> Result<Category, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    // Moves are serialized so two of them cannot close a cycle between them.
+    sqlx::query!("LOCK TABLE ezy_category IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+    let current = sqlx::query_as!(
+        Category,
+        "SELECT * FROM ezy_category WHERE category_id = $1",
+        category_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Category id not found".to_string()))?;
+
+    let name = match update_category.category_name {
+        Some(name) => category_name(&name)?,
+        None => current.category_name,
+    };
+    let parent_id = update_category.parent_id.unwrap_or(current.parent_id);
+    if let Some(parent_id) = parent_id {
+        ensure_parent(&mut tx, parent_id).await?;
+        let cycle = sqlx::query_scalar!(
+            r#"WITH RECURSIVE subtree AS (
+                SELECT category_id FROM ezy_category WHERE category_id = $1
+                UNION ALL
+                SELECT c.category_id FROM ezy_category c
+                JOIN subtree s ON c.parent_id = s.category_id
+            )
+            SELECT EXISTS (SELECT 1 FROM subtree WHERE category_id = $2) AS "cycle!""#,
+            category_id,
+            parent_id,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if cycle {
+            return Err(EzyTutorError::Conflict(
+                "A category cannot be moved under itself or its subcategories".to_string(),
+            ));
+        }
+    }
+
+    let category = sqlx::query_as!(
+        Category,
+        "UPDATE ezy_category SET parent_id = $1, category_name = $2
+        WHERE category_id = $3
+        RETURNING *",
+        parent_id,
+        name,
+        category_id,
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(duplicate_name)?;
+    tx.commit().await?;
+
+    Ok(category)
+}
+
+/// Deletes a category without subcategories; its courses simply leave it.
+pub async fn delete_category(pg_pool: &PgPool, category_id: i32) -> Result<String, EzyTutorError> {
+    let res = sqlx::query!(
+        "DELETE FROM ezy_category WHERE category_id = $1",
+        category_id,
+    )
+    .execute(pg_pool)
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::Database(db_err) if db_err.is_foreign_key_violation() => {
+            EzyTutorError::Conflict("Category has subcategories".to_string())
+        }
+        err => err.into(),
+    })?;
+
+    Ok(format!("Deleted {:?} record", res))
+}
+
+/// Adds the categories and tags each course is filed under and its current price.
+pub async fn with_details(
+    conn: &mut PgConnection,
+    courses: Vec<Course>,
+) -> Result<Vec<CourseDetails>, EzyTutorError> {
+    let course_ids: Vec<i32> = courses.iter().map(|course| course.course_id).collect();
+    let mut categories: HashMap<i32, Vec<i32>> = HashMap::new();
+    for link in sqlx::query!(
+        "SELECT course_id, category_id FROM ezy_course_category
+        WHERE course_id = ANY($1)
+        ORDER BY category_id",
+        &course_ids,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    {
+        categories
+            .entry(link.course_id)
+            .or_default()
+            .push(link.category_id);
+    }
+    let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+    for link in sqlx::query!(
+        "SELECT ct.course_id, t.tag_name FROM ezy_course_tag ct
+        JOIN ezy_tag t USING (tag_id)
+        WHERE ct.course_id = ANY($1)
+        ORDER BY t.tag_name",
+        &course_ids,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    {
+        tags.entry(link.course_id).or_default().push(link.tag_name);
+    }
+    let mut sales = current_sales(&mut *conn, &course_ids).await?;
+
+    Ok(courses
+        .into_iter()
+        .map(|course| {
+            let sale = sales.remove(&course.course_id);
+            CourseDetails {
+                category_ids: categories.remove(&course.course_id).unwrap_or_default(),
+                tags: tags.remove(&course.course_id).unwrap_or_default(),
+                pricing: CoursePricing {
+                    price: sale_price(
+                        course.course_price.unwrap_or(0),
+                        sale.as_ref().map(|sale| sale.sale_price),
+                    ),
+                    sale_ends_at: sale.map(|sale| sale.ends_at),
+                },
+                course,
+            }
+        })
+        .collect())
+}
+
+/// Replaces the course's categories and tags, leaving whichever is `None` as it is.
+pub async fn set_course_taxonomy(
+    conn: &mut PgConnection,
+    course_id: i32,
+    category_ids: Option<&[i32]>,
+    tags: Option<&[String]>,
+) -> Result<(), EzyTutorError> {
+    if let Some(category_ids) = category_ids {
+        let known = sqlx::query_scalar!(
+            "SELECT category_id FROM ezy_category WHERE category_id = ANY($1)",
+            category_ids,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        if let Some(unknown) = category_ids.iter().find(|id| !known.contains(id)) {
+            return Err(EzyTutorError::InvalidInput(format!(
+                "Category {} does not exist",
+                unknown
+            )));
+        }
+        sqlx::query!(
+            "DELETE FROM ezy_course_category WHERE course_id = $1",
+            course_id
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO ezy_course_category (course_id, category_id)
+            SELECT $1, unnest($2::int[])
+            ON CONFLICT DO NOTHING",
+            course_id,
+            category_ids,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    if let Some(tags) = tags {
+        let tags = normalize_tags(tags)?;
+        sqlx::query!(
+            "INSERT INTO ezy_tag (tag_name) SELECT unnest($1::varchar[])
+            ON CONFLICT (tag_name) DO NOTHING",
+            &tags,
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!("DELETE FROM ezy_course_tag WHERE course_id = $1", course_id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!(
+            "INSERT INTO ezy_course_tag (course_id, tag_id)
+            SELECT $1, tag_id FROM ezy_tag WHERE tag_name = ANY($2)",
+            course_id,
+            &tags,
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Tags are compared trimmed, lowercase and with single spaces, e.g. `Machine  Learning`
+/// is stored as `machine learning`.
+pub fn normalize_tag(tag: &str) -> Result<String, EzyTutorError> {
+    let tag = tag
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LEN {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "Tags must be 1 to {} characters long",
+            MAX_TAG_LEN
+        )));
+    }
+    Ok(tag)
+}
+
+fn normalize_tags(tags: &[String]) -> Result<Vec<String>, EzyTutorError> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = normalize_tag(tag)?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    if normalized.len() > MAX_TAGS {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "A course may have at most {} tags",
+            MAX_TAGS
+        )));
+    }
+    Ok(normalized)
+}
+
+fn category_name(name: &str) -> Result<String, EzyTutorError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_CATEGORY_NAME_LEN {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "category_name must be 1 to {} characters long",
+            MAX_CATEGORY_NAME_LEN
+        )));
+    }
+    Ok(name.to_string())
+}
+
+async fn ensure_parent(conn: &mut PgConnection, parent_id: i32) -> Result<(), EzyTutorError> {
+    sqlx::query_scalar!(
+        "SELECT category_id FROM ezy_category WHERE category_id = $1",
+        parent_id,
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(|_| ())
+    .ok_or_else(|| EzyTutorError::InvalidInput(format!("Category {} does not exist", parent_id)))
+}
+
+fn duplicate_name(err: sqlx::Error) -> EzyTutorError {
+    match err {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            EzyTutorError::Conflict("A sibling category already has that name".to_string())
+        }
+        err => err.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_and_deduplicates_tags() {
+        let tags = ["  Machine   Learning ", "machine learning", "Rust"].map(String::from);
+        assert_eq!(
+            vec!["machine learning", "rust"],
+            normalize_tags(&tags).unwrap()
+        );
+        assert!(normalize_tag("   ").is_err());
+        assert!(normalize_tag(&"x".repeat(51)).is_err());
+    }
+}
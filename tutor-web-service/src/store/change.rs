@@ -0,0 +1,60 @@
+use crate::errors::EzyTutorError;
+use crate::models::{ChangeEntity, ChangeEvent, CourseStatus};
+use crate::store::{enqueue_webhook_event, notify_change, record_audit};
+
+use serde::Serialize;
+use sqlx::postgres::PgConnection;
+
+/// Publishes a change made on `conn`'s transaction: it is audited, queued for webhook
+/// subscribers and announced to other instances, all only once the transaction commits.
+pub async fn record_change<T: Serialize>(
+    conn: &mut PgConnection,
+    event: &ChangeEvent,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<(), EzyTutorError> {
+    record_audit(conn, event, before, after).await?;
+    if let Some(data) = after.or(before) {
+        if visible_to_subscribers(conn, event, before, after).await? {
+            enqueue_webhook_event(conn, event, data).await?;
+        }
+    }
+    notify_change(conn, event).await
+}
+
+/// Webhook subscribers only ever see the public catalog, so they hear of a course change
+/// when the course is published before or after it: drafts, courses in review and
+/// scheduled ones stay private until they go live, while unpublishing or deleting a
+/// published course is announced. Changes to what belongs to a course, like its
+/// translations, follow the course's current status.
+async fn visible_to_subscribers<T: Serialize>(
+    conn: &mut PgConnection,
+    event: &ChangeEvent,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<bool, EzyTutorError> {
+    let Some(course_id) = event
+        .course_id
+        .filter(|_| event.entity == ChangeEntity::Course)
+    else {
+        return Ok(true);
+    };
+    let published = CourseStatus::Published.as_str();
+    let statuses: Vec<String> = [before, after]
+        .into_iter()
+        .flatten()
+        .filter_map(|data| serde_json::to_value(data).ok())
+        .filter_map(|data| data.get("course_status")?.as_str().map(str::to_string))
+        .collect();
+    if !statuses.is_empty() {
+        return Ok(statuses.iter().any(|status| status == published));
+    }
+    let status = sqlx::query_scalar!(
+        "SELECT course_status FROM ezy_course_c6 WHERE course_id = $1",
+        course_id,
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(status.as_deref() == Some(published))
+}
@@ -1,48 +1,293 @@
 use crate::errors::EzyTutorError;
-use crate::models::{Course, NewCourse, UpdateCourse};
+use crate::models::{
+    Catalog, CatalogFacets, CatalogQuery, ChangeAction, ChangeEvent, Course, CourseDetails,
+    CourseStatus, CourseTransition, NewCourse, PriceBucketCount, SimilarCourse,
+    SimilarCoursesQuery, UpdateCourse,
+};
+use crate::store::{
+    normalize_tag, record_change, record_course_revision, set_course_taxonomy, with_details,
+};
 
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use sqlx::postgres::PgPool;
 
+/// Lists the tutor's courses in `status`, or all of them when it is `None`, with their
+/// categories, tags and current price.
 pub async fn get_courses_for_tutor(
     pg_pool: &PgPool,
     tutor_id: i32,
-) -> Result<Vec<Course>, EzyTutorError> {
+    status: Option<CourseStatus>,
+) -> Result<Vec<CourseDetails>, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
     // Prepare SQL statement
     let courses = sqlx::query_as!(
         Course,
-        "SELECT * FROM ezy_course_c6 WHERE tutor_id = $1",
+        "SELECT * FROM ezy_course_c6
+        WHERE tutor_id = $1 and ($2::varchar IS NULL or course_status = $2)",
         tutor_id,
+        status.map(|status| status.as_str()),
     )
-    .fetch_all(pg_pool)
+    .fetch_all(&mut *conn)
     .await?;
 
-    Ok(courses)
+    with_details(&mut conn, courses).await
 }
 
+/// The course, which must be in `status` unless that is `None`.
 pub async fn get_course_details(
     pg_pool: &PgPool,
     totur_id: i32,
     course_id: i32,
-) -> Result<Course, EzyTutorError> {
+    status: Option<CourseStatus>,
+) -> Result<CourseDetails, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
     // Prepare SQL statement
     let course = sqlx::query_as!(
         Course,
         "SELECT * FROM ezy_course_c6
         WHERE tutor_id = $1 and course_id = $2
-        ",
+            and ($3::varchar IS NULL or course_status = $3)",
         totur_id,
         course_id,
+        status.map(|status| status.as_str()),
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Course id not found".to_string()))?;
+
+    let mut details = with_details(&mut conn, vec![course]).await?;
+    Ok(details.remove(0))
+}
+
+/// The published courses most similar to the given one, best first. The score weighs
+/// the overlap of the words in their names and descriptions at one half, of their
+/// categories (counting ancestors, so sibling subcategories overlap) at a fifth, and a
+/// shared level and language at 0.15 each.
+pub async fn get_similar_courses(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    query: SimilarCoursesQuery,
+) -> Result<Vec<SimilarCourse>, EzyTutorError> {
+    let limit = query.limit.unwrap_or(5);
+    if !(1..=50).contains(&limit) {
+        return Err(EzyTutorError::InvalidInput(
+            "limit must be between 1 and 50".to_string(),
+        ));
+    }
+
+    sqlx::query_scalar!(
+        "SELECT course_id FROM ezy_course_c6 WHERE tutor_id = $1 and course_id = $2",
+        tutor_id,
+        course_id,
     )
     .fetch_optional(pg_pool)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Course id not found".to_string()))?;
+
+    let similar = sqlx::query_scalar!(
+        r#"WITH RECURSIVE ancestry (ancestor_id, category_id) AS (
+            SELECT category_id, category_id FROM ezy_category
+            UNION ALL
+            SELECT a.ancestor_id, c.category_id FROM ezy_category c
+            JOIN ancestry a ON c.parent_id = a.category_id
+        ),
+        features AS (
+            SELECT c.course_id, c.tutor_id, c.course_status,
+                lower(coalesce(c.course_level, '')) AS course_level,
+                lower(coalesce(c.course_language, '')) AS course_language,
+                tsvector_to_array(to_tsvector('english',
+                    c.course_name || ' ' || coalesce(c.course_description, ''))) AS words,
+                ARRAY(SELECT DISTINCT a.ancestor_id FROM ezy_course_category cc
+                    JOIN ancestry a USING (category_id)
+                    WHERE cc.course_id = c.course_id) AS categories
+            FROM ezy_course_c6 c
+        ),
+        scored AS (
+            SELECT f.course_id,
+                0.5 * ezy_jaccard(f.words, t.words)
+                + 0.2 * ezy_jaccard(f.categories, t.categories)
+                + 0.15 * (f.course_level <> '' and f.course_level = t.course_level)::int
+                + 0.15 * (f.course_language <> '' and f.course_language = t.course_language)::int
+                    AS score
+            FROM features f
+            JOIN features t ON t.course_id = $1
+            WHERE f.course_status = 'published'
+                and f.course_id <> t.course_id
+                and not ($2 and f.tutor_id = t.tutor_id)
+        )
+        SELECT coalesce(jsonb_agg(
+            to_jsonb(c) || jsonb_build_object('score', round(s.score::numeric, 4))
+            ORDER BY s.score DESC, s.course_id), '[]') AS "similar!"
+        FROM (
+            SELECT * FROM scored WHERE score > 0
+            ORDER BY score DESC, course_id
+            LIMIT $3
+        ) s
+        JOIN ezy_course_c6 c USING (course_id)"#,
+        course_id,
+        query.exclude_same_tutor,
+        limit,
+    )
+    .fetch_one(pg_pool)
     .await?;
 
-    course.ok_or_else(|| EzyTutorError::NotFound("Course id not found".to_string()))
+    Ok(serde_json::from_value(similar).map_err(actix_web::Error::from)?)
+}
+
+/// Lower bounds of the catalog's price facet buckets, the first holding free courses.
+const PRICE_BUCKETS: [i32; 5] = [0, 1, 2500, 5000, 10000];
+
+/// One page of published courses matching `query`, with facet counts over all of them.
+/// The filters are applied once, in a single aggregate query that yields the page's ids
+/// along with every facet.
+pub async fn get_catalog(pg_pool: &PgPool, query: CatalogQuery) -> Result<Catalog, EzyTutorError> {
+    let limit = query.limit.unwrap_or(20);
+    let offset = query.offset.unwrap_or(0);
+    if !(1..=100).contains(&limit) || offset < 0 {
+        return Err(EzyTutorError::InvalidInput(
+            "limit must be between 1 and 100 and offset must not be negative".to_string(),
+        ));
+    }
+    let tag = query.tag.as_deref().map(normalize_tag).transpose()?;
+
+    let mut conn = pg_pool.acquire().await?;
+    // Category counts include courses filed under any descendant.
+    let result = sqlx::query!(
+        r#"WITH RECURSIVE scope AS (
+            SELECT category_id FROM ezy_category WHERE category_id = $1
+            UNION ALL
+            SELECT c.category_id FROM ezy_category c JOIN scope s ON c.parent_id = s.category_id
+        ),
+        ancestry (ancestor_id, category_id) AS (
+            SELECT category_id, category_id FROM ezy_category
+            UNION ALL
+            SELECT a.ancestor_id, c.category_id FROM ezy_category c
+            JOIN ancestry a ON c.parent_id = a.category_id
+        ),
+        matching AS MATERIALIZED (
+            SELECT c.course_id, c.posted_time, c.course_level, c.course_format, c.course_language,
+                width_bucket(coalesce(c.course_price, 0), $10::int[]) AS price_bucket
+            FROM ezy_course_c6 c
+            WHERE c.course_status = 'published'
+                and ($1::int IS NULL or EXISTS (
+                    SELECT 1 FROM ezy_course_category cc JOIN scope USING (category_id)
+                    WHERE cc.course_id = c.course_id))
+                and ($2::varchar IS NULL or EXISTS (
+                    SELECT 1 FROM ezy_course_tag ct JOIN ezy_tag t USING (tag_id)
+                    WHERE ct.course_id = c.course_id and t.tag_name = $2))
+                and ($3::varchar IS NULL or c.course_level = $3)
+                and ($4::varchar IS NULL or c.course_format = $4)
+                and ($5::varchar IS NULL or c.course_language = $5)
+                and ($6::int IS NULL or coalesce(c.course_price, 0) >= $6)
+                and ($7::int IS NULL or coalesce(c.course_price, 0) <= $7)
+        )
+        SELECT
+            (SELECT count(*) FROM matching) AS "total!",
+            (SELECT coalesce(array_agg(p.course_id ORDER BY p.position), '{}') FROM (
+                SELECT course_id,
+                    row_number() OVER (ORDER BY posted_time DESC NULLS LAST, course_id) AS position
+                FROM matching
+                ORDER BY position
+                LIMIT $8 OFFSET $9
+            ) p) AS "page!",
+            (SELECT coalesce(jsonb_agg(f ORDER BY lower(f.category_name)), '[]') FROM (
+                SELECT cat.category_id, cat.parent_id, cat.category_name,
+                    count(DISTINCT cc.course_id) AS count
+                FROM matching m
+                JOIN ezy_course_category cc USING (course_id)
+                JOIN ancestry a ON a.category_id = cc.category_id
+                JOIN ezy_category cat ON cat.category_id = a.ancestor_id
+                GROUP BY cat.category_id
+            ) f) AS "categories!",
+            (SELECT coalesce(jsonb_agg(f ORDER BY f.count DESC, f.tag), '[]') FROM (
+                SELECT t.tag_name AS tag, count(*) AS count
+                FROM matching m
+                JOIN ezy_course_tag ct USING (course_id)
+                JOIN ezy_tag t USING (tag_id)
+                GROUP BY t.tag_name
+            ) f) AS "tags!",
+            (SELECT coalesce(jsonb_agg(f ORDER BY f.count DESC, f.value), '[]') FROM (
+                SELECT course_level AS value, count(*) AS count FROM matching
+                WHERE course_level <> '' GROUP BY course_level
+            ) f) AS "levels!",
+            (SELECT coalesce(jsonb_agg(f ORDER BY f.count DESC, f.value), '[]') FROM (
+                SELECT course_format AS value, count(*) AS count FROM matching
+                WHERE course_format <> '' GROUP BY course_format
+            ) f) AS "formats!",
+            (SELECT coalesce(jsonb_agg(f ORDER BY f.count DESC, f.value), '[]') FROM (
+                SELECT course_language AS value, count(*) AS count FROM matching
+                WHERE course_language <> '' GROUP BY course_language
+            ) f) AS "languages!",
+            (SELECT coalesce(jsonb_agg(f ORDER BY f.bucket), '[]') FROM (
+                SELECT price_bucket AS bucket, count(*) AS count FROM matching
+                WHERE price_bucket > 0 GROUP BY price_bucket
+            ) f) AS "prices!""#,
+        query.category_id,
+        tag,
+        query.level,
+        query.format,
+        query.language,
+        query.min_price,
+        query.max_price,
+        limit,
+        offset,
+        &PRICE_BUCKETS,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let mut courses = sqlx::query_as!(
+        Course,
+        "SELECT * FROM ezy_course_c6 WHERE course_id = ANY($1)",
+        &result.page,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    courses.sort_by_key(|course| result.page.iter().position(|id| *id == course.course_id));
+
+    let facets = CatalogFacets {
+        categories: from_json(result.categories)?,
+        tags: from_json(result.tags)?,
+        levels: from_json(result.levels)?,
+        formats: from_json(result.formats)?,
+        languages: from_json(result.languages)?,
+        prices: from_json::<Vec<BucketCount>>(result.prices)?
+            .into_iter()
+            .map(|bucket| {
+                // `width_bucket` numbers buckets from 1; bucket 0 holds negative prices.
+                let index = bucket.bucket as usize;
+                PriceBucketCount {
+                    min_price: PRICE_BUCKETS[index - 1],
+                    max_price: PRICE_BUCKETS.get(index).map(|next| next - 1),
+                    count: bucket.count,
+                }
+            })
+            .collect(),
+    };
+    Ok(Catalog {
+        total: result.total,
+        courses: with_details(&mut conn, courses).await?,
+        facets,
+    })
+}
+
+#[derive(Deserialize)]
+struct BucketCount {
+    bucket: i32,
+    count: i64,
+}
+
+fn from_json<T: DeserializeOwned>(value: serde_json::Value) -> Result<T, EzyTutorError> {
+    Ok(serde_json::from_value(value).map_err(actix_web::Error::from)?)
 }
 
 pub async fn post_new_course(
     pg_pool: &PgPool,
     new_course: NewCourse,
-) -> Result<Course, EzyTutorError> {
+) -> Result<CourseDetails, EzyTutorError> {
     let NewCourse {
         tutor_id,
         course_name,
@@ -53,21 +298,25 @@ pub async fn post_new_course(
         course_price,
         course_language,
         course_level,
+        first_session_at,
+        category_ids,
+        tags,
     } = new_course;
+    let mut tx = pg_pool.begin().await?;
     let new_course = sqlx::query_as!(
         Course,
         "INSERT INTO ezy_course_c6 (
         tutor_id, course_name,
         course_description, course_duration,
         course_level, course_format, course_language,
-        course_structure, course_price
+        course_structure, course_price, first_session_at
         )
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
         RETURNING course_id, tutor_id, course_name,
         course_description, course_duration,
         course_level, course_format, course_language,
         course_structure, course_price,
-        posted_time",
+        posted_time, course_status, publish_at, first_session_at",
         tutor_id,
         course_name,
         course_description,
@@ -76,10 +325,28 @@ pub async fn post_new_course(
         course_format,
         course_language,
         course_structure,
-        course_price
+        course_price,
+        first_session_at,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let event = ChangeEvent::course(
+        ChangeAction::Created,
+        new_course.tutor_id,
+        new_course.course_id,
+    );
+    record_course_revision(&mut tx, None, &new_course, None).await?;
+    set_course_taxonomy(
+        &mut tx,
+        new_course.course_id,
+        Some(&category_ids),
+        Some(&tags),
     )
-    .fetch_one(pg_pool)
     .await?;
+    let new_course = with_details(&mut tx, vec![new_course]).await?.remove(0);
+    record_change(&mut tx, &event, None, Some(&new_course)).await?;
+    tx.commit().await?;
 
     Ok(new_course)
 }
@@ -89,14 +356,29 @@ pub async fn delete_course(
     tutor_id: i32,
     course_id: i32,
 ) -> Result<String, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    let before = sqlx::query_as!(
+        Course,
+        "SELECT * FROM ezy_course_c6 WHERE tutor_id = $1 and course_id = $2 FOR UPDATE",
+        tutor_id,
+        course_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
     let res = sqlx::query!(
         "DELETE FROM ezy_course_c6 WHERE tutor_id = $1 and course_id = $2",
         tutor_id,
         course_id,
     )
-    .execute(pg_pool)
+    .execute(&mut *tx)
     .await?;
 
+    if res.rows_affected() > 0 {
+        let event = ChangeEvent::course(ChangeAction::Deleted, tutor_id, course_id);
+        record_change(&mut tx, &event, before.as_ref(), None).await?;
+    }
+    tx.commit().await?;
+
     Ok(format!("Deleted {:?} record", res))
 }
 
@@ -105,19 +387,23 @@ pub async fn update_course_datails(
     tutor_id: i32,
     course_id: i32,
     update_course: UpdateCourse,
-) -> Result<Course, EzyTutorError> {
-    eprintln!("tutor_id: {}, course_id: {}", tutor_id, course_id);
+) -> Result<CourseDetails, EzyTutorError> {
+    tracing::debug!(tutor_id, course_id, "updating course");
 
-    // Retrieve current record
+    // Retrieve current record, locked until the update commits
+    let mut tx = pg_pool.begin().await?;
     let current = sqlx::query_as!(
         Course,
-        "SELECT * FROM ezy_course_c6 WHERE tutor_id = $1 and course_id = $2",
+        "SELECT * FROM ezy_course_c6 WHERE tutor_id = $1 and course_id = $2 FOR UPDATE",
         tutor_id,
         course_id,
     )
-    .fetch_one(pg_pool)
-    .await
-    .map_err(|_| EzyTutorError::NotFound("Course id not found".to_string()))?;
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Course id not found".to_string()))?;
+    let before = with_details(&mut tx, vec![current.clone()])
+        .await?
+        .remove(0);
 
     let name = update_course.course_name.unwrap_or(current.course_name);
     let description = update_course
@@ -141,6 +427,7 @@ pub async fn update_course_datails(
     let price = update_course
         .course_price
         .unwrap_or_else(|| current.course_price.unwrap_or_default());
+    let first_session_at = update_course.first_session_at.or(current.first_session_at);
 
     let updated_course = sqlx::query_as!(
         Course,
@@ -152,14 +439,16 @@ pub async fn update_course_datails(
             course_duration = $5,
             course_price = $6,
             course_language = $7,
-            course_level = $8
-        WHERE tutor_id = $9 and course_id = $10
+            course_level = $8,
+            first_session_at = $9
+        WHERE tutor_id = $10 and course_id = $11
         RETURNING
             tutor_id, course_id,
             course_name, course_description,
             course_duration, course_level,
             course_format, course_language,
-            course_structure, course_price, posted_time",
+            course_structure, course_price, posted_time,
+            course_status, publish_at, first_session_at",
         name,
         description,
         format,
@@ -168,10 +457,132 @@ pub async fn update_course_datails(
         price,
         language,
         level,
+        first_session_at,
         tutor_id,
         course_id
     )
-    .fetch_one(pg_pool)
+    .fetch_one(&mut *tx)
     .await?;
+
+    record_course_revision(&mut tx, Some(&before.course), &updated_course, None).await?;
+    set_course_taxonomy(
+        &mut tx,
+        course_id,
+        update_course.category_ids.as_deref(),
+        update_course.tags.as_deref(),
+    )
+    .await?;
+    let updated_course = with_details(&mut tx, vec![updated_course]).await?.remove(0);
+    let event = ChangeEvent::course(ChangeAction::Updated, tutor_id, course_id);
+    record_change(&mut tx, &event, Some(&before), Some(&updated_course)).await?;
+    tx.commit().await?;
     Ok(updated_course)
 }
+
+/// Moves the course along its lifecycle. Publishing with a future `publish_at` schedules
+/// the course instead, and the first publication sets its `posted_time`.
+pub async fn transition_course(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    transition: CourseTransition,
+    publish_at: Option<DateTime<Utc>>,
+) -> Result<Course, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    let before = sqlx::query_as!(
+        Course,
+        "SELECT * FROM ezy_course_c6 WHERE tutor_id = $1 and course_id = $2 FOR UPDATE",
+        tutor_id,
+        course_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Course id not found".to_string()))?;
+
+    let allowed = CourseStatus::parse(&before.course_status)
+        .is_some_and(|status| transition.allowed_from().contains(&status));
+    if !allowed {
+        return Err(EzyTutorError::Conflict(format!(
+            "Cannot {} a course that is {}",
+            transition.as_str(),
+            before.course_status.replace('_', " ")
+        )));
+    }
+
+    let (status, publish_at) = match transition {
+        CourseTransition::Submit => (CourseStatus::InReview, None),
+        CourseTransition::Reject | CourseTransition::Reopen => (CourseStatus::Draft, None),
+        CourseTransition::Archive => (CourseStatus::Archived, None),
+        CourseTransition::Publish => match publish_at {
+            Some(publish_at) if publish_at > Utc::now() => {
+                (CourseStatus::Scheduled, Some(publish_at))
+            }
+            _ => (CourseStatus::Published, None),
+        },
+    };
+    let course = sqlx::query_as!(
+        Course,
+        "UPDATE ezy_course_c6 SET
+            course_status = $1::varchar,
+            publish_at = $2,
+            posted_time = CASE WHEN $1::varchar = 'published'
+                THEN coalesce(posted_time, now()) ELSE posted_time END
+        WHERE tutor_id = $3 and course_id = $4
+        RETURNING *",
+        status.as_str(),
+        publish_at,
+        tutor_id,
+        course_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let event = ChangeEvent::course(ChangeAction::Updated, tutor_id, course_id);
+    record_change(&mut tx, &event, Some(&before), Some(&course)).await?;
+    tx.commit().await?;
+
+    Ok(course)
+}
+
+/// Publishes up to `limit` scheduled courses whose `publish_at` has passed, skipping any
+/// another instance is publishing at the same time.
+pub async fn publish_due_courses(
+    pg_pool: &PgPool,
+    limit: i64,
+) -> Result<Vec<Course>, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    let due = sqlx::query_as!(
+        Course,
+        "SELECT * FROM ezy_course_c6
+        WHERE course_status = 'scheduled' and publish_at <= now()
+        ORDER BY publish_at
+        LIMIT $1
+        FOR UPDATE SKIP LOCKED",
+        limit,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut published = Vec::with_capacity(due.len());
+    for before in due {
+        let course = sqlx::query_as!(
+            Course,
+            "UPDATE ezy_course_c6 SET
+                course_status = 'published',
+                posted_time = coalesce(posted_time, publish_at::timestamp),
+                publish_at = NULL
+            WHERE course_id = $1
+            RETURNING *",
+            before.course_id,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let event = ChangeEvent::course(ChangeAction::Updated, course.tutor_id, course.course_id);
+        record_change(&mut tx, &event, Some(&before), Some(&course)).await?;
+        published.push(course);
+    }
+    tx.commit().await?;
+
+    Ok(published)
+}
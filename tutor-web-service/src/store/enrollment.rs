@@ -0,0 +1,109 @@
+use crate::errors::EzyTutorError;
+use crate::middleware::current_student;
+use crate::models::Enrollment;
+use crate::store::{ensure_course, missing_prerequisites};
+
+use sqlx::postgres::PgPool;
+
+/// The caller's enrollments, most recent first.
+pub async fn get_enrollments(pg_pool: &PgPool) -> Result<Vec<Enrollment>, EzyTutorError> {
+    let enrollments = sqlx::query_as!(
+        Enrollment,
+        "SELECT * FROM ezy_enrollment WHERE student = $1
+        ORDER BY enrolled_at DESC, course_id",
+        current_student()?,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(enrollments)
+}
+
+/// Enrolls the caller in a published free course. With `require_prerequisites` they must
+/// have completed each of its prerequisites first; enrolling again changes nothing.
+pub async fn enroll_in_course(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    require_prerequisites: bool,
+) -> Result<Enrollment, EzyTutorError> {
+    let student = current_student()?;
+    let mut tx = pg_pool.begin().await?;
+    let course = sqlx::query!(
+        "SELECT course_status, course_price FROM ezy_course_c6
+        WHERE tutor_id = $1 and course_id = $2
+        FOR SHARE",
+        tutor_id,
+        course_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Course id not found".to_string()))?;
+
+    let existing = sqlx::query_as!(
+        Enrollment,
+        "SELECT * FROM ezy_enrollment WHERE course_id = $1 and student = $2",
+        course_id,
+        student,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(enrollment) = existing {
+        return Ok(enrollment);
+    }
+    if course.course_status != "published" {
+        return Err(EzyTutorError::Conflict(
+            "Only published courses accept enrollments".to_string(),
+        ));
+    }
+    if course.course_price.unwrap_or(0) > 0 {
+        return Err(EzyTutorError::Conflict(
+            "Paid courses are enrolled in by checking out".to_string(),
+        ));
+    }
+    if require_prerequisites {
+        let missing = missing_prerequisites(&mut tx, course_id, &student).await?;
+        if !missing.is_empty() {
+            return Err(EzyTutorError::Conflict(format!(
+                "Complete prerequisite courses {:?} first",
+                missing
+            )));
+        }
+    }
+
+    let enrollment = sqlx::query_as!(
+        Enrollment,
+        "INSERT INTO ezy_enrollment (course_id, student)
+        VALUES ($1, $2)
+        ON CONFLICT (course_id, student) DO UPDATE SET student = excluded.student
+        RETURNING *",
+        course_id,
+        student,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(enrollment)
+}
+
+/// Marks the caller's enrollment in the course completed, keeping the first completion.
+pub async fn complete_course(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+) -> Result<Enrollment, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    ensure_course(&mut conn, tutor_id, course_id).await?;
+    sqlx::query_as!(
+        Enrollment,
+        "UPDATE ezy_enrollment SET completed_at = coalesce(completed_at, now())
+        WHERE course_id = $1 and student = $2
+        RETURNING *",
+        course_id,
+        current_student()?,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Not enrolled in course".to_string()))
+}
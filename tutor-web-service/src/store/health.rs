@@ -0,0 +1,12 @@
+use crate::errors::EzyTutorError;
+
+use sqlx::postgres::PgPool;
+
+/// How many health checks any instance answered before this one.
+pub async fn next_visit_count(pg_pool: &PgPool) -> Result<i64, EzyTutorError> {
+    let visit_count = sqlx::query_scalar!(r#"SELECT nextval('ezy_visit_count_seq') AS "count!""#)
+        .fetch_one(pg_pool)
+        .await?;
+
+    Ok(visit_count)
+}
@@ -0,0 +1,315 @@
+use crate::errors::EzyTutorError;
+use crate::models::{EarningsBalance, EarningsStatement, Payout, StatementLine, StatementQuery};
+use crate::pricing::refunded_fee;
+
+use sqlx::postgres::{PgConnection, PgPool};
+
+use std::collections::BTreeMap;
+
+/// Records what the paid order earned each of its tutors, and the platform's fees on it.
+pub async fn record_sale(conn: &mut PgConnection, order_id: i32) -> Result<(), EzyTutorError> {
+    let sales = sqlx::query!(
+        r#"SELECT i.tutor_id, o.currency,
+            sum(i.final_price)::int AS "gross!", sum(i.platform_fee)::int AS "fee!"
+        FROM ezy_order_item i
+        JOIN ezy_order o USING (order_id)
+        WHERE i.order_id = $1
+        GROUP BY i.tutor_id, o.currency
+        ORDER BY i.tutor_id"#,
+        order_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for sale in sales {
+        post_transaction(
+            conn,
+            "sale",
+            sale.tutor_id,
+            order_id,
+            &sale.currency,
+            &[
+                ("student_payments", -sale.gross),
+                ("platform_fees", sale.fee),
+                ("tutor_available", sale.gross - sale.fee),
+            ],
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Records the approved refund against its items' tutors, handing back the platform's fees
+/// on them in proportion to what was refunded.
+pub async fn record_refund(conn: &mut PgConnection, refund_id: i32) -> Result<(), EzyTutorError> {
+    let items = sqlx::query!(
+        "SELECT i.tutor_id, o.currency, ri.amount, i.final_price, i.platform_fee
+        FROM ezy_refund_item ri
+        JOIN ezy_order_item i USING (order_id, line_no)
+        JOIN ezy_order o USING (order_id)
+        WHERE ri.refund_id = $1",
+        refund_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut refunds: BTreeMap<(i32, String), (i32, i32)> = BTreeMap::new();
+    for item in items {
+        let (amount, fee) = refunds.entry((item.tutor_id, item.currency)).or_default();
+        *amount += item.amount;
+        *fee += refunded_fee(item.platform_fee, item.final_price, item.amount);
+    }
+    for ((tutor_id, currency), (amount, fee)) in refunds {
+        post_transaction(
+            conn,
+            "refund",
+            tutor_id,
+            refund_id,
+            &currency,
+            &[
+                ("student_payments", amount),
+                ("platform_fees", -fee),
+                ("tutor_available", fee - amount),
+            ],
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn get_earnings_balance(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    currency: &str,
+) -> Result<EarningsBalance, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    ensure_tutor(&mut conn, tutor_id).await?;
+    let balance = sqlx::query_as!(
+        EarningsBalance,
+        r#"SELECT $1::int AS "tutor_id!", $2::varchar AS "currency!",
+            coalesce(-sum(e.amount)
+                FILTER (WHERE e.account = 'student_payments' and t.kind = 'sale'), 0)
+                AS "gross_sales!",
+            coalesce(sum(e.amount)
+                FILTER (WHERE e.account = 'student_payments' and t.kind = 'refund'), 0)
+                AS "refunds!",
+            coalesce(sum(e.amount) FILTER (WHERE e.account = 'platform_fees'), 0)
+                AS "platform_fees!",
+            coalesce(sum(e.amount) FILTER (WHERE e.account = 'tutor_available'), 0)
+                AS "available!",
+            coalesce(sum(e.amount) FILTER (WHERE e.account = 'tutor_paid_out'), 0)
+                AS "paid_out!"
+        FROM ezy_ledger_transaction t
+        JOIN ezy_ledger_entry e USING (transaction_id)
+        WHERE t.tutor_id = $1 and t.currency = $2"#,
+        tutor_id,
+        currency,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(balance)
+}
+
+/// The tutor's ledger transactions in the query's period, oldest first, with the available
+/// balance after each.
+pub async fn get_earnings_statement(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    currency: &str,
+    query: StatementQuery,
+) -> Result<EarningsStatement, EzyTutorError> {
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from >= to {
+            return Err(EzyTutorError::InvalidInput(
+                "from must be before to".to_string(),
+            ));
+        }
+    }
+    let mut tx = pg_pool.begin().await?;
+    // Statements are read in one snapshot so the lines add up to the closing balance.
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        .execute(&mut *tx)
+        .await?;
+    ensure_tutor(&mut tx, tutor_id).await?;
+    let opening_balance = sqlx::query_scalar!(
+        r#"SELECT coalesce(sum(e.amount), 0) AS "balance!"
+        FROM ezy_ledger_transaction t
+        JOIN ezy_ledger_entry e USING (transaction_id)
+        WHERE t.tutor_id = $1 and t.currency = $2 and e.account = 'tutor_available'
+            and t.recorded_at < $3"#,
+        tutor_id,
+        currency,
+        query.from,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    let mut lines = sqlx::query_as!(
+        StatementLine,
+        r#"SELECT t.transaction_id, t.kind, t.reference_id, t.recorded_at,
+            coalesce(-sum(e.amount) FILTER (WHERE e.account = 'student_payments'), 0)
+                AS "gross_amount!",
+            coalesce(sum(e.amount) FILTER (WHERE e.account = 'platform_fees'), 0)
+                AS "platform_fee!",
+            coalesce(sum(e.amount) FILTER (WHERE e.account = 'tutor_available'), 0)
+                AS "net_amount!",
+            coalesce(sum(e.amount) FILTER (WHERE e.account = 'tutor_paid_out'), 0)
+                AS "paid_out!",
+            0::bigint AS "balance!"
+        FROM ezy_ledger_transaction t
+        JOIN ezy_ledger_entry e USING (transaction_id)
+        WHERE t.tutor_id = $1 and t.currency = $2
+            and ($3::timestamptz IS NULL or t.recorded_at >= $3)
+            and ($4::timestamptz IS NULL or t.recorded_at < $4)
+        GROUP BY t.transaction_id
+        ORDER BY t.recorded_at, t.transaction_id"#,
+        tutor_id,
+        currency,
+        query.from,
+        query.to,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let mut balance = opening_balance;
+    for line in &mut lines {
+        balance += line.net_amount;
+        line.balance = balance;
+    }
+
+    Ok(EarningsStatement {
+        tutor_id,
+        currency: currency.to_string(),
+        from: query.from,
+        to: query.to,
+        opening_balance,
+        closing_balance: balance,
+        lines,
+    })
+}
+
+/// The tutor's payouts, most recent first.
+pub async fn get_payouts(pg_pool: &PgPool, tutor_id: i32) -> Result<Vec<Payout>, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    ensure_tutor(&mut conn, tutor_id).await?;
+    let payouts = sqlx::query_as!(
+        Payout,
+        "SELECT * FROM ezy_payout WHERE tutor_id = $1 ORDER BY created_at DESC, payout_id DESC",
+        tutor_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(payouts)
+}
+
+/// Pays every tutor whose available balance in the currency reached `min_payout`, moving
+/// it to paid out. Concurrent batches wait for each other, then find nothing left to pay.
+pub async fn pay_out_earnings(
+    pg_pool: &PgPool,
+    currency: &str,
+    min_payout: i64,
+) -> Result<Vec<Payout>, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    sqlx::query!("LOCK TABLE ezy_payout IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+    let balances = sqlx::query!(
+        r#"SELECT t.tutor_id, sum(e.amount) AS "available!"
+        FROM ezy_ledger_transaction t
+        JOIN ezy_ledger_entry e USING (transaction_id)
+        WHERE t.currency = $1 and e.account = 'tutor_available'
+        GROUP BY t.tutor_id
+        HAVING sum(e.amount) >= $2
+        ORDER BY t.tutor_id"#,
+        currency,
+        min_payout,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut payouts = Vec::with_capacity(balances.len());
+    for balance in balances {
+        // Anything beyond what one payout can hold goes out with the next batch.
+        let amount = i32::try_from(balance.available).unwrap_or(i32::MAX);
+        let payout = sqlx::query_as!(
+            Payout,
+            "INSERT INTO ezy_payout (tutor_id, amount, currency)
+            VALUES ($1, $2, $3)
+            RETURNING *",
+            balance.tutor_id,
+            amount,
+            currency,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        post_transaction(
+            &mut tx,
+            "payout",
+            payout.tutor_id,
+            payout.payout_id,
+            currency,
+            &[("tutor_available", -amount), ("tutor_paid_out", amount)],
+        )
+        .await?;
+        payouts.push(payout);
+    }
+    tx.commit().await?;
+
+    Ok(payouts)
+}
+
+/// Records a transaction moving money between the accounts, credits being positive and
+/// debits negative. Zero amounts are left out, and with them transactions moving nothing.
+async fn post_transaction(
+    conn: &mut PgConnection,
+    kind: &str,
+    tutor_id: i32,
+    reference_id: i32,
+    currency: &str,
+    entries: &[(&str, i32)],
+) -> Result<(), EzyTutorError> {
+    let (accounts, amounts): (Vec<String>, Vec<i32>) = entries
+        .iter()
+        .filter(|(_, amount)| *amount != 0)
+        .map(|(account, amount)| (account.to_string(), *amount))
+        .unzip();
+    if amounts.is_empty() {
+        return Ok(());
+    }
+    let transaction_id = sqlx::query_scalar!(
+        "INSERT INTO ezy_ledger_transaction (kind, tutor_id, reference_id, currency)
+        VALUES ($1, $2, $3, $4)
+        RETURNING transaction_id",
+        kind,
+        tutor_id,
+        reference_id,
+        currency,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO ezy_ledger_entry (transaction_id, account, amount)
+        SELECT $1, account, amount FROM unnest($2::varchar[], $3::int[]) AS e(account, amount)",
+        transaction_id,
+        &accounts,
+        &amounts,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+async fn ensure_tutor(conn: &mut PgConnection, tutor_id: i32) -> Result<(), EzyTutorError> {
+    sqlx::query_scalar!(
+        "SELECT tutor_id FROM ezy_tutor_c6 WHERE tutor_id = $1",
+        tutor_id,
+    )
+    .fetch_optional(conn)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".to_string()))?;
+
+    Ok(())
+}
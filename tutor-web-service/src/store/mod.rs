@@ -1,5 +1,35 @@
+mod audit;
+mod bundle;
+mod category;
+mod change;
 mod course;
+mod enrollment;
+mod health;
+mod ledger;
+mod notify;
+mod order;
+mod prerequisite;
+mod promotion;
+mod refund;
+mod revision;
+mod translation;
 mod tutor;
+mod webhook;
 
+pub use audit::*;
+pub use bundle::*;
+pub use category::*;
+pub use change::*;
 pub use course::*;
+pub use enrollment::*;
+pub use health::*;
+pub use ledger::*;
+pub use notify::*;
+pub use order::*;
+pub use prerequisite::*;
+pub use promotion::*;
+pub use refund::*;
+pub use revision::*;
+pub use translation::*;
 pub use tutor::*;
+pub use webhook::*;
@@ -0,0 +1,31 @@
+use crate::errors::EzyTutorError;
+use crate::models::ChangeEvent;
+
+use sqlx::postgres::PgConnection;
+
+/// Postgres channel carrying [`ChangeEvent`]s as JSON.
+pub const CHANGE_CHANNEL: &str = "ezy_changes";
+
+/// Queues `event` for every listening instance under a freshly assigned `change_id`.
+/// Run it inside the transaction making the change: Postgres only delivers the
+/// notification once that commits.
+pub async fn notify_change(
+    conn: &mut PgConnection,
+    event: &ChangeEvent,
+) -> Result<(), EzyTutorError> {
+    let change_id = sqlx::query_scalar!(r#"SELECT nextval('ezy_change_id_seq') AS "change_id!""#)
+        .fetch_one(&mut *conn)
+        .await?;
+    let event = ChangeEvent {
+        change_id: Some(change_id),
+        ..event.clone()
+    };
+    let payload = serde_json::to_string(&event).map_err(actix_web::Error::from)?;
+    // pg_notify returns void, which the checked query macros cannot describe.
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(CHANGE_CHANNEL)
+        .bind(payload)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,594 @@
+use crate::errors::EzyTutorError;
+use crate::middleware::current_student;
+use crate::models::{Cart, CartItem, Checkout, Coupon, Order, OrderDetails};
+use crate::payments::{PaymentEvent, PaymentOutcome, PaymentProvider};
+use crate::pricing::{apply_coupon, check_redeemable, coupon_applies, platform_fee, sale_price};
+use crate::store::{cart_coupon, missing_prerequisites, record_sale, redeem_coupon};
+use tutor_web_common::db::{is_transient, next_backoff};
+
+use chrono::Utc;
+use sqlx::postgres::{PgConnection, PgPool};
+
+use std::time::Duration;
+
+/// Most items one cart may hold.
+const MAX_CART_ITEMS: i64 = 50;
+/// Attempts at attaching a started payment to its order before leaving it to
+/// [`reconcile_checkouts`].
+const ATTACH_ATTEMPTS: u32 = 3;
+const ATTACH_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_ATTACH_BACKOFF: Duration = Duration::from_secs(1);
+
+pub async fn get_cart(pg_pool: &PgPool, currency: &str) -> Result<Cart, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    let (items, coupon, _) = priced_cart(&mut conn, &current_student()?).await?;
+    Ok(Cart {
+        coupon_code: coupon.map(|coupon| coupon.code),
+        original_amount: items
+            .iter()
+            .map(|item| i64::from(item.price.original_price))
+            .sum(),
+        discount_amount: items
+            .iter()
+            .map(|item| i64::from(item.price.discount))
+            .sum(),
+        total_amount: items
+            .iter()
+            .map(|item| i64::from(item.price.final_price))
+            .sum(),
+        items,
+        currency: currency.to_string(),
+    })
+}
+
+/// Adds a published course the caller is not enrolled in to their cart.
+pub async fn add_course_to_cart(pg_pool: &PgPool, course_id: i32) -> Result<(), EzyTutorError> {
+    let student = current_student()?;
+    let mut tx = pg_pool.begin().await?;
+    let status = sqlx::query_scalar!(
+        "SELECT course_status FROM ezy_course_c6 WHERE course_id = $1",
+        course_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Course id not found".to_string()))?;
+    if status != "published" {
+        return Err(EzyTutorError::Conflict(format!(
+            "Course {} is not available",
+            course_id
+        )));
+    }
+    let enrolled = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM ezy_enrollment WHERE course_id = $1 and student = $2
+        ) AS "enrolled!""#,
+        course_id,
+        student,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if enrolled {
+        return Err(EzyTutorError::Conflict(format!(
+            "Already enrolled in course {}",
+            course_id
+        )));
+    }
+    add_to_cart(&mut tx, &student, Some(course_id), None).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub async fn add_bundle_to_cart(pg_pool: &PgPool, bundle_id: i32) -> Result<(), EzyTutorError> {
+    let student = current_student()?;
+    let mut tx = pg_pool.begin().await?;
+    sqlx::query_scalar!(
+        "SELECT bundle_id FROM ezy_bundle WHERE bundle_id = $1",
+        bundle_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Bundle id not found".to_string()))?;
+    add_to_cart(&mut tx, &student, None, Some(bundle_id)).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Removes the course, or with `course_id` `None` the bundle, from the caller's cart.
+pub async fn remove_from_cart(
+    pg_pool: &PgPool,
+    course_id: Option<i32>,
+    bundle_id: Option<i32>,
+) -> Result<(), EzyTutorError> {
+    let res = sqlx::query!(
+        "DELETE FROM ezy_cart_item
+        WHERE student = $1 and course_id IS NOT DISTINCT FROM $2
+            and bundle_id IS NOT DISTINCT FROM $3",
+        current_student()?,
+        course_id,
+        bundle_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(EzyTutorError::NotFound(
+            "Item is not in the cart".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Turns the caller's cart into a pending order and starts paying for it, redeeming the
+/// cart's coupon when it takes anything off. The platform keeps `platform_fee_percent` of
+/// each item's price. Free orders are paid for at once.
+///
+/// The order is committed before the provider is asked for a payment, keyed by the order
+/// id, and the payment attached to it afterwards. When the provider fails, the order fails
+/// and the cart is restored. Payments that cannot be attached are attached later by
+/// [`reconcile_checkouts`].
+pub async fn checkout(
+    pg_pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    currency: &str,
+    require_prerequisites: bool,
+    platform_fee_percent: u32,
+) -> Result<Checkout, EzyTutorError> {
+    let student = current_student()?;
+    let mut tx = pg_pool.begin().await?;
+    // Serializes checkouts of the same cart.
+    sqlx::query!(
+        "SELECT student FROM ezy_cart_item WHERE student = $1 FOR UPDATE",
+        student,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let (items, coupon, coupon_discount) = priced_cart(&mut tx, &student).await?;
+    if items.is_empty() {
+        return Err(EzyTutorError::InvalidInput("Cart is empty".to_string()));
+    }
+
+    let course_ids: Vec<i32> = items
+        .iter()
+        .flat_map(|item| item.course_ids.iter().copied())
+        .collect();
+    if let Some(course_id) = sqlx::query_scalar!(
+        "SELECT course_id FROM ezy_course_c6
+        WHERE course_id = ANY($1) and course_status <> 'published'
+        ORDER BY course_id LIMIT 1",
+        &course_ids,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    {
+        return Err(EzyTutorError::Conflict(format!(
+            "Course {} is not available",
+            course_id
+        )));
+    }
+    // Bundles list their courses in prerequisite order, so only single courses are checked,
+    // counting the courses bought along with them as completed.
+    if require_prerequisites {
+        for course_id in items.iter().filter_map(|item| item.course_id) {
+            let missing: Vec<i32> = missing_prerequisites(&mut tx, course_id, &student)
+                .await?
+                .into_iter()
+                .filter(|id| !course_ids.contains(id))
+                .collect();
+            if !missing.is_empty() {
+                return Err(EzyTutorError::Conflict(format!(
+                    "Complete prerequisite courses {:?} of course {} first",
+                    missing, course_id
+                )));
+            }
+        }
+    }
+    let amount = |price: fn(&CartItem) -> i32| {
+        i32::try_from(items.iter().map(|item| i64::from(price(item))).sum::<i64>())
+            .map_err(|_| EzyTutorError::InvalidInput("Order total is too large".to_string()))
+    };
+    let original_amount = amount(|item| item.price.original_price)?;
+    let total_amount = amount(|item| item.price.final_price)?;
+    // A coupon taking nothing off, say one for another tutor's courses, is not redeemed.
+    let coupon = coupon.filter(|_| coupon_discount > 0);
+    if let Some(coupon) = &coupon {
+        redeem_coupon(&mut tx, coupon).await?;
+    }
+
+    let order = sqlx::query_as!(
+        Order,
+        "INSERT INTO ezy_order (
+            student, original_amount, discount_amount, total_amount, currency, coupon_id,
+            coupon_code
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING *",
+        student,
+        original_amount,
+        original_amount - total_amount,
+        total_amount,
+        currency,
+        coupon.as_ref().map(|coupon| coupon.coupon_id),
+        coupon.as_ref().map(|coupon| &coupon.code),
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    for (line_no, item) in (1..).zip(&items) {
+        sqlx::query!(
+            "INSERT INTO ezy_order_item (
+                order_id, line_no, course_id, bundle_id, tutor_id, item_name, original_price,
+                discount, final_price, platform_fee, course_ids
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            order.order_id,
+            line_no,
+            item.course_id,
+            item.bundle_id,
+            item.tutor_id,
+            item.item_name,
+            item.price.original_price,
+            item.price.discount,
+            item.price.final_price,
+            platform_fee(item.price.final_price, platform_fee_percent),
+            &item.course_ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    sqlx::query!("DELETE FROM ezy_cart_item WHERE student = $1", student)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM ezy_cart_coupon WHERE student = $1", student)
+        .execute(&mut *tx)
+        .await?;
+
+    if total_amount == 0 {
+        settle_order(&mut tx, order.order_id, PaymentOutcome::Succeeded).await?;
+        let order = load_order(&mut tx, &student, order.order_id).await?;
+        tx.commit().await?;
+        return Ok(Checkout {
+            order,
+            checkout_url: None,
+        });
+    }
+    // The provider is only called for committed orders, so no payment is ever started for
+    // an order that was rolled back.
+    tx.commit().await?;
+
+    let payment = match provider.create_payment(&order).await {
+        Ok(payment) => payment,
+        Err(err) => {
+            abandon_checkout(pg_pool, order.order_id).await?;
+            return Err(err);
+        }
+    };
+    let mut backoff = ATTACH_BACKOFF;
+    for attempt in 1.. {
+        match attach_payment(pg_pool, order.order_id, &payment.payment_id).await {
+            Err(EzyTutorError::DbError(err)) if attempt < ATTACH_ATTEMPTS && is_transient(&err) => {
+                tracing::warn!(
+                    order_id = order.order_id,
+                    error = %err,
+                    "Could not attach payment to order, retrying"
+                );
+                actix_web::rt::time::sleep(backoff).await;
+                backoff = next_backoff(backoff, MAX_ATTACH_BACKOFF);
+            }
+            result => {
+                result?;
+                break;
+            }
+        }
+    }
+    let mut conn = pg_pool.acquire().await?;
+    let order = load_order(&mut conn, &student, order.order_id).await?;
+
+    Ok(Checkout {
+        order,
+        checkout_url: payment.checkout_url,
+    })
+}
+
+/// Attaches the payment started for the order, unless another one already is.
+async fn attach_payment(
+    pg_pool: &PgPool,
+    order_id: i32,
+    payment_id: &str,
+) -> Result<(), EzyTutorError> {
+    sqlx::query!(
+        "UPDATE ezy_order SET payment_id = $1 WHERE order_id = $2 and payment_id IS NULL",
+        payment_id,
+        order_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Asks the provider again for the payments of up to `limit` pending orders that have gone
+/// without one for `age`, oldest first, and attaches them. The provider returns the payment
+/// it already started for an order, so only orders checkout failed to attach one to are
+/// affected. Returns the ids of the orders given a payment.
+pub async fn reconcile_checkouts(
+    pg_pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    age: Duration,
+    limit: i64,
+) -> Result<Vec<i32>, EzyTutorError> {
+    let orders = sqlx::query_as!(
+        Order,
+        "SELECT * FROM ezy_order
+        WHERE order_status = 'pending' and payment_id IS NULL
+            and created_at < now() - $1 * interval '1 second'
+        ORDER BY order_id
+        LIMIT $2",
+        age.as_secs_f64(),
+        limit,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    let mut reconciled = Vec::with_capacity(orders.len());
+    for order in &orders {
+        match provider.create_payment(order).await {
+            Ok(payment) => {
+                attach_payment(pg_pool, order.order_id, &payment.payment_id).await?;
+                reconciled.push(order.order_id);
+            }
+            Err(err) => tracing::warn!(
+                order_id = order.order_id,
+                error = %err,
+                "Could not look up the payment for order"
+            ),
+        }
+    }
+
+    Ok(reconciled)
+}
+
+/// Fails an order whose payment could not be started, handing back its coupon redemption
+/// and putting its items and coupon back in the student's cart to check out again.
+async fn abandon_checkout(pg_pool: &PgPool, order_id: i32) -> Result<(), EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    settle_order(&mut tx, order_id, PaymentOutcome::Failed).await?;
+    sqlx::query!(
+        "INSERT INTO ezy_cart_item (student, course_id, bundle_id)
+        SELECT o.student, i.course_id, i.bundle_id
+        FROM ezy_order o
+        JOIN ezy_order_item i USING (order_id)
+        WHERE o.order_id = $1 and (i.course_id IS NULL) <> (i.bundle_id IS NULL)
+        ORDER BY i.line_no
+        ON CONFLICT DO NOTHING",
+        order_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO ezy_cart_coupon (student, coupon_id)
+        SELECT student, coupon_id FROM ezy_order
+        WHERE order_id = $1 and coupon_id IS NOT NULL
+        ON CONFLICT DO NOTHING",
+        order_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Settles the pending order the payment is for, enrolling its student in everything
+/// bought and crediting its tutors when it succeeded. Notifications about settled orders
+/// change nothing, so the provider may repeat them, and ones arriving before the payment
+/// is attached to its order fail with `NotFound` for the provider to retry.
+pub async fn confirm_payment(
+    pg_pool: &PgPool,
+    event: PaymentEvent,
+) -> Result<OrderDetails, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    let order = sqlx::query_as!(
+        Order,
+        "SELECT * FROM ezy_order WHERE payment_id = $1 FOR UPDATE",
+        event.payment_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Payment not found".to_string()))?;
+    if order.order_status == "pending" {
+        settle_order(&mut tx, order.order_id, event.outcome).await?;
+    }
+    let order = load_order(&mut tx, &order.student, order.order_id).await?;
+    tx.commit().await?;
+
+    Ok(order)
+}
+
+/// The caller's orders, most recent first.
+pub async fn get_orders(pg_pool: &PgPool) -> Result<Vec<OrderDetails>, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    load_orders(&mut conn, &current_student()?, None).await
+}
+
+pub async fn get_order(pg_pool: &PgPool, order_id: i32) -> Result<OrderDetails, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    load_order(&mut conn, &current_student()?, order_id).await
+}
+
+async fn settle_order(
+    conn: &mut PgConnection,
+    order_id: i32,
+    outcome: PaymentOutcome,
+) -> Result<(), EzyTutorError> {
+    match outcome {
+        PaymentOutcome::Succeeded => {
+            sqlx::query!(
+                "UPDATE ezy_order SET order_status = 'paid', paid_at = now()
+                WHERE order_id = $1",
+                order_id,
+            )
+            .execute(&mut *conn)
+            .await?;
+            // Courses deleted since checkout are skipped.
+            sqlx::query!(
+                "INSERT INTO ezy_enrollment (course_id, student, order_id)
+                SELECT DISTINCT c.course_id, o.student, o.order_id
+                FROM ezy_order o
+                JOIN ezy_order_item i USING (order_id)
+                JOIN ezy_course_c6 c ON c.course_id = ANY(i.course_ids)
+                WHERE o.order_id = $1
+                ON CONFLICT (course_id, student) DO NOTHING",
+                order_id,
+            )
+            .execute(&mut *conn)
+            .await?;
+            record_sale(conn, order_id).await?;
+        }
+        PaymentOutcome::Failed => {
+            sqlx::query!(
+                "UPDATE ezy_order SET order_status = 'failed' WHERE order_id = $1",
+                order_id,
+            )
+            .execute(&mut *conn)
+            .await?;
+            // Frees the coupon's redemption for another order.
+            sqlx::query!(
+                "UPDATE ezy_coupon SET redemptions = redemptions - 1
+                WHERE coupon_id = (SELECT coupon_id FROM ezy_order WHERE order_id = $1)",
+                order_id,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn add_to_cart(
+    conn: &mut PgConnection,
+    student: &str,
+    course_id: Option<i32>,
+    bundle_id: Option<i32>,
+) -> Result<(), EzyTutorError> {
+    let count = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "count!" FROM ezy_cart_item WHERE student = $1"#,
+        student,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if count >= MAX_CART_ITEMS {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "A cart may hold at most {} items",
+            MAX_CART_ITEMS
+        )));
+    }
+    sqlx::query!(
+        "INSERT INTO ezy_cart_item (student, course_id, bundle_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING",
+        student,
+        course_id,
+        bundle_id,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// The student's cart at current prices, in the order items were added, with the coupon
+/// applied to it and how much that takes off while it can be redeemed.
+async fn priced_cart(
+    conn: &mut PgConnection,
+    student: &str,
+) -> Result<(Vec<CartItem>, Option<Coupon>, i64), EzyTutorError> {
+    let rows = sqlx::query!(
+        r#"SELECT ci.course_id, ci.bundle_id,
+            coalesce(c.tutor_id, b.tutor_id) AS "tutor_id!",
+            coalesce(c.course_name, b.bundle_name) AS "item_name!",
+            CASE WHEN ci.course_id IS NOT NULL THEN coalesce(c.course_price, 0)
+            ELSE b.bundle_price END AS "list_price!",
+            s.sale_price AS "sale_price?",
+            CASE WHEN ci.course_id IS NOT NULL THEN ARRAY[ci.course_id]
+            ELSE ARRAY(
+                SELECT bc.course_id FROM ezy_bundle_course bc
+                WHERE bc.bundle_id = ci.bundle_id
+                ORDER BY bc.position)
+            END AS "course_ids!",
+            ci.added_at
+        FROM ezy_cart_item ci
+        LEFT JOIN ezy_course_c6 c ON c.course_id = ci.course_id
+        LEFT JOIN ezy_bundle b ON b.bundle_id = ci.bundle_id
+        LEFT JOIN ezy_course_sale s ON s.course_id = ci.course_id
+            and s.starts_at <= now() and s.ends_at > now()
+        WHERE ci.student = $1
+        ORDER BY ci.added_at, ci.course_id, ci.bundle_id"#,
+        student,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut items: Vec<CartItem> = rows
+        .into_iter()
+        .map(|row| CartItem {
+            course_id: row.course_id,
+            bundle_id: row.bundle_id,
+            tutor_id: row.tutor_id,
+            item_name: row.item_name,
+            price: sale_price(row.list_price, row.sale_price),
+            course_ids: row.course_ids,
+            added_at: row.added_at,
+        })
+        .collect();
+
+    let coupon = cart_coupon(conn, student).await?;
+    let mut coupon_discount = 0;
+    if let Some(coupon) = coupon
+        .as_ref()
+        .filter(|coupon| check_redeemable(coupon, Utc::now()).is_ok())
+    {
+        coupon_discount = apply_coupon(
+            coupon,
+            items
+                .iter_mut()
+                .filter(|item| coupon_applies(coupon, item.tutor_id, item.course_id))
+                .map(|item| &mut item.price),
+        );
+    }
+
+    Ok((items, coupon, coupon_discount))
+}
+
+async fn load_order(
+    conn: &mut PgConnection,
+    student: &str,
+    order_id: i32,
+) -> Result<OrderDetails, EzyTutorError> {
+    load_orders(conn, student, Some(order_id))
+        .await?
+        .pop()
+        .ok_or_else(|| EzyTutorError::NotFound("Order id not found".to_string()))
+}
+
+async fn load_orders(
+    conn: &mut PgConnection,
+    student: &str,
+    order_id: Option<i32>,
+) -> Result<Vec<OrderDetails>, EzyTutorError> {
+    let orders = sqlx::query_scalar!(
+        r#"SELECT coalesce(jsonb_agg(
+            to_jsonb(o) || jsonb_build_object('items', (
+                SELECT coalesce(jsonb_agg(to_jsonb(i) ORDER BY i.line_no), '[]')
+                FROM ezy_order_item i
+                WHERE i.order_id = o.order_id))
+            ORDER BY o.created_at DESC, o.order_id DESC), '[]') AS "orders!"
+        FROM ezy_order o
+        WHERE o.student = $1 and ($2::int IS NULL or o.order_id = $2)"#,
+        student,
+        order_id,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(serde_json::from_value(orders).map_err(actix_web::Error::from)?)
+}
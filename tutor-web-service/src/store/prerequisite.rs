@@ -0,0 +1,161 @@
+use crate::errors::EzyTutorError;
+use crate::models::{ChangeAction, ChangeEvent, PrerequisiteCourse};
+use crate::store::{ensure_course, record_change};
+
+use sqlx::postgres::{PgConnection, PgPool};
+
+/// Every course the course transitively requires, in topological order: each course
+/// comes after all of its own prerequisites.
+pub async fn get_prerequisite_chain(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+) -> Result<Vec<PrerequisiteCourse>, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    ensure_course(&mut conn, tutor_id, course_id).await?;
+    prerequisite_chain(&mut conn, course_id).await
+}
+
+/// Requires `prerequisite_id` to be completed before the course, unless that would make
+/// either course a prerequisite of itself.
+pub async fn add_course_prerequisite(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    prerequisite_id: i32,
+) -> Result<Vec<PrerequisiteCourse>, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    // Edges are added one at a time so two of them cannot close a cycle between them.
+    sqlx::query!("LOCK TABLE ezy_course_prerequisite IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+    ensure_course(&mut tx, tutor_id, course_id).await?;
+    sqlx::query_scalar!(
+        "SELECT course_id FROM ezy_course_c6 WHERE course_id = $1",
+        prerequisite_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Prerequisite course not found".to_string()))?;
+
+    let cycle = sqlx::query_scalar!(
+        r#"WITH RECURSIVE required AS (
+            SELECT $1::int AS course_id
+            UNION
+            SELECT p.prerequisite_id FROM ezy_course_prerequisite p
+            JOIN required r ON p.course_id = r.course_id
+        )
+        SELECT EXISTS (SELECT 1 FROM required WHERE course_id = $2) AS "cycle!""#,
+        prerequisite_id,
+        course_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if cycle {
+        return Err(EzyTutorError::Conflict(
+            "A course cannot require itself or a course requiring it".to_string(),
+        ));
+    }
+
+    let added = sqlx::query!(
+        "INSERT INTO ezy_course_prerequisite (course_id, prerequisite_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING",
+        course_id,
+        prerequisite_id,
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if added > 0 {
+        let edge =
+            serde_json::json!({ "course_id": course_id, "prerequisite_id": prerequisite_id });
+        let event = ChangeEvent::course(ChangeAction::Updated, tutor_id, course_id);
+        record_change(&mut tx, &event, None, Some(&edge)).await?;
+    }
+    let chain = prerequisite_chain(&mut tx, course_id).await?;
+    tx.commit().await?;
+
+    Ok(chain)
+}
+
+pub async fn delete_course_prerequisite(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    prerequisite_id: i32,
+) -> Result<Vec<PrerequisiteCourse>, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    ensure_course(&mut tx, tutor_id, course_id).await?;
+    let deleted = sqlx::query!(
+        "DELETE FROM ezy_course_prerequisite WHERE course_id = $1 and prerequisite_id = $2",
+        course_id,
+        prerequisite_id,
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if deleted == 0 {
+        return Err(EzyTutorError::NotFound(
+            "Course prerequisite not found".to_string(),
+        ));
+    }
+
+    let edge = serde_json::json!({ "course_id": course_id, "prerequisite_id": prerequisite_id });
+    let event = ChangeEvent::course(ChangeAction::Updated, tutor_id, course_id);
+    record_change(&mut tx, &event, Some(&edge), None).await?;
+    let chain = prerequisite_chain(&mut tx, course_id).await?;
+    tx.commit().await?;
+
+    Ok(chain)
+}
+
+/// Orders the chain by each course's longest path from `course_id`, which puts every
+/// prerequisite before the courses requiring it.
+async fn prerequisite_chain(
+    conn: &mut PgConnection,
+    course_id: i32,
+) -> Result<Vec<PrerequisiteCourse>, EzyTutorError> {
+    let chain = sqlx::query_scalar!(
+        r#"WITH RECURSIVE chain (course_id, depth) AS (
+            SELECT prerequisite_id, 1 FROM ezy_course_prerequisite WHERE course_id = $1
+            UNION
+            SELECT p.prerequisite_id, c.depth + 1 FROM ezy_course_prerequisite p
+            JOIN chain c ON p.course_id = c.course_id
+        )
+        SELECT coalesce(jsonb_agg(
+            to_jsonb(c) || jsonb_build_object('prerequisite_ids', ARRAY(
+                SELECT p.prerequisite_id FROM ezy_course_prerequisite p
+                WHERE p.course_id = c.course_id
+                ORDER BY p.prerequisite_id))
+            ORDER BY o.depth DESC, o.course_id), '[]') AS "chain!"
+        FROM (SELECT course_id, max(depth) AS depth FROM chain GROUP BY course_id) o
+        JOIN ezy_course_c6 c USING (course_id)"#,
+        course_id,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(serde_json::from_value(chain).map_err(actix_web::Error::from)?)
+}
+
+/// The course's direct prerequisites the student has not completed.
+pub async fn missing_prerequisites(
+    conn: &mut PgConnection,
+    course_id: i32,
+    student: &str,
+) -> Result<Vec<i32>, EzyTutorError> {
+    let missing = sqlx::query_scalar!(
+        "SELECT p.prerequisite_id FROM ezy_course_prerequisite p
+        LEFT JOIN ezy_enrollment e
+            ON e.course_id = p.prerequisite_id and e.student = $2
+        WHERE p.course_id = $1 and e.completed_at IS NULL
+        ORDER BY p.prerequisite_id",
+        course_id,
+        student,
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(missing)
+}
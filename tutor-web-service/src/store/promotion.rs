@@ -0,0 +1,352 @@
+use crate::errors::EzyTutorError;
+use crate::middleware::current_student;
+use crate::models::{ChangeAction, ChangeEvent, Coupon, CourseSale, NewCoupon, NewCourseSale};
+use crate::pricing::check_redeemable;
+use crate::store::{ensure_course, record_change};
+
+use chrono::Utc;
+use sqlx::postgres::{PgConnection, PgPool};
+
+use std::collections::HashMap;
+
+const MAX_COUPON_CODE_LEN: usize = 40;
+
+/// The course's sales, past, current and upcoming, by when they start.
+pub async fn get_course_sales(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+) -> Result<Vec<CourseSale>, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    ensure_course(&mut conn, tutor_id, course_id).await?;
+    let sales = sqlx::query_as!(
+        CourseSale,
+        "SELECT * FROM ezy_course_sale WHERE course_id = $1 ORDER BY starts_at",
+        course_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(sales)
+}
+
+/// Puts the course on sale below its price for a while, which must not overlap its other
+/// sales. Recorded as an update of the course, whose price it changes.
+pub async fn post_course_sale(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    new_sale: NewCourseSale,
+) -> Result<CourseSale, EzyTutorError> {
+    let starts_at = new_sale.starts_at.unwrap_or_else(Utc::now);
+    if new_sale.ends_at <= starts_at || new_sale.ends_at <= Utc::now() {
+        return Err(EzyTutorError::InvalidInput(
+            "A sale must end after it starts and in the future".to_string(),
+        ));
+    }
+    let mut tx = pg_pool.begin().await?;
+    // Serializes sales of the same course, so overlapping ones cannot slip in together.
+    let course_price = sqlx::query_scalar!(
+        "SELECT course_price FROM ezy_course_c6
+        WHERE tutor_id = $1 and course_id = $2
+        FOR UPDATE",
+        tutor_id,
+        course_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Course id not found".to_string()))?;
+    if new_sale.sale_price < 0 || new_sale.sale_price >= course_price.unwrap_or(0) {
+        return Err(EzyTutorError::InvalidInput(
+            "Sale price must be below the course price".to_string(),
+        ));
+    }
+    let overlapping = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM ezy_course_sale
+            WHERE course_id = $1 and starts_at < $3 and ends_at > $2
+        ) AS "overlapping!""#,
+        course_id,
+        starts_at,
+        new_sale.ends_at,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if overlapping {
+        return Err(EzyTutorError::Conflict(
+            "The course is already on sale during that time".to_string(),
+        ));
+    }
+
+    let sale = sqlx::query_as!(
+        CourseSale,
+        "INSERT INTO ezy_course_sale (course_id, sale_price, starts_at, ends_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *",
+        course_id,
+        new_sale.sale_price,
+        starts_at,
+        new_sale.ends_at,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    let event = ChangeEvent::course(ChangeAction::Updated, tutor_id, course_id);
+    record_change(&mut tx, &event, None, Some(&sale)).await?;
+    tx.commit().await?;
+
+    Ok(sale)
+}
+
+/// Ends the sale; orders already placed keep the price they were placed at.
+pub async fn delete_course_sale(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    sale_id: i32,
+) -> Result<String, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    ensure_course(&mut tx, tutor_id, course_id).await?;
+    let sale = sqlx::query_as!(
+        CourseSale,
+        "SELECT * FROM ezy_course_sale WHERE course_id = $1 and sale_id = $2 FOR UPDATE",
+        course_id,
+        sale_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Sale id not found".to_string()))?;
+    let res = sqlx::query!("DELETE FROM ezy_course_sale WHERE sale_id = $1", sale_id)
+        .execute(&mut *tx)
+        .await?;
+    let event = ChangeEvent::course(ChangeAction::Updated, tutor_id, course_id);
+    record_change(&mut tx, &event, Some(&sale), None).await?;
+    tx.commit().await?;
+
+    Ok(format!("Deleted {:?} record", res))
+}
+
+/// The sale each of the courses is on right now, if any.
+pub async fn current_sales(
+    conn: &mut PgConnection,
+    course_ids: &[i32],
+) -> Result<HashMap<i32, CourseSale>, EzyTutorError> {
+    let sales = sqlx::query_as!(
+        CourseSale,
+        "SELECT * FROM ezy_course_sale
+        WHERE course_id = ANY($1) and starts_at <= now() and ends_at > now()",
+        course_ids,
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(sales
+        .into_iter()
+        .map(|sale| (sale.course_id, sale))
+        .collect())
+}
+
+/// The tutor's coupons, newest first.
+pub async fn get_coupons(pg_pool: &PgPool, tutor_id: i32) -> Result<Vec<Coupon>, EzyTutorError> {
+    let coupons = sqlx::query_as!(
+        Coupon,
+        "SELECT * FROM ezy_coupon WHERE tutor_id = $1 ORDER BY created_at DESC, coupon_id DESC",
+        tutor_id,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(coupons)
+}
+
+pub async fn post_new_coupon(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    new_coupon: NewCoupon,
+) -> Result<Coupon, EzyTutorError> {
+    let code = coupon_code(&new_coupon.code)?;
+    match (new_coupon.percent_off, new_coupon.amount_off) {
+        (Some(percent_off), None) if (1..=100).contains(&percent_off) => {}
+        (None, Some(amount_off)) if amount_off > 0 => {}
+        _ => {
+            return Err(EzyTutorError::InvalidInput(
+                "Give either percent_off from 1 to 100 or a positive amount_off".to_string(),
+            ))
+        }
+    }
+    if new_coupon.max_redemptions.is_some_and(|max| max < 1) {
+        return Err(EzyTutorError::InvalidInput(
+            "max_redemptions must be positive".to_string(),
+        ));
+    }
+    if new_coupon
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return Err(EzyTutorError::InvalidInput(
+            "A coupon must expire in the future".to_string(),
+        ));
+    }
+    let mut tx = pg_pool.begin().await?;
+    sqlx::query_scalar!(
+        "SELECT tutor_id FROM ezy_tutor_c6 WHERE tutor_id = $1",
+        tutor_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".to_string()))?;
+    if let Some(course_id) = new_coupon.course_id {
+        ensure_course(&mut tx, tutor_id, course_id)
+            .await
+            .map_err(|_| {
+                EzyTutorError::InvalidInput(format!(
+                    "Course {} is not one of the tutor's courses",
+                    course_id
+                ))
+            })?;
+    }
+
+    let coupon = sqlx::query_as!(
+        Coupon,
+        "INSERT INTO ezy_coupon (
+            code, tutor_id, course_id, percent_off, amount_off, max_redemptions, expires_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING *",
+        code,
+        tutor_id,
+        new_coupon.course_id,
+        new_coupon.percent_off,
+        new_coupon.amount_off,
+        new_coupon.max_redemptions,
+        new_coupon.expires_at,
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            EzyTutorError::Conflict(format!("Coupon code {} is taken", code))
+        }
+        err => err.into(),
+    })?;
+    tx.commit().await?;
+
+    Ok(coupon)
+}
+
+/// Deletes the coupon, taking it out of carts; orders placed with it keep their discount.
+pub async fn delete_coupon(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    coupon_id: i32,
+) -> Result<String, EzyTutorError> {
+    let res = sqlx::query!(
+        "DELETE FROM ezy_coupon WHERE tutor_id = $1 and coupon_id = $2",
+        tutor_id,
+        coupon_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(EzyTutorError::NotFound("Coupon id not found".to_string()));
+    }
+
+    Ok(format!("Deleted {:?} record", res))
+}
+
+/// Applies the coupon to the caller's cart in place of any other. It is redeemed, taking
+/// its discount off, only at checkout.
+pub async fn apply_coupon_to_cart(pg_pool: &PgPool, code: &str) -> Result<(), EzyTutorError> {
+    let coupon = sqlx::query_as!(
+        Coupon,
+        "SELECT * FROM ezy_coupon WHERE code = $1",
+        code.trim().to_uppercase(),
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Coupon not found".to_string()))?;
+    check_redeemable(&coupon, Utc::now())?;
+    sqlx::query!(
+        "INSERT INTO ezy_cart_coupon (student, coupon_id)
+        VALUES ($1, $2)
+        ON CONFLICT (student) DO UPDATE SET coupon_id = excluded.coupon_id",
+        current_student()?,
+        coupon.coupon_id,
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn remove_coupon_from_cart(pg_pool: &PgPool) -> Result<(), EzyTutorError> {
+    let res = sqlx::query!(
+        "DELETE FROM ezy_cart_coupon WHERE student = $1",
+        current_student()?,
+    )
+    .execute(pg_pool)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(EzyTutorError::NotFound(
+            "No coupon is applied to the cart".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// The coupon applied to the student's cart, whether or not it can still be redeemed.
+pub async fn cart_coupon(
+    conn: &mut PgConnection,
+    student: &str,
+) -> Result<Option<Coupon>, EzyTutorError> {
+    let coupon = sqlx::query_as!(
+        Coupon,
+        "SELECT c.* FROM ezy_coupon c
+        JOIN ezy_cart_coupon cc USING (coupon_id)
+        WHERE cc.student = $1",
+        student,
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(coupon)
+}
+
+/// Counts an order placed with the coupon, failing with `Conflict` when it expired or was
+/// used up since it was applied to the cart.
+pub async fn redeem_coupon(conn: &mut PgConnection, coupon: &Coupon) -> Result<(), EzyTutorError> {
+    let res = sqlx::query!(
+        "UPDATE ezy_coupon SET redemptions = redemptions + 1
+        WHERE coupon_id = $1
+            and (expires_at IS NULL or expires_at > now())
+            and (max_redemptions IS NULL or redemptions < max_redemptions)",
+        coupon.coupon_id,
+    )
+    .execute(conn)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(EzyTutorError::Conflict(format!(
+            "Coupon {} can no longer be redeemed",
+            coupon.code
+        )));
+    }
+
+    Ok(())
+}
+
+/// Uppercases the code, which must be 3 to 40 letters, digits, hyphens or underscores.
+fn coupon_code(code: &str) -> Result<String, EzyTutorError> {
+    let code = code.trim().to_uppercase();
+    if !(3..=MAX_COUPON_CODE_LEN).contains(&code.len())
+        || !code
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "Coupon codes are 3 to {} letters, digits, hyphens or underscores",
+            MAX_COUPON_CODE_LEN
+        )));
+    }
+
+    Ok(code)
+}
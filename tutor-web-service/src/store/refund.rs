@@ -0,0 +1,510 @@
+use crate::errors::EzyTutorError;
+use crate::middleware::current_student;
+use crate::models::{
+    CancellationPolicy, NewRefund, Order, PutCancellationPolicy, RefundDecision, RefundDetails,
+    RefundListQuery,
+};
+use crate::payments::PaymentProvider;
+use crate::pricing::refund_amount;
+use crate::store::record_refund;
+
+use chrono::Utc;
+use sqlx::postgres::{PgConnection, PgPool};
+
+use std::collections::{HashMap, HashSet};
+
+/// The policy of tutors who have not set one: a full refund within two weeks of paying.
+const DEFAULT_FULL_REFUND_DAYS: i32 = 14;
+const DEFAULT_PARTIAL_REFUND_PERCENT: i32 = 0;
+const MAX_FULL_REFUND_DAYS: i32 = 365;
+const MAX_REFUND_TEXT_LEN: usize = 1000;
+const REFUND_STATUSES: [&str; 4] = ["requested", "refunding", "rejected", "refunded"];
+const DEFAULT_REFUND_LIMIT: i64 = 100;
+const MAX_REFUND_LIMIT: i64 = 1000;
+
+pub async fn get_cancellation_policy(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+) -> Result<CancellationPolicy, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    sqlx::query_scalar!(
+        "SELECT tutor_id FROM ezy_tutor_c6 WHERE tutor_id = $1",
+        tutor_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".to_string()))?;
+    let mut policies = cancellation_policies(&mut conn, &[tutor_id]).await?;
+
+    Ok(policies
+        .remove(&tutor_id)
+        .unwrap_or_else(|| default_policy(tutor_id)))
+}
+
+/// Sets the policy refunds requested from now on follow.
+pub async fn put_cancellation_policy(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    policy: PutCancellationPolicy,
+) -> Result<CancellationPolicy, EzyTutorError> {
+    if !(0..=MAX_FULL_REFUND_DAYS).contains(&policy.full_refund_days) {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "full_refund_days must be from 0 to {}",
+            MAX_FULL_REFUND_DAYS
+        )));
+    }
+    if !(0..=100).contains(&policy.partial_refund_percent) {
+        return Err(EzyTutorError::InvalidInput(
+            "partial_refund_percent must be from 0 to 100".to_string(),
+        ));
+    }
+    sqlx::query_as!(
+        CancellationPolicy,
+        r#"INSERT INTO ezy_cancellation_policy (
+            tutor_id, full_refund_days, partial_refund_percent
+        )
+        VALUES ($1, $2, $3)
+        ON CONFLICT (tutor_id) DO UPDATE SET
+            full_refund_days = excluded.full_refund_days,
+            partial_refund_percent = excluded.partial_refund_percent,
+            updated_at = now()
+        RETURNING tutor_id, full_refund_days, partial_refund_percent,
+            updated_at AS "updated_at?""#,
+        tutor_id,
+        policy.full_refund_days,
+        policy.partial_refund_percent,
+    )
+    .fetch_one(pg_pool)
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::Database(db_err) if db_err.is_foreign_key_violation() => {
+            EzyTutorError::NotFound("Tutor id not found".to_string())
+        }
+        err => err.into(),
+    })
+}
+
+/// Asks for a refund of items of one of the caller's paid orders, as much as their
+/// tutors' cancellation policies grant right now. It is made once an operator approves it.
+pub async fn request_refund(
+    pg_pool: &PgPool,
+    order_id: i32,
+    new_refund: NewRefund,
+) -> Result<RefundDetails, EzyTutorError> {
+    if new_refund
+        .reason
+        .as_ref()
+        .is_some_and(|reason| reason.chars().count() > MAX_REFUND_TEXT_LEN)
+    {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "reason may be at most {} characters",
+            MAX_REFUND_TEXT_LEN
+        )));
+    }
+    let mut tx = pg_pool.begin().await?;
+    // Serializes refund requests for the order, so no item is claimed twice.
+    let order = sqlx::query_as!(
+        Order,
+        "SELECT * FROM ezy_order WHERE order_id = $1 and student = $2 FOR UPDATE",
+        order_id,
+        current_student()?,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Order id not found".to_string()))?;
+    let paid_at = match (order.order_status.as_str(), order.paid_at) {
+        ("paid", Some(paid_at)) => paid_at,
+        _ => {
+            return Err(EzyTutorError::Conflict(
+                "Only paid orders can be refunded".to_string(),
+            ))
+        }
+    };
+
+    // The first session of an item is its courses' earliest, unless one of them is
+    // self-paced or was deleted.
+    let lines = sqlx::query!(
+        r#"SELECT i.line_no, i.tutor_id, i.final_price,
+            (SELECT CASE WHEN count(c.course_id) = cardinality(i.course_ids)
+                and bool_and(c.first_session_at IS NOT NULL)
+                THEN min(c.first_session_at) END
+            FROM ezy_course_c6 c
+            WHERE c.course_id = ANY(i.course_ids)) AS first_session_at,
+            EXISTS (
+                SELECT 1 FROM ezy_refund_item ri
+                JOIN ezy_refund r USING (refund_id)
+                WHERE ri.order_id = i.order_id and ri.line_no = i.line_no
+                    and r.refund_status <> 'rejected'
+            ) AS "claimed!"
+        FROM ezy_order_item i
+        WHERE i.order_id = $1
+        ORDER BY i.line_no"#,
+        order_id,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let lines = match &new_refund.line_nos {
+        Some(line_nos) => {
+            let mut seen = HashSet::new();
+            let mut chosen = Vec::new();
+            for line_no in line_nos {
+                if !seen.insert(line_no) {
+                    return Err(EzyTutorError::InvalidInput(format!(
+                        "Line {} is listed more than once",
+                        line_no
+                    )));
+                }
+                let line = lines
+                    .iter()
+                    .find(|line| line.line_no == *line_no)
+                    .ok_or_else(|| {
+                        EzyTutorError::InvalidInput(format!("Order has no line {}", line_no))
+                    })?;
+                if line.claimed {
+                    return Err(EzyTutorError::Conflict(format!(
+                        "Line {} is already refunded or awaiting a refund",
+                        line_no
+                    )));
+                }
+                chosen.push(line);
+            }
+            chosen
+        }
+        None => lines.iter().filter(|line| !line.claimed).collect(),
+    };
+    if lines.is_empty() {
+        return Err(EzyTutorError::Conflict(
+            "Everything in the order is already refunded or awaiting a refund".to_string(),
+        ));
+    }
+
+    let tutor_ids: Vec<i32> = lines.iter().map(|line| line.tutor_id).collect();
+    let policies = cancellation_policies(&mut tx, &tutor_ids).await?;
+    let now = Utc::now();
+    let (line_nos, amounts): (Vec<i32>, Vec<i32>) = lines
+        .iter()
+        .map(|line| {
+            let policy = policies
+                .get(&line.tutor_id)
+                .cloned()
+                .unwrap_or_else(|| default_policy(line.tutor_id));
+            let amount = refund_amount(
+                &policy,
+                line.final_price,
+                paid_at,
+                line.first_session_at,
+                now,
+            );
+            (line.line_no, amount)
+        })
+        .unzip();
+    let amount: i32 = amounts.iter().sum();
+    if amount == 0 {
+        return Err(EzyTutorError::Conflict(
+            "The cancellation policy grants no refund for these items".to_string(),
+        ));
+    }
+
+    let refund_id = sqlx::query_scalar!(
+        "INSERT INTO ezy_refund (order_id, reason, amount)
+        VALUES ($1, $2, $3)
+        RETURNING refund_id",
+        order_id,
+        new_refund.reason,
+        amount,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO ezy_refund_item (refund_id, order_id, line_no, amount)
+        SELECT $1, $2, unnest($3::int[]), unnest($4::int[])",
+        refund_id,
+        order_id,
+        &line_nos,
+        &amounts,
+    )
+    .execute(&mut *tx)
+    .await?;
+    let refund = load_refund(&mut tx, refund_id).await?;
+    tx.commit().await?;
+
+    Ok(refund)
+}
+
+/// Refunds of the caller's order, most recent first.
+pub async fn get_order_refunds(
+    pg_pool: &PgPool,
+    order_id: i32,
+) -> Result<Vec<RefundDetails>, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    sqlx::query_scalar!(
+        "SELECT order_id FROM ezy_order WHERE order_id = $1 and student = $2",
+        order_id,
+        current_student()?,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Order id not found".to_string()))?;
+    load_refunds(&mut conn, None, Some(order_id), None, None, None).await
+}
+
+pub async fn get_refunds(
+    pg_pool: &PgPool,
+    query: RefundListQuery,
+) -> Result<Vec<RefundDetails>, EzyTutorError> {
+    let limit = query.limit.unwrap_or(DEFAULT_REFUND_LIMIT);
+    if !(1..=MAX_REFUND_LIMIT).contains(&limit) {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "limit must be between 1 and {}",
+            MAX_REFUND_LIMIT
+        )));
+    }
+    if let Some(status) = &query.status {
+        if !REFUND_STATUSES.contains(&status.as_str()) {
+            return Err(EzyTutorError::InvalidInput(format!(
+                "status must be one of {}",
+                REFUND_STATUSES.join(", ")
+            )));
+        }
+    }
+    let mut conn = pg_pool.acquire().await?;
+    load_refunds(
+        &mut conn,
+        None,
+        None,
+        query.status.as_deref(),
+        query.before_id,
+        Some(limit),
+    )
+    .await
+}
+
+/// Makes the requested refund through the payment provider, charging it to the items'
+/// tutors, and revokes the enrollments bought with the refunded items, keeping those
+/// another item of the order also paid for.
+///
+/// The refund is committed as `refunding` before the provider is called with the refund id
+/// as its idempotency key, and settled afterwards. When the provider fails, it stays
+/// `refunding` and approving it again resumes where it stopped.
+pub async fn approve_refund(
+    pg_pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    refund_id: i32,
+    decision: RefundDecision,
+) -> Result<RefundDetails, EzyTutorError> {
+    decision_note(&decision)?;
+    let mut tx = pg_pool.begin().await?;
+    let refund = sqlx::query!(
+        "SELECT r.refund_status, r.amount, o.payment_id
+        FROM ezy_refund r
+        JOIN ezy_order o USING (order_id)
+        WHERE r.refund_id = $1
+        FOR UPDATE",
+        refund_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Refund id not found".to_string()))?;
+    if !matches!(refund.refund_status.as_str(), "requested" | "refunding") {
+        return Err(EzyTutorError::Conflict(format!(
+            "Refund is already {}",
+            refund.refund_status
+        )));
+    }
+    sqlx::query!(
+        "UPDATE ezy_refund SET refund_status = 'refunding', decision_note = $2
+        WHERE refund_id = $1",
+        refund_id,
+        decision.note,
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let provider_refund_id = match &refund.payment_id {
+        Some(payment_id) if refund.amount > 0 => Some(
+            provider
+                .refund_payment(refund_id, payment_id, refund.amount)
+                .await?,
+        ),
+        _ => None,
+    };
+
+    let mut tx = pg_pool.begin().await?;
+    // A concurrent approval may have settled the refund while the provider was called.
+    let settled = sqlx::query!(
+        "UPDATE ezy_refund SET
+            refund_status = 'refunded',
+            provider_refund_id = $2,
+            decided_at = now()
+        WHERE refund_id = $1 and refund_status = 'refunding'
+        RETURNING order_id, amount",
+        refund_id,
+        provider_refund_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(settled) = settled {
+        sqlx::query!(
+            "UPDATE ezy_order SET refunded_amount = refunded_amount + $2 WHERE order_id = $1",
+            settled.order_id,
+            settled.amount,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM ezy_enrollment e
+            USING ezy_refund_item ri
+            JOIN ezy_order_item i USING (order_id, line_no)
+            WHERE ri.refund_id = $1
+                and e.order_id = i.order_id and e.course_id = ANY(i.course_ids)
+                and NOT EXISTS (
+                    SELECT 1 FROM ezy_order_item kept
+                    WHERE kept.order_id = i.order_id and e.course_id = ANY(kept.course_ids)
+                        and NOT EXISTS (
+                            SELECT 1 FROM ezy_refund_item kri
+                            JOIN ezy_refund kr USING (refund_id)
+                            WHERE kri.order_id = kept.order_id and kri.line_no = kept.line_no
+                                and kr.refund_status = 'refunded'
+                        )
+                )",
+            refund_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        record_refund(&mut tx, refund_id).await?;
+    }
+    let refund = load_refund(&mut tx, refund_id).await?;
+    tx.commit().await?;
+
+    Ok(refund)
+}
+
+/// Turns down the requested refund, freeing its items to be asked for again.
+pub async fn reject_refund(
+    pg_pool: &PgPool,
+    refund_id: i32,
+    decision: RefundDecision,
+) -> Result<RefundDetails, EzyTutorError> {
+    decision_note(&decision)?;
+    let mut tx = pg_pool.begin().await?;
+    let status = sqlx::query_scalar!(
+        "SELECT refund_status FROM ezy_refund WHERE refund_id = $1 FOR UPDATE",
+        refund_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Refund id not found".to_string()))?;
+    if status != "requested" {
+        return Err(EzyTutorError::Conflict(format!(
+            "Refund is already {}",
+            status
+        )));
+    }
+    sqlx::query!(
+        "UPDATE ezy_refund SET refund_status = 'rejected', decided_at = now(), decision_note = $2
+        WHERE refund_id = $1",
+        refund_id,
+        decision.note,
+    )
+    .execute(&mut *tx)
+    .await?;
+    let refund = load_refund(&mut tx, refund_id).await?;
+    tx.commit().await?;
+
+    Ok(refund)
+}
+
+fn default_policy(tutor_id: i32) -> CancellationPolicy {
+    CancellationPolicy {
+        tutor_id,
+        full_refund_days: DEFAULT_FULL_REFUND_DAYS,
+        partial_refund_percent: DEFAULT_PARTIAL_REFUND_PERCENT,
+        updated_at: None,
+    }
+}
+
+/// The policies the tutors set; those who have not set one are left out.
+async fn cancellation_policies(
+    conn: &mut PgConnection,
+    tutor_ids: &[i32],
+) -> Result<HashMap<i32, CancellationPolicy>, EzyTutorError> {
+    let policies = sqlx::query_as!(
+        CancellationPolicy,
+        r#"SELECT tutor_id, full_refund_days, partial_refund_percent,
+            updated_at AS "updated_at?"
+        FROM ezy_cancellation_policy
+        WHERE tutor_id = ANY($1)"#,
+        tutor_ids,
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(policies
+        .into_iter()
+        .map(|policy| (policy.tutor_id, policy))
+        .collect())
+}
+
+fn decision_note(decision: &RefundDecision) -> Result<(), EzyTutorError> {
+    if decision
+        .note
+        .as_ref()
+        .is_some_and(|note| note.chars().count() > MAX_REFUND_TEXT_LEN)
+    {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "note may be at most {} characters",
+            MAX_REFUND_TEXT_LEN
+        )));
+    }
+
+    Ok(())
+}
+
+async fn load_refund(
+    conn: &mut PgConnection,
+    refund_id: i32,
+) -> Result<RefundDetails, EzyTutorError> {
+    load_refunds(conn, Some(refund_id), None, None, None, None)
+        .await?
+        .pop()
+        .ok_or_else(|| EzyTutorError::NotFound("Refund id not found".to_string()))
+}
+
+/// The refunds matching the filters, most recent first and at most `limit` of them.
+async fn load_refunds(
+    conn: &mut PgConnection,
+    refund_id: Option<i32>,
+    order_id: Option<i32>,
+    status: Option<&str>,
+    before_id: Option<i32>,
+    limit: Option<i64>,
+) -> Result<Vec<RefundDetails>, EzyTutorError> {
+    let refunds = sqlx::query_scalar!(
+        r#"SELECT coalesce(jsonb_agg(
+            to_jsonb(r) || jsonb_build_object('items', (
+                SELECT jsonb_agg(jsonb_build_object('line_no', ri.line_no, 'amount', ri.amount)
+                    ORDER BY ri.line_no)
+                FROM ezy_refund_item ri
+                WHERE ri.refund_id = r.refund_id))
+            ORDER BY r.refund_id DESC), '[]') AS "refunds!"
+        FROM (
+            SELECT * FROM ezy_refund
+            WHERE ($1::int IS NULL or refund_id = $1)
+                and ($2::int IS NULL or order_id = $2)
+                and ($3::varchar IS NULL or refund_status = $3)
+                and ($4::int IS NULL or refund_id < $4)
+            ORDER BY refund_id DESC
+            LIMIT $5
+        ) r"#,
+        refund_id,
+        order_id,
+        status,
+        before_id,
+        limit,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(serde_json::from_value(refunds).map_err(actix_web::Error::from)?)
+}
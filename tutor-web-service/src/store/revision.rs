@@ -0,0 +1,233 @@
+use crate::errors::EzyTutorError;
+use crate::middleware::{current_actor, SYSTEM_ACTOR};
+use crate::models::{ChangeAction, ChangeEvent, Course, CourseRevision, CourseStatus, FieldChange};
+use crate::store::record_change;
+
+use sqlx::postgres::{PgConnection, PgPool};
+
+/// Stores `course` as its next revision. Courses that predate revision history get their
+/// `before` content recorded first, as revision 1 by the system.
+pub async fn record_course_revision(
+    conn: &mut PgConnection,
+    before: Option<&Course>,
+    course: &Course,
+    restored_from: Option<i32>,
+) -> Result<i32, EzyTutorError> {
+    let latest = sqlx::query_scalar!(
+        "SELECT max(revision) FROM ezy_course_revision WHERE course_id = $1",
+        course.course_id,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let mut revision = latest.unwrap_or(0) + 1;
+    if let (None, Some(before)) = (latest, before) {
+        insert_revision(conn, before, revision, SYSTEM_ACTOR, None).await?;
+        revision += 1;
+    }
+    insert_revision(conn, course, revision, &current_actor(), restored_from).await?;
+    Ok(revision)
+}
+
+async fn insert_revision(
+    conn: &mut PgConnection,
+    course: &Course,
+    revision: i32,
+    actor: &str,
+    restored_from: Option<i32>,
+) -> Result<(), EzyTutorError> {
+    let content = serde_json::to_value(course).map_err(actix_web::Error::from)?;
+    sqlx::query!(
+        "INSERT INTO ezy_course_revision (course_id, revision, content, actor, restored_from)
+        VALUES ($1, $2, $3, $4, $5)",
+        course.course_id,
+        revision,
+        content,
+        actor,
+        restored_from,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Returns the course's revisions oldest first, each with the fields it changed. With a
+/// `status`, courses in any other status are not found.
+pub async fn get_course_revisions(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    status: Option<CourseStatus>,
+) -> Result<Vec<CourseRevision>, EzyTutorError> {
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(
+            SELECT 1 FROM ezy_course_c6
+            WHERE tutor_id = $1 and course_id = $2
+                and ($3::varchar IS NULL or course_status = $3)
+        ) AS "exists!""#,
+        tutor_id,
+        course_id,
+        status.map(|status| status.as_str()),
+    )
+    .fetch_one(pg_pool)
+    .await?;
+    if !exists {
+        return Err(EzyTutorError::NotFound("Course id not found".to_string()));
+    }
+
+    let rows = sqlx::query!(
+        "SELECT revision, actor, restored_from, created_at, content
+        FROM ezy_course_revision
+        WHERE course_id = $1
+        ORDER BY revision",
+        course_id,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    let mut previous = serde_json::Value::Null;
+    let revisions = rows
+        .into_iter()
+        .map(|rec| {
+            let changes = diff_fields(&previous, &rec.content);
+            previous = rec.content.clone();
+            CourseRevision {
+                revision: rec.revision,
+                actor: rec.actor,
+                restored_from: rec.restored_from,
+                created_at: rec.created_at,
+                course: rec.content,
+                changes,
+            }
+        })
+        .collect();
+
+    Ok(revisions)
+}
+
+/// Puts the course's content back to what it was at `revision`, recording that as a new
+/// revision rather than discarding the ones in between.
+pub async fn restore_course_revision(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    revision: i32,
+) -> Result<Course, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    let before = sqlx::query_as!(
+        Course,
+        "SELECT * FROM ezy_course_c6 WHERE tutor_id = $1 and course_id = $2 FOR UPDATE",
+        tutor_id,
+        course_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Course id not found".to_string()))?;
+
+    let content = sqlx::query_scalar!(
+        "SELECT content FROM ezy_course_revision WHERE course_id = $1 and revision = $2",
+        course_id,
+        revision,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Course revision not found".to_string()))?;
+    let target: Course = serde_json::from_value(content).map_err(actix_web::Error::from)?;
+
+    let restored = sqlx::query_as!(
+        Course,
+        "UPDATE ezy_course_c6 SET
+            course_name = $1,
+            course_description = $2,
+            course_format = $3,
+            course_structure = $4,
+            course_duration = $5,
+            course_price = $6,
+            course_language = $7,
+            course_level = $8
+        WHERE tutor_id = $9 and course_id = $10
+        RETURNING
+            tutor_id, course_id,
+            course_name, course_description,
+            course_duration, course_level,
+            course_format, course_language,
+            course_structure, course_price, posted_time,
+            course_status, publish_at, first_session_at",
+        target.course_name,
+        target.course_description,
+        target.course_format,
+        target.course_structure,
+        target.course_duration,
+        target.course_price,
+        target.course_language,
+        target.course_level,
+        tutor_id,
+        course_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_course_revision(&mut tx, Some(&before), &restored, Some(revision)).await?;
+    let event = ChangeEvent::course(ChangeAction::Updated, tutor_id, course_id);
+    record_change(&mut tx, &event, Some(&before), Some(&restored)).await?;
+    tx.commit().await?;
+
+    Ok(restored)
+}
+
+/// Lists the top-level fields whose values differ, in field name order. Anything that is
+/// not an object, such as the `Null` before a first revision, counts as having no fields.
+fn diff_fields(before: &serde_json::Value, after: &serde_json::Value) -> Vec<FieldChange> {
+    let empty = serde_json::Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+    fields
+        .into_iter()
+        .filter_map(|field| {
+            let from = before.get(field).cloned().unwrap_or_default();
+            let to = after.get(field).cloned().unwrap_or_default();
+            (from != to).then(|| FieldChange {
+                field: field.clone(),
+                from,
+                to,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn diffs_changed_fields_only() {
+        let before = json!({"course_name": "A", "course_price": 10, "course_level": null});
+        let after = json!({"course_name": "A", "course_price": 20, "course_level": "Beginner"});
+        assert_eq!(
+            vec![
+                FieldChange {
+                    field: "course_level".to_string(),
+                    from: json!(null),
+                    to: json!("Beginner"),
+                },
+                FieldChange {
+                    field: "course_price".to_string(),
+                    from: json!(10),
+                    to: json!(20),
+                },
+            ],
+            diff_fields(&before, &after)
+        );
+
+        let created = diff_fields(&serde_json::Value::Null, &after);
+        assert_eq!(
+            vec!["course_level", "course_name", "course_price"],
+            created.iter().map(|c| c.field.as_str()).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use crate::errors::EzyTutorError;
+use crate::models::{ChangeAction, ChangeEvent, CourseTranslation, PutCourseTranslation};
+use crate::store::record_change;
+
+use sqlx::postgres::{PgConnection, PgPool};
+
+pub async fn get_course_translations(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+) -> Result<Vec<CourseTranslation>, EzyTutorError> {
+    let mut conn = pg_pool.acquire().await?;
+    ensure_course(&mut conn, tutor_id, course_id).await?;
+    let translations = sqlx::query_as!(
+        CourseTranslation,
+        "SELECT * FROM ezy_course_translation WHERE course_id = $1 ORDER BY locale",
+        course_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(translations)
+}
+
+/// Translations of any of `course_ids` stored under one of `locales`.
+pub async fn get_translations_for_courses(
+    pg_pool: &PgPool,
+    course_ids: &[i32],
+    locales: &[String],
+) -> Result<Vec<CourseTranslation>, EzyTutorError> {
+    if course_ids.is_empty() || locales.is_empty() {
+        return Ok(Vec::new());
+    }
+    let translations = sqlx::query_as!(
+        CourseTranslation,
+        "SELECT * FROM ezy_course_translation
+        WHERE course_id = ANY($1) and locale = ANY($2)",
+        course_ids,
+        locales,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(translations)
+}
+
+/// Adds or replaces the course's translation into the normalized `locale`. The audit log
+/// and webhook payload of the resulting `course.updated` event carry the translation.
+pub async fn put_course_translation(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    locale: &str,
+    translation: PutCourseTranslation,
+) -> Result<CourseTranslation, EzyTutorError> {
+    if translation.course_name.trim().is_empty() {
+        return Err(EzyTutorError::InvalidInput(
+            "course_name must not be empty".to_string(),
+        ));
+    }
+    let mut tx = pg_pool.begin().await?;
+    ensure_course(&mut tx, tutor_id, course_id).await?;
+    let before = sqlx::query_as!(
+        CourseTranslation,
+        "SELECT * FROM ezy_course_translation WHERE course_id = $1 and locale = $2",
+        course_id,
+        locale,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let after = sqlx::query_as!(
+        CourseTranslation,
+        "INSERT INTO ezy_course_translation (course_id, locale, course_name, course_description)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (course_id, locale) DO UPDATE SET
+            course_name = excluded.course_name,
+            course_description = excluded.course_description,
+            updated_at = now()
+        RETURNING *",
+        course_id,
+        locale,
+        translation.course_name,
+        translation.course_description,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let event = ChangeEvent::course(ChangeAction::Updated, tutor_id, course_id);
+    record_change(&mut tx, &event, before.as_ref(), Some(&after)).await?;
+    tx.commit().await?;
+
+    Ok(after)
+}
+
+pub async fn delete_course_translation(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    course_id: i32,
+    locale: &str,
+) -> Result<String, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    ensure_course(&mut tx, tutor_id, course_id).await?;
+    let before = sqlx::query_as!(
+        CourseTranslation,
+        "DELETE FROM ezy_course_translation WHERE course_id = $1 and locale = $2
+        RETURNING *",
+        course_id,
+        locale,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Course translation not found".to_string()))?;
+
+    let event = ChangeEvent::course(ChangeAction::Updated, tutor_id, course_id);
+    record_change(&mut tx, &event, Some(&before), None).await?;
+    tx.commit().await?;
+
+    Ok(format!("Deleted {} translation", before.locale))
+}
+
+/// Fails with `NotFound` unless the tutor has the course.
+pub async fn ensure_course(
+    conn: &mut PgConnection,
+    tutor_id: i32,
+    course_id: i32,
+) -> Result<(), EzyTutorError> {
+    sqlx::query_scalar!(
+        "SELECT course_id FROM ezy_course_c6 WHERE tutor_id = $1 and course_id = $2",
+        tutor_id,
+        course_id,
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(|_| ())
+    .ok_or_else(|| EzyTutorError::NotFound("Course id not found".to_string()))
+}
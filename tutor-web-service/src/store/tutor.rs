@@ -1,7 +1,12 @@
 use crate::errors::EzyTutorError;
-use crate::models::{NewTutor, Tutor, UpdateTutor};
+use crate::middleware::current_student;
+use crate::models::{
+    ChangeAction, ChangeEvent, Course, NewTutor, RateTutor, Tutor, TutorDirectory,
+    TutorDirectoryQuery, TutorRating, UpdateTutor,
+};
+use crate::store::{normalize_tag, record_change};
 
-use sqlx::postgres::PgPool;
+use sqlx::postgres::{PgConnection, PgPool};
 
 pub async fn get_all_tutors(pg_pool: &PgPool) -> Result<Vec<Tutor>, EzyTutorError> {
     let tutors = sqlx::query!(
@@ -38,12 +43,183 @@ pub async fn get_tutor_details(pg_pool: &PgPool, tutor_id: i32) -> Result<Tutor,
     .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".to_string()))
 }
 
+/// Searches and pages through tutors. Every word of `q` must appear in the tutor's name
+/// or profile; the other filters look at their published courses and ratings.
+pub async fn get_tutor_directory(
+    pg_pool: &PgPool,
+    query: TutorDirectoryQuery,
+) -> Result<TutorDirectory, EzyTutorError> {
+    let limit = query.limit.unwrap_or(20);
+    let offset = query.offset.unwrap_or(0);
+    if !(1..=100).contains(&limit) || offset < 0 {
+        return Err(EzyTutorError::InvalidInput(
+            "limit must be between 1 and 100 and offset must not be negative".to_string(),
+        ));
+    }
+    if query
+        .min_rating
+        .is_some_and(|rating| !(1.0..=5.0).contains(&rating))
+    {
+        return Err(EzyTutorError::InvalidInput(
+            "min_rating must be between 1 and 5".to_string(),
+        ));
+    }
+    let words: Vec<String> = query
+        .q
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .take(10)
+        .map(escape_like)
+        .collect();
+    let tag = query.tag.as_deref().map(normalize_tag).transpose()?;
+
+    let result = sqlx::query!(
+        r#"WITH RECURSIVE scope AS (
+            SELECT category_id FROM ezy_category WHERE category_id = $2
+            UNION ALL
+            SELECT c.category_id FROM ezy_category c JOIN scope s ON c.parent_id = s.category_id
+        ),
+        listing AS MATERIALIZED (
+            SELECT t.tutor_id, t.tutor_name, t.tutor_pic_url, t.tutor_profile,
+                (SELECT count(*) FROM ezy_course_c6 c
+                    WHERE c.tutor_id = t.tutor_id and c.course_status = 'published') AS course_count,
+                (SELECT coalesce(array_agg(DISTINCT cc.category_id), '{}')
+                    FROM ezy_course_c6 c JOIN ezy_course_category cc USING (course_id)
+                    WHERE c.tutor_id = t.tutor_id and c.course_status = 'published') AS category_ids,
+                (SELECT coalesce(array_agg(DISTINCT c.course_language), '{}')
+                    FROM ezy_course_c6 c
+                    WHERE c.tutor_id = t.tutor_id and c.course_status = 'published'
+                        and c.course_language <> '') AS languages,
+                (SELECT avg(r.rating)::float8 FROM ezy_tutor_rating r
+                    WHERE r.tutor_id = t.tutor_id) AS average_rating,
+                (SELECT count(*) FROM ezy_tutor_rating r
+                    WHERE r.tutor_id = t.tutor_id) AS rating_count
+            FROM ezy_tutor_c6 t
+            WHERE NOT EXISTS (
+                    SELECT 1 FROM unnest($1::text[]) w
+                    WHERE t.tutor_name NOT ILIKE '%' || w || '%'
+                        and t.tutor_profile NOT ILIKE '%' || w || '%')
+                and ($2::int IS NULL or EXISTS (
+                    SELECT 1 FROM ezy_course_c6 c
+                    JOIN ezy_course_category cc USING (course_id)
+                    JOIN scope USING (category_id)
+                    WHERE c.tutor_id = t.tutor_id and c.course_status = 'published'))
+                and ($3::varchar IS NULL or EXISTS (
+                    SELECT 1 FROM ezy_course_c6 c
+                    JOIN ezy_course_tag ct USING (course_id)
+                    JOIN ezy_tag tg USING (tag_id)
+                    WHERE c.tutor_id = t.tutor_id and c.course_status = 'published'
+                        and tg.tag_name = $3))
+                and ($4::varchar IS NULL or EXISTS (
+                    SELECT 1 FROM ezy_course_c6 c
+                    WHERE c.tutor_id = t.tutor_id and c.course_status = 'published'
+                        and lower(c.course_language) = lower($4)))
+        ),
+        matching AS MATERIALIZED (
+            SELECT * FROM listing
+            WHERE $5::float8 IS NULL or average_rating >= $5
+        )
+        SELECT
+            (SELECT count(*) FROM matching) AS "total!",
+            (SELECT coalesce(jsonb_agg(p ORDER BY p.position), '[]') FROM (
+                SELECT m.*, row_number() OVER (ORDER BY
+                    CASE WHEN $6 = 'courses' THEN m.course_count END DESC,
+                    CASE WHEN $6 = 'rating' THEN m.average_rating END DESC NULLS LAST,
+                    lower(m.tutor_name), m.tutor_id) AS position
+                FROM matching m
+                ORDER BY position
+                LIMIT $7 OFFSET $8
+            ) p) AS "tutors!""#,
+        &words,
+        query.category_id,
+        tag,
+        query.language,
+        query.min_rating,
+        query.sort.as_str(),
+        limit,
+        offset,
+    )
+    .fetch_one(pg_pool)
+    .await?;
+
+    Ok(TutorDirectory {
+        total: result.total,
+        tutors: serde_json::from_value(result.tutors).map_err(actix_web::Error::from)?,
+    })
+}
+
+/// Records the current student's rating of the tutor, replacing any earlier one. Only
+/// students enrolled in one of the tutor's courses may rate them.
+pub async fn rate_tutor(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    rate: RateTutor,
+) -> Result<TutorRating, EzyTutorError> {
+    if !(1..=5).contains(&rate.rating) {
+        return Err(EzyTutorError::InvalidInput(
+            "rating must be between 1 and 5".to_string(),
+        ));
+    }
+    let student = current_student()?;
+    let mut tx = pg_pool.begin().await?;
+    lock_tutor(&mut tx, tutor_id)
+        .await?
+        .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".to_string()))?;
+    let enrolled = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM ezy_enrollment e
+            JOIN ezy_course_c6 c USING (course_id)
+            WHERE c.tutor_id = $1 and e.student = $2
+        ) AS "enrolled!""#,
+        tutor_id,
+        student,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if !enrolled {
+        return Err(EzyTutorError::Forbidden(
+            "Only students enrolled in one of the tutor's courses may rate them".to_string(),
+        ));
+    }
+    sqlx::query!(
+        "INSERT INTO ezy_tutor_rating (tutor_id, rater, rating)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (tutor_id, rater) DO UPDATE SET rating = excluded.rating, rated_at = now()",
+        tutor_id,
+        student,
+        rate.rating,
+    )
+    .execute(&mut *tx)
+    .await?;
+    let rating = sqlx::query_as!(
+        TutorRating,
+        r#"SELECT $1::int AS "tutor_id!", avg(rating)::float8 AS average_rating,
+            count(*) AS "rating_count!"
+        FROM ezy_tutor_rating WHERE tutor_id = $1"#,
+        tutor_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(rating)
+}
+
+/// Escapes `%`, `_` and `\` so the word matches literally inside an `ILIKE` pattern.
+fn escape_like(word: &str) -> String {
+    word.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
 pub async fn post_new_tutor(pg_pool: &PgPool, new_tutor: NewTutor) -> Result<Tutor, EzyTutorError> {
     let NewTutor {
         tutor_name,
         tutor_pic_url,
         tutor_profile,
     } = new_tutor;
+    let mut tx = pg_pool.begin().await?;
     let tutor = sqlx::query!(
         "INSERT INTO ezy_tutor_c6 (
         tutor_name, tutor_pic_url, tutor_profile)
@@ -60,9 +236,13 @@ pub async fn post_new_tutor(pg_pool: &PgPool, new_tutor: NewTutor) -> Result<Tut
         tutor_pic_url: rec.tutor_pic_url,
         tutor_profile: rec.tutor_profile,
     })
-    .fetch_one(pg_pool)
+    .fetch_one(&mut *tx)
     .await?;
 
+    let event = ChangeEvent::tutor(ChangeAction::Created, tutor.tutor_id);
+    record_change(&mut tx, &event, None, Some(&tutor)).await?;
+    tx.commit().await?;
+
     Ok(tutor)
 }
 
@@ -71,21 +251,11 @@ pub async fn update_tutor_details(
     tutor_id: i32,
     update_tutor: UpdateTutor,
 ) -> Result<Tutor, EzyTutorError> {
-    let current = sqlx::query!(
-        "SELECT tutor_id, tutor_name, tutor_pic_url, tutor_profile
-        FROM ezy_tutor_c6
-        WHERE tutor_id = $1",
-        tutor_id,
-    )
-    .map(|rec| Tutor {
-        tutor_id: rec.tutor_id,
-        tutor_name: rec.tutor_name,
-        tutor_pic_url: rec.tutor_pic_url,
-        tutor_profile: rec.tutor_profile,
-    })
-    .fetch_optional(pg_pool)
-    .await?
-    .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".to_string()))?;
+    let mut tx = pg_pool.begin().await?;
+    let current = lock_tutor(&mut tx, tutor_id)
+        .await?
+        .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".to_string()))?;
+    let before = current.clone();
 
     let name = update_tutor.tutor_name.unwrap_or(current.tutor_name);
     let pic_url = update_tutor.tutor_pic_url.unwrap_or(current.tutor_pic_url);
@@ -111,15 +281,105 @@ pub async fn update_tutor_details(
         tutor_pic_url: rec.tutor_pic_url,
         tutor_profile: rec.tutor_profile,
     })
-    .fetch_one(pg_pool)
+    .fetch_one(&mut *tx)
     .await?;
 
+    let event = ChangeEvent::tutor(ChangeAction::Updated, tutor_id);
+    record_change(&mut tx, &event, Some(&before), Some(&updated_tutor)).await?;
+    tx.commit().await?;
+
     Ok(updated_tutor)
 }
 
+pub async fn update_tutor_pic_url(
+    pg_pool: &PgPool,
+    tutor_id: i32,
+    pic_url: &str,
+) -> Result<Tutor, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    let before = lock_tutor(&mut tx, tutor_id)
+        .await?
+        .ok_or_else(|| EzyTutorError::NotFound("Tutor id not found".to_string()))?;
+    let tutor = sqlx::query!(
+        "UPDATE ezy_tutor_c6 SET tutor_pic_url = $1
+        WHERE tutor_id = $2
+        RETURNING
+        tutor_id, tutor_name, tutor_pic_url, tutor_profile",
+        pic_url,
+        tutor_id,
+    )
+    .map(|rec| Tutor {
+        tutor_id: rec.tutor_id,
+        tutor_name: rec.tutor_name,
+        tutor_pic_url: rec.tutor_pic_url,
+        tutor_profile: rec.tutor_profile,
+    })
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let event = ChangeEvent::tutor(ChangeAction::Updated, tutor_id);
+    record_change(&mut tx, &event, Some(&before), Some(&tutor)).await?;
+    tx.commit().await?;
+
+    Ok(tutor)
+}
+
 pub async fn delete_tutor(pg_pool: &PgPool, tutor_id: i32) -> Result<String, EzyTutorError> {
+    let mut tx = pg_pool.begin().await?;
+    let before = lock_tutor(&mut tx, tutor_id).await?;
+    // Courses go along with their tutor, so their deletion is recorded too.
+    let courses = sqlx::query_as!(
+        Course,
+        "SELECT * FROM ezy_course_c6 WHERE tutor_id = $1 FOR UPDATE",
+        tutor_id,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
     let res = sqlx::query!("DELETE FROM ezy_tutor_c6 WHERE tutor_id = $1", tutor_id,)
-        .execute(pg_pool)
+        .execute(&mut *tx)
         .await?;
+    if res.rows_affected() > 0 {
+        for course in &courses {
+            let event = ChangeEvent::course(ChangeAction::Deleted, tutor_id, course.course_id);
+            record_change(&mut tx, &event, Some(course), None).await?;
+        }
+        let event = ChangeEvent::tutor(ChangeAction::Deleted, tutor_id);
+        record_change(&mut tx, &event, before.as_ref(), None).await?;
+    }
+    tx.commit().await?;
     Ok(format!("Deleted {:?} record", res))
 }
+
+/// Reads the tutor and locks their row until the transaction ends.
+async fn lock_tutor(
+    conn: &mut PgConnection,
+    tutor_id: i32,
+) -> Result<Option<Tutor>, EzyTutorError> {
+    let tutor = sqlx::query!(
+        "SELECT tutor_id, tutor_name, tutor_pic_url, tutor_profile
+        FROM ezy_tutor_c6
+        WHERE tutor_id = $1
+        FOR UPDATE",
+        tutor_id,
+    )
+    .map(|rec| Tutor {
+        tutor_id: rec.tutor_id,
+        tutor_name: rec.tutor_name,
+        tutor_pic_url: rec.tutor_pic_url,
+        tutor_profile: rec.tutor_profile,
+    })
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(tutor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_like_wildcards() {
+        assert_eq!(r"50\%\_off\\", escape_like(r"50%_off\"));
+    }
+}
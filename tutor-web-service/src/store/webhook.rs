@@ -0,0 +1,464 @@
+use crate::errors::EzyTutorError;
+use crate::models::{
+    ChangeEvent, NewWebhookSubscription, UpdateWebhookSubscription, WebhookAttempt,
+    WebhookDelivery, WebhookDeliveryDetail, WebhookDeliveryQuery, WebhookSubscription,
+    WEBHOOK_EVENT_TYPES,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::postgres::{PgConnection, PgPool};
+use url::Url;
+use uuid::Uuid;
+
+use std::time::Duration;
+
+const DEFAULT_DELIVERY_LIMIT: i64 = 100;
+const MAX_DELIVERY_LIMIT: i64 = 1000;
+const DELIVERY_STATUSES: [&str; 3] = ["pending", "succeeded", "failed"];
+const MAX_ERROR_LEN: usize = 2000;
+
+/// A delivery claimed for sending, with everything needed to send it.
+#[derive(Debug, Clone)]
+pub struct DueWebhookDelivery {
+    pub delivery_id: i64,
+    pub attempts: i32,
+    pub event_id: i64,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub url: String,
+    pub secret: String,
+}
+
+/// What happened when a delivery was sent.
+#[derive(Debug, Clone)]
+pub struct WebhookAttemptResult {
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub duration: Duration,
+}
+
+impl WebhookAttemptResult {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+            && self
+                .status_code
+                .is_some_and(|code| (200..300).contains(&code))
+    }
+}
+
+/// Queues `event` for every active subscription asking for it. Run it inside the
+/// transaction making the change: the event is only delivered once that commits.
+pub async fn enqueue_webhook_event<T: Serialize>(
+    conn: &mut PgConnection,
+    event: &ChangeEvent,
+    data: &T,
+) -> Result<(), EzyTutorError> {
+    let event_type = event.event_type();
+    let wildcard = format!("{}.*", event.entity.as_str());
+    let payload = serde_json::to_value(data).map_err(actix_web::Error::from)?;
+
+    let event_id = sqlx::query_scalar!(
+        "INSERT INTO ezy_webhook_outbox (event_type, payload)
+        VALUES ($1, $2)
+        RETURNING event_id",
+        event_type,
+        payload,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO ezy_webhook_delivery (event_id, subscription_id)
+        SELECT $1, subscription_id FROM ezy_webhook_subscription
+        WHERE active AND ($2 = ANY(event_types) OR $3 = ANY(event_types) OR '*' = ANY(event_types))",
+        event_id,
+        event_type,
+        wildcard,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+fn validate_url(url: &str) -> Result<(), EzyTutorError> {
+    match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => Ok(()),
+        _ => Err(EzyTutorError::InvalidInput(format!(
+            "Webhook url must be an http(s) URL, got {:?}",
+            url
+        ))),
+    }
+}
+
+fn validate_event_types(event_types: &[String]) -> Result<(), EzyTutorError> {
+    if event_types.is_empty() {
+        return Err(EzyTutorError::InvalidInput(
+            "At least one event type is required".to_string(),
+        ));
+    }
+    match event_types
+        .iter()
+        .find(|event_type| !WEBHOOK_EVENT_TYPES.contains(&event_type.as_str()))
+    {
+        Some(unknown) => Err(EzyTutorError::InvalidInput(format!(
+            "Unknown event type {:?}, expected one of {}",
+            unknown,
+            WEBHOOK_EVENT_TYPES.join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+pub async fn post_new_webhook_subscription(
+    pg_pool: &PgPool,
+    new_subscription: NewWebhookSubscription,
+) -> Result<WebhookSubscription, EzyTutorError> {
+    let NewWebhookSubscription {
+        url,
+        secret,
+        event_types,
+    } = new_subscription;
+    validate_url(&url)?;
+    validate_event_types(&event_types)?;
+    let secret = match secret {
+        Some(secret) if secret.len() < 16 => {
+            return Err(EzyTutorError::InvalidInput(
+                "Webhook secret must be at least 16 characters long".to_string(),
+            ))
+        }
+        Some(secret) => secret,
+        None => format!(
+            "whsec_{}{}",
+            Uuid::new_v4().simple(),
+            Uuid::new_v4().simple()
+        ),
+    };
+
+    let subscription = sqlx::query!(
+        "INSERT INTO ezy_webhook_subscription (url, secret, event_types)
+        VALUES ($1, $2, $3)
+        RETURNING subscription_id, url, secret, event_types, active, created_at",
+        url,
+        secret,
+        &event_types,
+    )
+    .map(|rec| WebhookSubscription {
+        subscription_id: rec.subscription_id,
+        url: rec.url,
+        event_types: rec.event_types,
+        active: rec.active,
+        created_at: rec.created_at,
+        secret: Some(rec.secret),
+    })
+    .fetch_one(pg_pool)
+    .await?;
+
+    Ok(subscription)
+}
+
+pub async fn get_webhook_subscriptions(
+    pg_pool: &PgPool,
+) -> Result<Vec<WebhookSubscription>, EzyTutorError> {
+    let subscriptions = sqlx::query!(
+        "SELECT subscription_id, url, event_types, active, created_at
+        FROM ezy_webhook_subscription
+        ORDER BY subscription_id"
+    )
+    .map(|rec| WebhookSubscription {
+        subscription_id: rec.subscription_id,
+        url: rec.url,
+        event_types: rec.event_types,
+        active: rec.active,
+        created_at: rec.created_at,
+        secret: None,
+    })
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(subscriptions)
+}
+
+pub async fn update_webhook_subscription(
+    pg_pool: &PgPool,
+    subscription_id: i32,
+    update: UpdateWebhookSubscription,
+) -> Result<WebhookSubscription, EzyTutorError> {
+    if let Some(url) = &update.url {
+        validate_url(url)?;
+    }
+    if let Some(event_types) = &update.event_types {
+        validate_event_types(event_types)?;
+    }
+
+    sqlx::query!(
+        "UPDATE ezy_webhook_subscription SET
+        url = COALESCE($1, url),
+        event_types = COALESCE($2, event_types),
+        active = COALESCE($3, active)
+        WHERE subscription_id = $4
+        RETURNING subscription_id, url, event_types, active, created_at",
+        update.url,
+        update.event_types.as_deref(),
+        update.active,
+        subscription_id,
+    )
+    .map(|rec| WebhookSubscription {
+        subscription_id: rec.subscription_id,
+        url: rec.url,
+        event_types: rec.event_types,
+        active: rec.active,
+        created_at: rec.created_at,
+        secret: None,
+    })
+    .fetch_optional(pg_pool)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Webhook subscription not found".to_string()))
+}
+
+/// Removes the subscription along with its pending deliveries and delivery log.
+pub async fn delete_webhook_subscription(
+    pg_pool: &PgPool,
+    subscription_id: i32,
+) -> Result<String, EzyTutorError> {
+    let res = sqlx::query!(
+        "DELETE FROM ezy_webhook_subscription WHERE subscription_id = $1",
+        subscription_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(EzyTutorError::NotFound(
+            "Webhook subscription not found".to_string(),
+        ));
+    }
+    Ok(format!("Deleted {:?} record", res))
+}
+
+/// Returns the subscription's deliveries newest first; `before_id` continues a page.
+pub async fn get_webhook_deliveries(
+    pg_pool: &PgPool,
+    subscription_id: i32,
+    query: WebhookDeliveryQuery,
+) -> Result<Vec<WebhookDelivery>, EzyTutorError> {
+    let limit = query.limit.unwrap_or(DEFAULT_DELIVERY_LIMIT);
+    if !(1..=MAX_DELIVERY_LIMIT).contains(&limit) {
+        return Err(EzyTutorError::InvalidInput(format!(
+            "limit must be between 1 and {}",
+            MAX_DELIVERY_LIMIT
+        )));
+    }
+    if let Some(status) = &query.status {
+        if !DELIVERY_STATUSES.contains(&status.as_str()) {
+            return Err(EzyTutorError::InvalidInput(format!(
+                "status must be one of {}",
+                DELIVERY_STATUSES.join(", ")
+            )));
+        }
+    }
+
+    let deliveries = sqlx::query_as!(
+        WebhookDelivery,
+        "SELECT d.delivery_id, d.event_id, o.event_type, d.subscription_id,
+        d.status, d.attempts, d.next_attempt_at, d.last_status_code, d.last_error,
+        d.created_at, d.delivered_at
+        FROM ezy_webhook_delivery d
+        JOIN ezy_webhook_outbox o ON o.event_id = d.event_id
+        WHERE d.subscription_id = $1
+        AND ($2::varchar IS NULL OR d.status = $2)
+        AND ($3::bigint IS NULL OR d.delivery_id < $3)
+        ORDER BY d.delivery_id DESC
+        LIMIT $4",
+        subscription_id,
+        query.status,
+        query.before_id,
+        limit,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(deliveries)
+}
+
+pub async fn get_webhook_delivery(
+    pg_pool: &PgPool,
+    delivery_id: i64,
+) -> Result<WebhookDeliveryDetail, EzyTutorError> {
+    let (delivery, payload) = sqlx::query!(
+        "SELECT d.delivery_id, d.event_id, o.event_type, o.payload, d.subscription_id,
+        d.status, d.attempts, d.next_attempt_at, d.last_status_code, d.last_error,
+        d.created_at, d.delivered_at
+        FROM ezy_webhook_delivery d
+        JOIN ezy_webhook_outbox o ON o.event_id = d.event_id
+        WHERE d.delivery_id = $1",
+        delivery_id,
+    )
+    .map(|rec| {
+        let delivery = WebhookDelivery {
+            delivery_id: rec.delivery_id,
+            event_id: rec.event_id,
+            event_type: rec.event_type,
+            subscription_id: rec.subscription_id,
+            status: rec.status,
+            attempts: rec.attempts,
+            next_attempt_at: rec.next_attempt_at,
+            last_status_code: rec.last_status_code,
+            last_error: rec.last_error,
+            created_at: rec.created_at,
+            delivered_at: rec.delivered_at,
+        };
+        (delivery, rec.payload)
+    })
+    .fetch_optional(pg_pool)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Webhook delivery not found".to_string()))?;
+
+    let attempts_log = sqlx::query_as!(
+        WebhookAttempt,
+        "SELECT attempt_id, attempted_at, status_code, error, duration_ms
+        FROM ezy_webhook_attempt
+        WHERE delivery_id = $1
+        ORDER BY attempt_id",
+        delivery_id,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(WebhookDeliveryDetail {
+        delivery,
+        payload,
+        attempts_log,
+    })
+}
+
+/// Queues the delivery to be sent again right away, with a fresh set of attempts.
+pub async fn redeliver_webhook(
+    pg_pool: &PgPool,
+    delivery_id: i64,
+) -> Result<WebhookDelivery, EzyTutorError> {
+    sqlx::query_as!(
+        WebhookDelivery,
+        "WITH redelivered AS (
+            UPDATE ezy_webhook_delivery
+            SET status = 'pending', attempts = 0, next_attempt_at = now()
+            WHERE delivery_id = $1
+            RETURNING *
+        )
+        SELECT d.delivery_id, d.event_id, o.event_type, d.subscription_id,
+        d.status, d.attempts, d.next_attempt_at, d.last_status_code, d.last_error,
+        d.created_at, d.delivered_at
+        FROM redelivered d
+        JOIN ezy_webhook_outbox o ON o.event_id = d.event_id",
+        delivery_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    .ok_or_else(|| EzyTutorError::NotFound("Webhook delivery not found".to_string()))
+}
+
+/// Queues every failed delivery of the subscription again; returns how many there were.
+pub async fn redeliver_failed_webhooks(
+    pg_pool: &PgPool,
+    subscription_id: i32,
+) -> Result<u64, EzyTutorError> {
+    let res = sqlx::query!(
+        "UPDATE ezy_webhook_delivery
+        SET status = 'pending', attempts = 0, next_attempt_at = now()
+        WHERE subscription_id = $1 AND status = 'failed'",
+        subscription_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+/// Claims up to `limit` due deliveries of active subscriptions. Claimed deliveries are
+/// not due again for `lease`, so other dispatchers leave them alone while they are sent.
+pub async fn claim_webhook_deliveries(
+    pg_pool: &PgPool,
+    limit: i64,
+    lease: Duration,
+) -> Result<Vec<DueWebhookDelivery>, EzyTutorError> {
+    let deliveries = sqlx::query_as!(
+        DueWebhookDelivery,
+        r#"WITH due AS (
+            SELECT d.delivery_id FROM ezy_webhook_delivery d
+            JOIN ezy_webhook_subscription s ON s.subscription_id = d.subscription_id
+            WHERE d.status = 'pending' AND d.next_attempt_at <= now() AND s.active
+            ORDER BY d.next_attempt_at
+            LIMIT $1
+            FOR UPDATE OF d SKIP LOCKED
+        )
+        UPDATE ezy_webhook_delivery d
+        SET next_attempt_at = now() + make_interval(secs => $2)
+        FROM due, ezy_webhook_outbox o, ezy_webhook_subscription s
+        WHERE d.delivery_id = due.delivery_id
+        AND o.event_id = d.event_id
+        AND s.subscription_id = d.subscription_id
+        RETURNING d.delivery_id, d.attempts, o.event_id, o.event_type,
+        o.payload, o.created_at, s.url, s.secret"#,
+        limit,
+        lease.as_secs_f64(),
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(deliveries)
+}
+
+/// Logs an attempt and settles the delivery: succeeded, failed for good once
+/// `max_attempts` is reached, or pending again after `retry_in`.
+pub async fn record_webhook_attempt(
+    pg_pool: &PgPool,
+    delivery_id: i64,
+    result: &WebhookAttemptResult,
+    max_attempts: i32,
+    retry_in: Duration,
+) -> Result<(), EzyTutorError> {
+    let error = result.error.as_ref().map(|error| {
+        let mut end = error.len().min(MAX_ERROR_LEN);
+        while !error.is_char_boundary(end) {
+            end -= 1;
+        }
+        error[..end].to_string()
+    });
+    let duration_ms = i32::try_from(result.duration.as_millis()).unwrap_or(i32::MAX);
+
+    let mut tx = pg_pool.begin().await?;
+    sqlx::query!(
+        "INSERT INTO ezy_webhook_attempt (delivery_id, status_code, error, duration_ms)
+        VALUES ($1, $2, $3, $4)",
+        delivery_id,
+        result.status_code,
+        error,
+        duration_ms,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE ezy_webhook_delivery SET
+        attempts = attempts + 1,
+        last_status_code = $2,
+        last_error = $3,
+        status = CASE
+            WHEN $4 THEN 'succeeded'
+            WHEN attempts + 1 >= $5 THEN 'failed'
+            ELSE 'pending'
+        END,
+        delivered_at = CASE WHEN $4 THEN now() ELSE delivered_at END,
+        next_attempt_at = now() + make_interval(secs => $6)
+        WHERE delivery_id = $1",
+        delivery_id,
+        result.status_code,
+        error,
+        result.succeeded(),
+        max_attempts,
+        retry_in.as_secs_f64(),
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
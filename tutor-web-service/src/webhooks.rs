@@ -0,0 +1,297 @@
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use sqlx::postgres::PgPool;
+
+use std::error::Error as _;
+use std::time::Instant;
+
+use crate::config::WebhooksConfig;
+use crate::errors::EzyTutorError;
+use crate::store::{self, DueWebhookDelivery, WebhookAttemptResult};
+
+pub const EVENT_HEADER: &str = "x-ezy-event";
+pub const DELIVERY_HEADER: &str = "x-ezy-delivery";
+/// `t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>" keyed with the secret>`.
+pub const SIGNATURE_HEADER: &str = "x-ezy-signature";
+
+/// The JSON body POSTed to subscribers.
+#[derive(Debug, Serialize)]
+struct WebhookEnvelope<'a> {
+    id: i64,
+    #[serde(rename = "type")]
+    event_type: &'a str,
+    created_at: DateTime<Utc>,
+    data: &'a serde_json::Value,
+}
+
+/// Signs `body` as sent at `timestamp`, in the format of the signature header.
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+/// Sends queued webhook events to their subscribers, retrying failures with backoff.
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    config: WebhooksConfig,
+}
+
+impl WebhookDispatcher {
+    pub fn new(config: &WebhooksConfig) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout())
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(concat!("ezytutors-webhooks/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+        })
+    }
+
+    /// Delivers due events until the pool is closed, sleeping between polls only when
+    /// the last batch left nothing behind.
+    pub async fn run(self, pg_pool: PgPool) {
+        tracing::info!("Dispatching webhooks");
+        loop {
+            match self.dispatch_due(&pg_pool).await {
+                Ok(sent) if sent == self.config.batch_size as usize => continue,
+                Ok(_) => {}
+                Err(EzyTutorError::DbError(sqlx::Error::PoolClosed)) => return,
+                Err(err) => tracing::warn!(error = %err, "Could not dispatch webhooks"),
+            }
+            actix_web::rt::time::sleep(self.config.poll_interval()).await;
+        }
+    }
+
+    /// Claims one batch of due deliveries and sends them concurrently. Returns how many
+    /// were claimed.
+    pub async fn dispatch_due(&self, pg_pool: &PgPool) -> Result<usize, EzyTutorError> {
+        // Claims outlive the request timeout, so no other dispatcher sends them meanwhile.
+        let lease = self.config.timeout() * 2;
+        let deliveries =
+            store::claim_webhook_deliveries(pg_pool, i64::from(self.config.batch_size), lease)
+                .await?;
+        let claimed = deliveries.len();
+
+        join_all(deliveries.into_iter().map(|delivery| async move {
+            let result = self.deliver(&delivery).await;
+            let attempts = delivery.attempts as u32 + 1;
+            if result.succeeded() {
+                tracing::debug!(delivery_id = delivery.delivery_id, "Webhook delivered");
+            } else {
+                tracing::warn!(
+                    delivery_id = delivery.delivery_id,
+                    url = %delivery.url,
+                    attempts,
+                    status_code = result.status_code,
+                    error = result.error.as_deref(),
+                    "Webhook delivery failed"
+                );
+            }
+            if let Err(err) = store::record_webhook_attempt(
+                pg_pool,
+                delivery.delivery_id,
+                &result,
+                self.config.max_attempts as i32,
+                self.config.retry_backoff(attempts),
+            )
+            .await
+            {
+                tracing::warn!(
+                    delivery_id = delivery.delivery_id,
+                    error = %err,
+                    "Could not record webhook attempt"
+                );
+            }
+        }))
+        .await;
+
+        Ok(claimed)
+    }
+
+    async fn deliver(&self, delivery: &DueWebhookDelivery) -> WebhookAttemptResult {
+        let start = Instant::now();
+        let envelope = WebhookEnvelope {
+            id: delivery.event_id,
+            event_type: &delivery.event_type,
+            created_at: delivery.created_at,
+            data: &delivery.payload,
+        };
+        let body = match serde_json::to_vec(&envelope) {
+            Ok(body) => body,
+            Err(err) => {
+                return WebhookAttemptResult {
+                    status_code: None,
+                    error: Some(err.to_string()),
+                    duration: start.elapsed(),
+                }
+            }
+        };
+        let signature = signature(&delivery.secret, Utc::now().timestamp(), &body);
+
+        let res = self
+            .client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &delivery.event_type)
+            .header(DELIVERY_HEADER, delivery.delivery_id.to_string())
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await;
+
+        match res {
+            Ok(res) => {
+                let status = res.status();
+                WebhookAttemptResult {
+                    status_code: Some(i32::from(status.as_u16())),
+                    error: (!status.is_success()).then(|| format!("HTTP {}", status)),
+                    duration: start.elapsed(),
+                }
+            }
+            Err(err) => WebhookAttemptResult {
+                status_code: None,
+                error: Some(error_chain(&err)),
+                duration: start.elapsed(),
+            },
+        }
+    }
+}
+
+/// reqwest keeps the interesting part, e.g. "connection refused", in the source chain.
+fn error_chain(err: &reqwest::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NewWebhookSubscription, UpdateCourse};
+    use actix_web::web::Bytes;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use std::env;
+    use std::sync::Mutex;
+
+    const SECRET: &str = "test-secret-0123456789";
+
+    type Received = web::Data<Mutex<Vec<serde_json::Value>>>;
+
+    /// Accepts events whose signature checks out and rejects everything else.
+    async fn receive(req: HttpRequest, body: Bytes, received: Received) -> HttpResponse {
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+        };
+        let sent = header(SIGNATURE_HEADER);
+        let timestamp = sent
+            .strip_prefix("t=")
+            .and_then(|rest| rest.split(',').next())
+            .and_then(|t| t.parse().ok())
+            .unwrap_or_default();
+        if sent != signature(SECRET, timestamp, &body) {
+            return HttpResponse::Unauthorized().finish();
+        }
+        let event: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(event["type"], header(EVENT_HEADER));
+        received.lock().unwrap().push(event);
+        HttpResponse::NoContent().finish()
+    }
+
+    #[actix_rt::test]
+    async fn delivers_signed_events_to_subscribers() {
+        let received: Received = web::Data::new(Mutex::new(Vec::new()));
+        let app_received = received.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_received.clone())
+                .route("/hook", web::post().to(receive))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        dotenvy::dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let pg_pool = PgPool::connect(&database_url).await.unwrap();
+        let subscription = store::post_new_webhook_subscription(
+            &pg_pool,
+            NewWebhookSubscription {
+                url: format!("http://{}/hook", addr),
+                secret: Some(SECRET.to_string()),
+                event_types: vec!["course.updated".to_string()],
+            },
+        )
+        .await
+        .unwrap();
+
+        let update = UpdateCourse {
+            course_name: None,
+            course_description: Some("Now with webhooks".to_string()),
+            course_format: None,
+            course_structure: None,
+            course_duration: None,
+            course_price: None,
+            course_language: None,
+            course_level: None,
+            first_session_at: None,
+            category_ids: None,
+            tags: None,
+        };
+        store::update_course_datails(&pg_pool, 1, 2, update)
+            .await
+            .unwrap();
+
+        let dispatcher = WebhookDispatcher::new(&WebhooksConfig::default()).unwrap();
+        while dispatcher.dispatch_due(&pg_pool).await.unwrap() > 0 {}
+
+        // Other tests may update courses meanwhile, so look for this one's event.
+        let events = received.lock().unwrap().clone();
+        let event = events
+            .iter()
+            .find(|event| event["data"]["course_id"] == 2)
+            .expect("course.updated event for course 2");
+        assert_eq!("course.updated", event["type"]);
+        assert_eq!("Now with webhooks", event["data"]["course_description"]);
+
+        let deliveries = store::get_webhook_deliveries(
+            &pg_pool,
+            subscription.subscription_id,
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        assert!(deliveries
+            .iter()
+            .all(|delivery| delivery.status == "succeeded" && delivery.attempts == 1));
+
+        store::delete_webhook_subscription(&pg_pool, subscription.subscription_id)
+            .await
+            .unwrap();
+        handle.stop(true).await;
+    }
+}